@@ -0,0 +1,155 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// shared error / warning reporting for every stage of the tool chain
+// each stage pushes into its own Diagnostics, the driver collects them
+// and prints them all at the end of the run
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+// line and column are 1 based, the end is exclusive
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+impl Span {
+    pub fn new(line: usize, col: usize, end_line: usize, end_col: usize) -> Self {
+        Self {
+            line,
+            col,
+            end_line,
+            end_col,
+        }
+    }
+    // a span covering a whole source line
+    pub fn line(line: usize) -> Self {
+        Self {
+            line,
+            col: 1,
+            end_line: line,
+            end_col: 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: String,
+    pub span: Span,
+    pub message: String,
+    pub note: Option<String>,
+}
+
+impl Diagnostic {
+    // rustc style report
+    //
+    // error: cannot find variable `z`
+    //   --> Main.jack:22:17
+    //    |
+    // 22 |         let x = z;
+    //    |                 ^
+    //    = note: ...
+    pub fn render(&self, source: Option<&str>) -> String {
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+        let line_no = self.span.line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            gutter, self.file, self.span.line, self.span.col
+        ));
+        let text = source.and_then(|s| s.lines().nth(self.span.line.wrapping_sub(1)));
+        if let Some(text) = text {
+            let text = text.trim_end();
+            let start = self.span.col.max(1) - 1;
+            let len = text.chars().count();
+            let end = if self.span.end_line == self.span.line && self.span.end_col > self.span.col {
+                self.span.end_col - 1
+            } else {
+                len
+            };
+            let carets = end.min(len).saturating_sub(start).max(1);
+            // keep tabs so the caret lines up with the source
+            let pad: String = text
+                .chars()
+                .take(start)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} | {}\n", line_no, text));
+            out.push_str(&format!("{} | {}{}\n", gutter, pad, "^".repeat(carets)));
+        }
+        if let Some(note) = &self.note {
+            out.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        out
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Diagnostics {
+    pub items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+    pub fn push(&mut self, severity: Severity, file: &str, span: Span, message: &str) {
+        self.items.push(Diagnostic {
+            severity,
+            file: file.to_string(),
+            span,
+            message: message.to_string(),
+            note: None,
+        });
+    }
+    pub fn error(&mut self, file: &str, span: Span, message: &str) {
+        self.push(Severity::Error, file, span, message);
+    }
+    pub fn warning(&mut self, file: &str, span: Span, message: &str) {
+        self.push(Severity::Warning, file, span, message);
+    }
+    // attach a note to the most recent diagnostic
+    pub fn note(&mut self, note: &str) {
+        if let Some(last) = self.items.last_mut() {
+            last.note = Some(note.to_string());
+        }
+    }
+    pub fn append(&mut self, other: &mut Diagnostics) {
+        self.items.append(&mut other.items);
+    }
+    pub fn error_count(&self) -> usize {
+        self.count(Severity::Error)
+    }
+    pub fn warning_count(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+    fn count(&self, severity: Severity) -> usize {
+        self.items.iter().filter(|d| d.severity == severity).count()
+    }
+}
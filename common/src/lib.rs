@@ -1,4 +1,5 @@
 pub mod pdb {
     pub mod database;
 }
+pub mod diagnostics;
 pub mod utils;
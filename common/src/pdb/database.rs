@@ -11,7 +11,7 @@ pub struct Pdb {
     pub source_map: Vec<SourceMap>,
     pub file_info: Vec<FileInfo>,
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileInfo {
     pub name: PathBuf,
     pub file_type: FileType,
@@ -29,6 +29,8 @@ pub enum SymbolType {
     Func,
     Var,
     Label,
+    // jack const or enum member, the address holds the value
+    Const,
     Unknown,
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
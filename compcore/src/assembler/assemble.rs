@@ -1,9 +1,16 @@
-use crate::constants;
+use crate::{
+    constants,
+    utils::{push_parse_error, span_of},
+};
 use anyhow::{Context, Result};
-use common::pdb::database::{Pdb, SourceMap};
+use common::{
+    diagnostics::Diagnostics,
+    pdb::database::{Pdb, SourceMap},
+};
 use pest::{iterators::Pair, Parser};
 use std::collections::BTreeMap;
 use std::io::Write;
+use std::path::Path;
 use std::{collections::HashMap, fs};
 #[derive(pest_derive::Parser)]
 #[grammar = "assembler/asm.pest"]
@@ -27,10 +34,12 @@ pub struct Assembler<'pdb> {
     pdb: &'pdb mut Pdb,
     current_data_address: u16,
     data: Vec<(u16, u16)>,
+    source_path: String,
+    pub diagnostics: Diagnostics,
 }
 
 impl<'pdb> Assembler<'pdb> {
-    pub fn new(pdb: &'pdb mut Pdb) -> Assembler {
+    pub fn new(pdb: &'pdb mut Pdb) -> Assembler<'pdb> {
         Self {
             labels: HashMap::new(),
             instructions: Vec::new(),
@@ -43,11 +52,20 @@ impl<'pdb> Assembler<'pdb> {
             pdb,
             current_data_address: 0,
             data: Vec::new(),
+            source_path: String::new(),
+            diagnostics: Diagnostics::new(),
         }
     }
-    pub fn run(&mut self, source: &str, _name: &str, verbose: bool) -> Result<()> {
+    pub fn run(&mut self, source: &str, path: &Path, verbose: bool) -> Result<()> {
         self.verbose = verbose;
-        let parsed = AsmParser::parse(Rule::program, source)?;
+        self.source_path = path.display().to_string();
+        let parsed = match AsmParser::parse(Rule::program, source) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                push_parse_error(&mut self.diagnostics, &self.source_path, &err);
+                return Ok(());
+            }
+        };
         for pair in parsed {
             //  if pair.as_rule() != Rule::comment {
             self.generated_inst_addr = None;
@@ -229,10 +247,15 @@ impl<'pdb> Assembler<'pdb> {
 
     fn parse_dot_org(&mut self, pair: Pair<Rule>) -> Result<()> {
         let this_pair = pair.into_inner().next().unwrap();
-        println!("directive {:?}", this_pair);
+        if self.verbose {
+            println!("directive {:?}", this_pair);
+        }
         let addr_str = this_pair.as_str();
-        let addr = Self::parse_maybe_hex(addr_str).unwrap();
-        self.current_data_address = addr;
+        if let Some(addr) = Self::parse_maybe_hex(addr_str) {
+            self.current_data_address = addr;
+        } else {
+            self.error(&this_pair, &format!("invalid address `{}`", addr_str));
+        }
         Ok(())
     }
 
@@ -251,10 +274,17 @@ impl<'pdb> Assembler<'pdb> {
         let pair = pair.into_inner().next().unwrap();
 
         let num_str = pair.as_str();
-        let num = Self::parse_maybe_hex(num_str).unwrap();
-        self.gen_data(num);
+        if let Some(num) = Self::parse_maybe_hex(num_str) {
+            self.gen_data(num);
+        } else {
+            self.error(&pair, &format!("invalid word `{}`", num_str));
+        }
         Ok(())
     }
+    fn error(&mut self, pair: &Pair<Rule>, msg: &str) {
+        self.diagnostics
+            .error(&self.source_path, span_of(&pair.as_span()), msg);
+    }
     fn complete(&mut self) -> Result<()> {
         let mut var_count = constants::STATIC as i64;
         if self.verbose {
@@ -291,8 +321,10 @@ impl<'pdb> Assembler<'pdb> {
                 var_count += 1;
             }
         }
-        for sm in self.source_map.iter() {
-            println!("{:?}", sm);
+        if self.verbose {
+            for sm in self.source_map.iter() {
+                println!("{:?}", sm);
+            }
         }
         Ok(())
     }
@@ -335,10 +367,12 @@ impl<'pdb> Assembler<'pdb> {
         let this_pair = pair.into_inner().next().unwrap();
         let label = this_pair.as_str();
         if Self::lookup_symbol(label).is_some() {
-            return Err(anyhow::anyhow!("label reserved "));
+            self.error(&this_pair, &format!("label {} is reserved", label));
+            return Ok(());
         }
         if self.labels.contains_key(label) {
-            return Err(anyhow::anyhow!("label {} already defined", label));
+            self.error(&this_pair, &format!("label {} already defined", label));
+            return Ok(());
         }
         self.labels
             .insert(label.to_string(), self.instructions.len() as i64);
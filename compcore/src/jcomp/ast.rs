@@ -0,0 +1,271 @@
+use std::fmt;
+
+use common::diagnostics::Span;
+use serde::Serialize;
+
+use super::{
+    compiler::SubroutineKind,
+    symbols::{VarKind, VarType},
+};
+
+// typed syntax tree for one jack class
+// built from the JackParser output by jcomp::parse, consumed by the
+// type checker and code generator (and anything else that wants to look
+// at a program without walking pest pairs)
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Class {
+    // the /** */ comment before the class, without the stars, the other
+    // declarations keep theirs the same way
+    pub doc: Option<String>,
+    pub name: Ident,
+    // class Circle extends Shape
+    pub parent: Option<Ident>,
+    pub constants: Vec<Constant>,
+    pub enums: Vec<Enum>,
+    pub vars: Vec<ClassVar>,
+    pub subroutines: Vec<Subroutine>,
+}
+
+// const int MAX = 100;
+#[derive(Serialize, Debug, Clone)]
+pub struct Constant {
+    pub doc: Option<String>,
+    pub var_type: VarType,
+    pub name: Ident,
+    pub value: Expression,
+    pub span: Span,
+}
+
+// enum Dir { UP, DOWN = 4 }
+// the members are int constants of the class named Dir.UP, Dir.DOWN
+#[derive(Serialize, Debug, Clone)]
+pub struct Enum {
+    pub doc: Option<String>,
+    pub name: Ident,
+    pub members: Vec<EnumMember>,
+    pub span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EnumMember {
+    pub name: Ident,
+    // None for one more than the previous member
+    pub value: Option<Expression>,
+}
+
+// static int a, b;
+#[derive(Serialize, Debug, Clone)]
+pub struct ClassVar {
+    pub doc: Option<String>,
+    pub kind: VarKind,
+    pub var_type: VarType,
+    pub names: Vec<Ident>,
+    pub span: Span,
+}
+
+// var int a, b;
+#[derive(Serialize, Debug, Clone)]
+pub struct VarDec {
+    pub var_type: VarType,
+    pub names: Vec<Ident>,
+    pub span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Parameter {
+    pub var_type: VarType,
+    pub name: Ident,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Subroutine {
+    pub doc: Option<String>,
+    pub kind: SubroutineKind,
+    // None for void
+    pub return_type: Option<VarType>,
+    pub name: Ident,
+    pub params: Vec<Parameter>,
+    pub locals: Vec<VarDec>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+    // where the body starts, the function entry point in the debugger
+    pub body_span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub enum StatementKind {
+    Let {
+        target: Ident,
+        index: Option<Expression>,
+        value: Expression,
+    },
+    If {
+        condition: Expression,
+        then_branch: Vec<Statement>,
+        else_branch: Option<Vec<Statement>>,
+    },
+    While {
+        condition: Expression,
+        body: Vec<Statement>,
+    },
+    // init and step are lets, no condition loops forever
+    For {
+        init: Option<Box<Statement>>,
+        condition: Option<Expression>,
+        step: Option<Box<Statement>>,
+        body: Vec<Statement>,
+    },
+    // no fall through, each case ends at the next one
+    Switch {
+        scrutinee: Expression,
+        cases: Vec<Case>,
+        default: Option<Case>,
+    },
+    Break,
+    Continue,
+    Do(SubroutineCall),
+    Return(Option<Expression>),
+}
+
+// case 1, 2: statements, the default has no values
+#[derive(Serialize, Debug, Clone)]
+pub struct Case {
+    pub values: Vec<Expression>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+// name(args), Target.name(args) or obj.name(args)
+#[derive(Serialize, Debug, Clone)]
+pub struct SubroutineCall {
+    pub target: Option<Ident>,
+    pub name: Ident,
+    pub args: Vec<Expression>,
+    pub span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Expression {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub enum ExprKind {
+    Term(Term),
+    Unary(UnaryOp, Box<Expression>),
+    Binary(BinaryOp, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub enum Term {
+    // value is already wrapped to 16 bits, 0xFFFF is -1
+    Int(i32, Radix),
+    // string and character literals are kept as written between the
+    // quotes, escapes are decoded by jcomp::charset
+    Char(String),
+    Str(String),
+    Keyword(Keyword),
+    Var(String),
+    Index(Ident, Box<Expression>),
+    Call(Box<SubroutineCall>),
+    Paren(Box<Expression>),
+    // Class.MAX, Dir.UP or Class.Dir.UP, always a constant
+    Path(Vec<Ident>),
+}
+
+// how an integer literal was written
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Radix {
+    Decimal,
+    Hex,
+    Binary,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Keyword {
+    True,
+    False,
+    Null,
+    This,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Gt,
+    Eq,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    // binding strength in precedence mode, higher binds tighter
+    pub fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div => 5,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq => 3,
+            BinaryOp::And => 2,
+            BinaryOp::Or => 1,
+        }
+    }
+}
+
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Keyword::True => write!(f, "true"),
+            Keyword::False => write!(f, "false"),
+            Keyword::Null => write!(f, "null"),
+            Keyword::This => write!(f, "this"),
+        }
+    }
+}
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::Not => write!(f, "~"),
+        }
+    }
+}
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Eq => "=",
+            BinaryOp::And => "&",
+            BinaryOp::Or => "|",
+        };
+        write!(f, "{}", op)
+    }
+}
@@ -0,0 +1,97 @@
+// the Hack character set
+// 32..126 are the printable ascii characters, 128 and up are the keyboard keys
+// everything else cannot be drawn by Output or typed on the Keyboard
+
+pub const NEWLINE: i32 = 128;
+pub const BACKSPACE: i32 = 129;
+const LAST_KEY: i32 = 152; // F12
+
+pub fn in_charset(code: i32) -> bool {
+    (32..=126).contains(&code) || (NEWLINE..=LAST_KEY).contains(&code)
+}
+
+// decode a string or character literal as written between its quotes
+// into hack character codes
+pub fn decode(raw: &str) -> Result<Vec<i32>, String> {
+    let mut codes = Vec::new();
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            if !(' '..='~').contains(&c) {
+                return Err(format!(
+                    "character {:?} is not in the Hack character set",
+                    c
+                ));
+            }
+            codes.push(c as i32);
+            continue;
+        }
+        let code = match chars.next() {
+            Some('n') => NEWLINE,
+            Some('b') => BACKSPACE,
+            // Output has no tab stops, a tab is printed as a single space
+            Some('t') => ' ' as i32,
+            Some('\\') => '\\' as i32,
+            Some('"') => '"' as i32,
+            Some('\'') => '\'' as i32,
+            Some('x') => {
+                let digits: String = chars.by_ref().take(2).collect();
+                let code = match i32::from_str_radix(&digits, 16) {
+                    Ok(code) if digits.len() == 2 => code,
+                    _ => {
+                        return Err(format!(
+                            "bad escape `\\x{}`, expected two hex digits",
+                            digits
+                        ))
+                    }
+                };
+                if !in_charset(code) {
+                    return Err(format!("`\\x{}` is not in the Hack character set", digits));
+                }
+                code
+            }
+            Some(other) => return Err(format!("unknown escape `\\{}`", other)),
+            None => return Err("unfinished escape at end of literal".to_string()),
+        };
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_plain_and_escaped() {
+        assert_eq!(decode("Hi ~"), Ok(vec![72, 105, 32, 126]));
+        assert_eq!(decode(""), Ok(vec![]));
+        assert_eq!(decode(r"a\nb"), Ok(vec![97, NEWLINE, 98]));
+        assert_eq!(decode(r"\b"), Ok(vec![BACKSPACE]));
+        assert_eq!(decode(r"a\tb"), Ok(vec![97, 32, 98]));
+        assert_eq!(decode(r#"\\\"\'"#), Ok(vec![92, 34, 39]));
+        assert_eq!(decode(r"\x41\x80\x98"), Ok(vec![65, 128, LAST_KEY]));
+    }
+
+    #[test]
+    fn decode_rejects_what_hack_cannot_show() {
+        assert!(decode("tab\there").is_err());
+        assert!(decode("é").is_err());
+        assert!(decode(r"\x7f").is_err());
+        assert!(decode(r"\x99").is_err());
+        assert!(decode(r"\x1").unwrap_err().contains("two hex digits"));
+        assert!(decode(r"\xzz").unwrap_err().contains("two hex digits"));
+        assert_eq!(decode(r"\q"), Err("unknown escape `\\q`".to_string()));
+        assert_eq!(
+            decode("abc\\"),
+            Err("unfinished escape at end of literal".to_string())
+        );
+    }
+
+    #[test]
+    fn charset_bounds() {
+        assert!(in_charset(32) && in_charset(126));
+        assert!(in_charset(NEWLINE) && in_charset(LAST_KEY));
+        assert!(!in_charset(31) && !in_charset(127) && !in_charset(LAST_KEY + 1));
+    }
+}
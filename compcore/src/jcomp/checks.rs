@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use super::{compiler::Compiler, symbols::Symbol};
+
+// checked builds, run time guards that stop the program with Sys.error
+// - array indexes are checked against the allocation, the os's
+//   Memory.alloc leaves the address just past a block in the word before
+//   it, so the length of a is a[-1] - a. only bases in the heap are
+//   checked, arrays pointed at the screen or at 0 to reach all of memory
+//   are left alone
+// - calls through a null object, checked where the call is and again when
+//   a method is entered, so fields reached through this are covered when
+//   the call came from unchecked library code
+// - dividing by zero, Math.divide has its own error but the code says
+//   where it happened
+// - a subroutine entered with the stack about to run into the heap, or
+//   into the pooled string literals that sit just below it. builds with
+//   pooled strings always have this one, checked or not, see set_stack_guard
+// a failed check calls the class's own checked$fail(code, line), which
+// prints the jack file name and hands on to Checked.fail(code, line) to
+// print ":n " with the line of the statement and stop in Sys.error, so
+// the screen shows Main.jack:12 before the error code. the build writes
+// Checked.vm next to the project's classes
+// temps 2 and 3 are only live inside a guard
+
+// the Sys.error codes, above the os's own 1 to 20
+pub const INDEX_OUT_OF_BOUNDS: i32 = 100;
+pub const NULL_OBJECT: i32 = 101;
+pub const DIVIDE_BY_ZERO: i32 = 102;
+pub const STACK_OVERFLOW: i32 = 103;
+
+// the class the build adds for the guards to call
+pub const FAIL_CLASS: &str = "Checked";
+// the function each checked class gets, a jack name cannot have a $
+const FAIL_FUNCTION: &str = "checked$fail";
+
+const HEAP_BASE: i32 = 2048;
+const HEAP_END: i32 = 16383;
+// room left above the stack for expressions and for Sys.error to print
+const STACK_HEADROOM: i32 = 128;
+
+impl Compiler<'_> {
+    // index and base are on the stack, leaves the element's address
+    pub(crate) fn element_address(&mut self) {
+        if !self.checked {
+            self.write("add");
+            return;
+        }
+        let ok = format!("CHECK_OK{}", self.next_label("CHECK"));
+        self.write("pop temp 3");
+        self.write("pop temp 2");
+        self.write("push temp 3");
+        self.push_constant(HEAP_BASE);
+        self.write("lt");
+        self.write("push temp 3");
+        self.push_constant(HEAP_END);
+        self.write("gt");
+        self.write("or");
+        self.write(&format!("if-goto {}", ok));
+        // index < 0 or index >= a[-1] - a
+        self.write("push temp 2");
+        self.write("push constant 0");
+        self.write("lt");
+        self.write("push temp 2");
+        self.write("push temp 3");
+        self.write("push constant 1");
+        self.write("sub");
+        self.write("pop pointer 1");
+        self.write("push that 0");
+        self.write("push temp 3");
+        self.write("sub");
+        self.write("lt");
+        self.write("not");
+        self.write("or");
+        self.write("not");
+        self.write(&format!("if-goto {}", ok));
+        self.error_call(INDEX_OUT_OF_BOUNDS, &ok);
+        self.write("push temp 2");
+        self.write("push temp 3");
+        self.write("add");
+    }
+
+    // the object a method is called on, pushed as its first argument
+    pub(crate) fn push_object(&mut self, symbol: &Symbol) {
+        self.push_symbol(symbol);
+        if !self.checked {
+            return;
+        }
+        let ok = format!("CHECK_OK{}", self.next_label("CHECK"));
+        self.push_symbol(symbol);
+        self.write(&format!("if-goto {}", ok));
+        self.error_call(NULL_OBJECT, &ok);
+    }
+
+    // right after a method has set this from argument 0
+    pub(crate) fn this_check(&mut self) {
+        if !self.checked {
+            return;
+        }
+        let ok = format!("CHECK_OK{}", self.next_label("CHECK"));
+        self.write("push pointer 0");
+        self.write(&format!("if-goto {}", ok));
+        self.error_call(NULL_OBJECT, &ok);
+    }
+
+    // dividend and divisor are on the stack
+    pub(crate) fn divisor_check(&mut self) {
+        if !self.checked {
+            return;
+        }
+        let ok = format!("CHECK_OK{}", self.next_label("CHECK"));
+        self.write("pop temp 2");
+        self.write("push temp 2");
+        self.write(&format!("if-goto {}", ok));
+        self.error_call(DIVIDE_BY_ZERO, &ok);
+        self.write("push temp 2");
+    }
+
+    // right after the function line, sp is ram[0]
+    pub(crate) fn stack_check(&mut self) {
+        if !self.checked && !self.stack_guard {
+            return;
+        }
+        let ok = format!("CHECK_OK{}", self.next_label("CHECK"));
+        // every literal is in the pool before any class is compiled
+        let top = self
+            .strings
+            .as_ref()
+            .map_or(HEAP_BASE, |strings| strings.floor() as i32);
+        self.write("push constant 0");
+        self.write("pop pointer 1");
+        self.write("push that 0");
+        self.push_constant(top - STACK_HEADROOM);
+        self.write("lt");
+        self.write(&format!("if-goto {}", ok));
+        self.error_call(STACK_OVERFLOW, &ok);
+    }
+
+    fn error_call(&mut self, code: i32, ok: &str) {
+        self.push_constant(code);
+        self.push_constant(self.line as i32);
+        self.write(&self.fail_call());
+        self.write("pop temp 0");
+        self.write(&format!("label {}", ok));
+    }
+
+    fn fail_call(&self) -> String {
+        format!("call {}.{} 2", self.class_name, FAIL_FUNCTION)
+    }
+
+    // at the end of a class with any guards, prints its file name
+    pub(crate) fn fail_function(&mut self) {
+        let call = self.fail_call();
+        if !self.code.contains(&call) {
+            return;
+        }
+        let file = Path::new(&self.file_name)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(format!("{}.jack", self.class_name), str::to_string);
+        self.write(&format!("function {}.{} 0", self.class_name, FAIL_FUNCTION));
+        for c in file.chars() {
+            self.push_constant(c as i32);
+            self.write("call Output.printChar 1");
+            self.write("pop temp 0");
+        }
+        self.write("push argument 0");
+        self.write("push argument 1");
+        self.write(&format!("call {}.fail 2", FAIL_CLASS));
+        self.write("return");
+        self.add_function_symbol(FAIL_FUNCTION, 3);
+    }
+}
+
+// the vm for Checked.fail(code, line), the file name is already printed
+pub fn fail_vm() -> String {
+    let mut code = vec![format!("function {}.fail 0", FAIL_CLASS)];
+    code.extend(
+        [
+            "push constant 58", // :
+            "call Output.printChar 1",
+            "pop temp 0",
+            "push argument 1",
+            "call Output.printInt 1",
+            "pop temp 0",
+            "push constant 32",
+            "call Output.printChar 1",
+            "pop temp 0",
+            "push argument 0",
+            "call Sys.error 1",
+            "pop temp 0",
+            "push constant 0",
+            "return",
+        ]
+        .map(String::from),
+    );
+    code.join("\n") + "\n"
+}
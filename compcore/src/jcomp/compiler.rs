@@ -1,15 +1,30 @@
 use std::{
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
 };
 
-use super::symbols::{SymbolTable, VarKind, VarType};
+use super::{
+    ast::{
+        Class, ClassVar, Expression, Ident, Statement, StatementKind, Subroutine, SubroutineCall,
+    },
+    fold::fold_class,
+    index::ClassIndex,
+    inherit::{init_method, SUPER},
+    lint::{Lint, Linter},
+    parse::AstBuilder,
+    reduce::OptimizeFor,
+    strings::StringPool,
+    symbols::{Symbol as VarSymbol, SymbolTable, VarKind, VarType},
+    typecheck::TypeChecker,
+};
 use anyhow::Result;
 use common::{
+    diagnostics::{Diagnostics, Span},
     pdb::database::{FileInfo, FileType, Pdb, Symbol},
     utils::adjust_canonicalization,
 };
-use pest::{iterators::Pair, Parser};
+use serde::Serialize;
 
 #[derive(pest_derive::Parser)]
 #[grammar = "jcomp/jack.pest"]
@@ -22,14 +37,72 @@ pub struct Compiler<'pdb> {
     pub(crate) code: Vec<String>,
     pub(crate) subroutine_kind: SubroutineKind,
     pub(crate) verbose: bool,
-    file_name: String,
+    pub diagnostics: Diagnostics,
+    pub(crate) file_name: String,
     file_number: usize,
-    error: bool,
-    pdb: &'pdb mut Pdb,
+    pub(crate) pdb: &'pdb mut Pdb,
+    pub(crate) index: Option<&'pdb ClassIndex>,
+    precedence: bool,
+    fold: bool,
+    allow: Vec<Lint>,
+    pub(crate) optimize_for: OptimizeFor,
+    pub(crate) strings: Option<&'pdb mut StringPool>,
     current_function_name: String,
+    pub(crate) loops: Vec<LoopLabels>,
+    // labels are numbered per subroutine, one counter for each kind
+    label_counts: HashMap<&'static str, usize>,
+    pub(crate) reference: bool,
+    pub(crate) checked: bool,
+    pub(crate) stack_guard: bool,
+    // the jack line of the statement being compiled, for checked builds
+    pub(crate) line: usize,
+}
+// where break and continue go in the innermost loop
+// a switch has a break but passes continue on to the loop around it
+pub(crate) struct LoopLabels {
+    pub(crate) break_label: String,
+    pub(crate) continue_label: Option<String>,
+}
+// point the pdb comments in compiled code at another file number, for
+// code kept from a build where the file had a different place in the pdb
+pub fn renumber_pdb_comments(code: &str, file_number: usize) -> String {
+    code.split('\n')
+        .map(|line| match line.strip_prefix("// ++pdb ") {
+            Some(rest) => match rest.split_once(':') {
+                Some((_, position)) => format!("// ++pdb {}:{}", file_number, position),
+                None => line.to_string(),
+            },
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+// a subroutine's arguments and locals, methods get this as argument 0
+pub(crate) fn subroutine_symbols(class_name: &str, subroutine: &Subroutine) -> Result<SymbolTable> {
+    let mut symbols = SymbolTable::new();
+    if subroutine.kind == SubroutineKind::Method {
+        symbols.insert(
+            "this".to_string(),
+            VarType::Instance(class_name.to_string()),
+            VarKind::Argument,
+        )?;
+    }
+    for param in &subroutine.params {
+        symbols.insert(
+            param.name.name.clone(),
+            param.var_type.clone(),
+            VarKind::Argument,
+        )?;
+    }
+    for var_dec in &subroutine.locals {
+        for name in &var_dec.names {
+            symbols.insert(name.name.clone(), var_dec.var_type.clone(), VarKind::Local)?;
+        }
+    }
+    Ok(symbols)
 }
-#[derive(PartialEq)]
-pub(crate) enum SubroutineKind {
+#[derive(Serialize, PartialEq, Clone, Copy, Debug)]
+pub enum SubroutineKind {
     Constructor,
     Function,
     Method,
@@ -45,77 +118,154 @@ impl<'pdb> Compiler<'pdb> {
             code: Vec::new(),
             subroutine_kind: SubroutineKind::None,
             verbose,
-            error: false,
+            diagnostics: Diagnostics::new(),
             file_number: 0,
             file_name: String::new(),
             pdb,
+            index: None,
+            precedence: false,
+            fold: true,
+            allow: Vec::new(),
+            optimize_for: OptimizeFor::Size,
+            strings: None,
             current_function_name: String::new(),
+            loops: Vec::new(),
+            label_counts: HashMap::new(),
+            reference: false,
+            checked: false,
+            stack_guard: false,
+            line: 0,
         }
     }
+    // check calls to other classes against the project index
+    pub fn set_index(&mut self, index: &'pdb ClassIndex) {
+        self.index = Some(index);
+    }
+    // conventional operator precedence instead of strict left to right
+    pub fn set_precedence(&mut self, precedence: bool) {
+        self.precedence = precedence;
+    }
+    // evaluate constant expressions at compile time, on by default
+    pub fn set_fold(&mut self, fold: bool) {
+        self.fold = fold;
+    }
+    // lints that are turned off, everything else warns
+    pub fn set_allow(&mut self, allow: &[Lint]) {
+        self.allow = allow.to_vec();
+    }
+    // output exactly what the course's JackCompiler would, no folding,
+    // no strength reduction, no pooled strings and no pdb comments
+    pub fn set_reference(&mut self, reference: bool) {
+        self.reference = reference;
+    }
+    // run time guards that call Sys.error, see jcomp::checks
+    pub fn set_checked(&mut self, checked: bool) {
+        self.checked = checked;
+    }
+    // only the stack check of a checked build, it keeps the stack out of
+    // the string pool
+    pub fn set_stack_guard(&mut self, stack_guard: bool) {
+        self.stack_guard = stack_guard;
+    }
+    // how hard to inline multiplies and divides by constants
+    pub fn set_optimize_for(&mut self, optimize_for: OptimizeFor) {
+        self.optimize_for = optimize_for;
+    }
+    // string literals come from the pool rather than being built at run time
+    pub fn set_string_pool(&mut self, strings: &'pdb mut StringPool) {
+        self.strings = Some(strings);
+    }
     pub fn output_code(&mut self, output_name: &str) -> Result<()> {
-        self.code.push("\n".to_string());
-        let code = self.code.join("\n");
+        let code = self.finish();
         fs::write(output_name, code).expect("Unable to write file");
-        self.generate_debug_symbols(true);
 
         Ok(())
     }
+    // the finished vm code, the class's own symbols go into the pdb
+    pub fn finish(&mut self) -> String {
+        self.generate_debug_symbols(true);
+        // the course's files end with a single newline
+        let end = if self.reference { "\n" } else { "\n\n" };
+        self.code.join("\n") + end
+    }
+
+    // source to ast, syntax errors go into diagnostics
+    pub fn parse(&mut self, source: &str, path: &Path) -> Result<Option<Class>> {
+        // an editor's new document may not be on disk yet
+        let canon = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.file_name = adjust_canonicalization(canon);
+        let mut builder = AstBuilder::new(&self.file_name, self.precedence && !self.reference);
+        let class = builder.parse_class(source);
+        self.diagnostics.append(&mut builder.diagnostics);
+        Ok(class)
+    }
 
     pub fn run(&mut self, source: &str, path: &Path) -> Result<bool> {
-        let pairs = JackParser::parse(Rule::class_file, source)?;
+        let Some(mut class) = self.parse(source, path)? else {
+            return Ok(false);
+        };
 
-        let canon = path.canonicalize().unwrap();
-        self.file_name = adjust_canonicalization(canon);
         self.file_number = self.pdb.file_info.len();
         self.pdb.file_info.push(FileInfo {
             name: PathBuf::from(&self.file_name),
             file_type: FileType::Jack,
         });
 
-        for pair in pairs {
-            match pair.as_rule() {
-                Rule::class_name => {
-                    self.class_name = pair.as_str().to_string();
-                }
-                Rule::class_var => {
-                    self.do_class_var(pair)?;
-                }
-                Rule::subroutine => {
-                    self.do_subroutine(pair)?;
-                }
-                Rule::EOI => {
-                    if self.verbose {
-                        println!("Global symbols");
-                        self.global_symbols.dump();
-                    }
-                }
-                _ => {}
-            }
+        // semantic checks first, no point generating code for a broken class
+        TypeChecker::new(&self.file_name, &mut self.diagnostics, self.index).check(&class);
+        if self.diagnostics.has_errors() {
+            return Ok(false);
         }
-
-        Ok(!self.error)
+        self.class_symbols(&class)?;
+        let extended = self
+            .index
+            .is_some_and(|index| index.is_extended(&class.name.name));
+        Linter::new(
+            &self.file_name,
+            &mut self.diagnostics,
+            &self.allow,
+            extended,
+            &self.global_symbols,
+        )
+        .check(&class)?;
+
+        self.do_constants(&mut class);
+        if self.diagnostics.has_errors() {
+            return Ok(false);
+        }
+        if self.fold && !self.reference {
+            fold_class(&mut class);
+        }
+        self.do_class(&class)?;
+        Ok(!self.diagnostics.has_errors())
     }
-    fn do_class_var(&mut self, pair: Pair<Rule>) -> Result<()> {
-        let mut pair_iter = pair.into_inner();
-        let kind_str = pair_iter.next().unwrap().as_str();
-        let type_str = pair_iter.next().unwrap().as_str();
-
-        let kind = match kind_str {
-            "field" => VarKind::Field,
-            "static" => VarKind::Static,
-            _ => unreachable!(),
-        };
-        let vtype = match type_str {
-            "int" => VarType::Int,
-            "char" => VarType::Char,
-            "boolean" => VarType::Bool,
-            _ => VarType::Instance(type_str.to_string()),
-        };
-        for name_str in pair_iter {
+    // the class level symbols, the linter works from them too
+    fn class_symbols(&mut self, class: &Class) -> Result<()> {
+        self.class_name = class.name.name.clone();
+        self.inherited_fields()?;
+        for var in &class.vars {
+            self.do_class_var(var)?;
+        }
+        Ok(())
+    }
+    fn do_class(&mut self, class: &Class) -> Result<()> {
+        for subroutine in &class.subroutines {
+            self.do_subroutine(subroutine)?;
+        }
+        self.fail_function();
+        self.do_vtable();
+        if self.verbose {
+            println!("Global symbols");
+            self.global_symbols.dump();
+        }
+        Ok(())
+    }
+    fn do_class_var(&mut self, var: &ClassVar) -> Result<()> {
+        for name in &var.names {
             self.global_symbols.insert(
-                name_str.as_str().to_string(),
-                vtype.clone(),
-                kind.clone(),
+                name.name.clone(),
+                var.var_type.clone(),
+                var.kind.clone(),
             )?;
         }
 
@@ -124,53 +274,58 @@ impl<'pdb> Compiler<'pdb> {
     pub(crate) fn write(&mut self, line: &str) {
         self.code.push(line.to_string());
     }
-    fn do_subroutine(&mut self, pair: Pair<Rule>) -> Result<()> {
+    fn write_pdb_comment(&mut self, span: Span) {
+        self.line = span.line;
+        if self.reference {
+            return;
+        }
+        self.write(&format!(
+            "// ++pdb {}:{}:{}",
+            self.file_number, span.line, span.col
+        ));
+    }
+    // the next number for a kind of label in this subroutine
+    pub(crate) fn next_label(&mut self, kind: &'static str) -> usize {
+        let count = self.label_counts.entry(kind).or_default();
+        *count += 1;
+        *count - 1
+    }
+    pub(crate) fn symbol_not_found(&mut self, name: &str, span: Span) {
+        self.diagnostics.error(
+            &self.file_name,
+            span,
+            &format!("cannot find variable `{}`", name),
+        );
+    }
+    pub(crate) fn lookup(&self, name: &str) -> Option<VarSymbol> {
+        self.subroutine_symbols
+            .get(name)
+            .or_else(|| self.global_symbols.get(name))
+            .cloned()
+    }
+    fn do_subroutine(&mut self, subroutine: &Subroutine) -> Result<()> {
         // subroutine is constructor, method  or function (aka static)
 
-        let mut pair_iter = pair.into_inner();
-        self.subroutine_symbols = SymbolTable::new();
-        let kind_str = pair_iter.next().unwrap().as_str();
-        let _return_str = pair_iter.next().unwrap().as_str();
-        let name_str = pair_iter.next().unwrap().as_str();
-        let param_pair = pair_iter.next().unwrap();
+        self.label_counts.clear();
+        let name_str = subroutine.name.name.as_str();
         self.current_function_name = name_str.to_string();
-
-        // what kind is it?
-
-        match kind_str {
-            "constructor" => self.subroutine_kind = SubroutineKind::Constructor,
-            "method" => self.subroutine_kind = SubroutineKind::Method,
-            "function" => self.subroutine_kind = SubroutineKind::Function,
-            _ => unreachable!(),
-        }
+        self.subroutine_kind = subroutine.kind;
         // self.pdb_data.add_func(name_str.to_string());
-
-        // methods get 'this' as arg0
-
-        if self.subroutine_kind == SubroutineKind::Method {
-            self.subroutine_symbols.insert(
-                "this".to_string(),
-                VarType::Instance(self.class_name.clone()),
-                VarKind::Argument,
-            )?;
-        }
-
-        // args into the local symbol table
-
-        for pair in param_pair.into_inner() {
-            self.do_sub_var(pair, VarKind::Argument)?;
+        if self.is_chained(subroutine) {
+            self.write_pdb_comment(subroutine.body_span);
+            self.constructor_stub(subroutine);
+            self.add_function_symbol(name_str, 1);
+            return self.do_subroutine(&init_method(subroutine));
         }
+        self.subroutine_symbols = subroutine_symbols(&self.class_name, subroutine)?;
 
         // body is
         // - one or more locals
         // - one or more statements
         // in that order, no mixing
 
-        let var_pair = pair_iter.next().unwrap();
-        self.do_variables(var_pair, name_str)?;
-        let st_pair = pair_iter.next().unwrap();
-        self.do_statments(st_pair);
-        assert!(pair_iter.next().is_none());
+        self.do_variables(subroutine)?;
+        self.do_statments(&subroutine.body);
         if self.verbose {
             println!("Subroutine symbols");
             self.subroutine_symbols.dump();
@@ -182,10 +337,14 @@ impl<'pdb> Compiler<'pdb> {
             SubroutineKind::Function => 3,
             SubroutineKind::None => 0,
         };
+        self.add_function_symbol(name_str, ftype);
+        Ok(())
+    }
+    pub(crate) fn add_function_symbol(&mut self, name: &str, func_type: i64) {
         self.pdb.symbols.push(common::pdb::database::Symbol {
-            name: format!("{}.{}", self.class_name, name_str.to_string()),
+            name: format!("{}.{}", self.class_name, name),
             symbol_type: common::pdb::database::SymbolType::Func,
-            func_type: ftype,
+            func_type,
             address: 0,
             file_type: FileType::Jack,
             var_type: 0,
@@ -193,7 +352,6 @@ impl<'pdb> Compiler<'pdb> {
             size: 0,
             instance_type: "".to_string(),
         });
-        Ok(())
     }
     fn generate_debug_symbols(&mut self, global: bool) {
         let table = if global {
@@ -233,216 +391,108 @@ impl<'pdb> Compiler<'pdb> {
             });
         }
     }
-    fn do_statments(&mut self, pair: Pair<Rule>) {
-        let pair_iter = pair.into_inner();
-        for pair in pair_iter {
-            self.write(&format!(
-                "// ++pdb {}:{}:{}",
-                self.file_number,
-                pair.line_col().0,
-                pair.line_col().1
-            ));
-            match pair.as_rule() {
-                Rule::do_st => {
-                    self.do_subcall(pair.into_inner().next().unwrap());
+    pub(crate) fn do_statments(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.write_pdb_comment(statement.span);
+            match &statement.kind {
+                StatementKind::Do(call) => {
+                    self.do_subcall(call);
                     self.write("pop temp 0");
                 }
 
-                Rule::let_st => self.do_let(pair),
-                Rule::while_st => self.do_while(pair),
-                Rule::return_st => self.do_return(pair),
-                Rule::if_st => self.do_if(pair),
-                _ => {
-                    unreachable!("{:?}", pair.as_rule())
+                StatementKind::Let {
+                    target,
+                    index,
+                    value,
+                } => self.do_let(target, index.as_ref(), value),
+                StatementKind::While { condition, body } => self.do_while(condition, body),
+                StatementKind::For {
+                    init,
+                    condition,
+                    step,
+                    body,
+                } => self.do_for(init.as_deref(), condition.as_ref(), step.as_deref(), body),
+                StatementKind::Switch {
+                    scrutinee,
+                    cases,
+                    default,
+                } => self.do_switch(scrutinee, cases, default.as_ref()),
+                StatementKind::Break => {
+                    self.do_jump(statement.span, |labels| Some(&labels.break_label))
+                }
+                StatementKind::Continue => {
+                    self.do_jump(statement.span, |labels| labels.continue_label.as_ref())
                 }
+                StatementKind::Return(value) => self.do_return(value.as_ref()),
+                StatementKind::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => self.do_if(condition, then_branch, else_branch.as_deref()),
             }
         }
     }
-    fn do_variables(&mut self, pair: Pair<Rule>, name: &str) -> Result<()> {
-        let p = pair.clone();
-        let pair_iter = pair.into_inner();
-
-        for pair in pair_iter {
-            self.do_sub_var(pair, VarKind::Local)?;
-        }
-        self.write(&format!(
-            "// ++pdb {}:{}:{}",
-            self.file_number,
-            p.line_col().0,
-            p.line_col().1
-        ));
+    fn do_variables(&mut self, subroutine: &Subroutine) -> Result<()> {
+        self.write_pdb_comment(subroutine.body_span);
         let local_count = self.subroutine_symbols.get_count(VarKind::Local);
         self.write(&format!(
             "function {}.{} {}",
-            self.class_name, name, local_count
+            self.class_name, subroutine.name.name, local_count
         ));
+        self.stack_check();
 
         match self.subroutine_kind {
-            SubroutineKind::Constructor => {
-                let field_count = self.global_symbols.get_count(VarKind::Field);
-
-                self.write(&format!("push constant {}", field_count));
-                self.write("call Memory.alloc 1");
-                self.write("pop pointer 0");
-            }
+            SubroutineKind::Constructor => self.allocate(),
             SubroutineKind::Method => {
-                // self.write(&format!(
-                //     "// ++pdb {}:{}:{}",
-                //     self.file_name,
-                //     p.line_col().0,
-                //     p.line_col().1
-                // ));
                 self.write("push argument 0");
                 self.write("pop pointer 0");
+                self.this_check();
             }
-            SubroutineKind::Function => {
-                // self.write(&format!(
-                //     "// ++pdb {}:{}:{}",
-                //     self.file_name,
-                //     p.line_col().0,
-                //     p.line_col().1
-                // ));
-            }
+            SubroutineKind::Function => {}
             _ => unreachable!(),
         };
         Ok(())
     }
-    fn do_sub_var(&mut self, pair: Pair<Rule>, kind: VarKind) -> Result<()> {
-        let mut pair_iter = pair.into_inner();
 
-        let type_str = pair_iter.next().unwrap().as_str();
+    // a constructor's new object becomes this
+    pub(crate) fn allocate(&mut self) {
+        let field_count = self.global_symbols.get_count(VarKind::Field);
 
-        loop {
-            let maybe_name_str = pair_iter.next();
-            if maybe_name_str.is_none() {
-                break;
-            }
-            let name_str = maybe_name_str.unwrap().as_str();
-            let vtype = match type_str {
-                "int" => VarType::Int,
-                "char" => VarType::Char,
-                "boolean" => VarType::Bool,
-                _ => VarType::Instance(type_str.to_string()),
-            };
-            self.subroutine_symbols
-                .insert(name_str.to_string(), vtype, kind.clone())?;
-        }
-        Ok(())
+        self.write(&format!("push constant {}", field_count));
+        self.write("call Memory.alloc 1");
+        self.write("pop pointer 0");
+        self.set_vtable();
     }
 
-    fn do_lhs_array(&mut self, pair: Pair<Rule>) {
-        let mut pair_iter = pair.into_inner();
-        let name = pair_iter.next().unwrap().as_str();
-        self.do_expr(pair_iter.next().unwrap());
-        let symbol = self.subroutine_symbols.get(name);
-
-        match symbol {
-            Some(symbol) => match symbol.var_kind {
-                VarKind::Local => {
-                    self.write(&format!("push local {}", symbol.number));
-                }
-                VarKind::Field => {
-                    self.write(&format!("push field {}", symbol.number));
-                }
-                VarKind::Static => {
-                    self.write(&format!("push static {}", symbol.number));
-                }
-                VarKind::Argument => {
-                    self.write(&format!("push argument {}", symbol.number));
-                }
-            },
-            None => {
-                if let Some(symbol) = self.global_symbols.get(name) {
-                    match symbol.var_kind {
-                        VarKind::Field => {
-                            self.write(&format!("push this {}", symbol.number));
-                        }
-                        VarKind::Static => {
-                            self.write(&format!("push static {}", symbol.number));
-                        }
-                        _ => unreachable!(),
-                    }
-                } else {
-                    println!("Symbol {} not found", name);
-                    self.error = true;
-                }
-            }
-        }
-
-        self.write("add");
-    }
-    fn do_let(&mut self, pair: Pair<Rule>) {
+    fn do_let(&mut self, target: &Ident, index: Option<&Expression>, value: &Expression) {
         // let name<[expr]> = expr;
 
-        // LHS first
-        let mut pair_iter = pair.into_inner();
-
-        let lhs = pair_iter.next().unwrap();
-
-        let name_str = lhs.as_str();
-
-        let mut array = false;
-
-        match lhs.as_rule() {
-            Rule::array_var => {
-                self.do_lhs_array(lhs);
-                array = true;
-            }
-            Rule::identifier => {}
-            _ => unreachable!(),
+        // LHS first, for an array element that is the address of the element
+        if let Some(index) = index {
+            self.do_expr(index);
+            self.lookup_push_symbol(target);
+            self.element_address();
         }
 
         // now the expression
 
-        let expr = pair_iter.next().unwrap();
-
-        self.do_expr(expr);
+        self.do_expr(value);
 
         // finally pop the value to the correct variable
-        if array {
+        if index.is_some() {
             self.write("pop temp 0");
             self.write("pop pointer 1");
             self.write("push temp 0");
             self.write("pop that 0");
+        } else if let Some(symbol) = self.lookup(&target.name) {
+            self.pop_symbol(&symbol);
         } else {
-            let symbol = self.subroutine_symbols.get(name_str);
-            match symbol {
-                Some(symbol) => match symbol.var_kind {
-                    VarKind::Local => {
-                        self.write(&format!("pop local {}", symbol.number));
-                    }
-                    VarKind::Field => {
-                        self.write(&format!("pop field {}", symbol.number));
-                    }
-                    VarKind::Static => {
-                        self.write(&format!("pop static {}", symbol.number));
-                    }
-                    VarKind::Argument => {
-                        self.write(&format!("pop argument {}", symbol.number));
-                    }
-                },
-                None => {
-                    if let Some(symbol) = self.global_symbols.get(name_str) {
-                        match symbol.var_kind {
-                            VarKind::Field => {
-                                self.write(&format!("pop this {}", symbol.number));
-                            }
-                            VarKind::Static => {
-                                self.write(&format!("pop static {}", symbol.number));
-                            }
-                            _ => unreachable!(),
-                        }
-                    } else {
-                        println!("Symbol {} not found", name_str);
-                    }
-                }
-            }
+            self.symbol_not_found(&target.name, target.span);
         }
     }
 
-    fn do_return(&mut self, pair: Pair<Rule>) {
-        let mut pair_iter = pair.into_inner();
-        if let Some(expr) = pair_iter.next() {
+    fn do_return(&mut self, value: Option<&Expression>) {
+        if let Some(expr) = value {
             self.do_expr(expr);
         } else {
             self.write("push constant 0");
@@ -450,26 +500,25 @@ impl<'pdb> Compiler<'pdb> {
         self.write("return");
     }
 
-    pub(crate) fn do_subcall(&mut self, pair: Pair<Rule>) {
-        let mut pair_iter = pair.into_inner();
-        // first is name
-        let name_pair = pair_iter.next().unwrap();
-
+    pub(crate) fn do_subcall(&mut self, call: &SubroutineCall) {
         let name;
         let mut arg_count = 0;
-        match name_pair.as_rule() {
-            Rule::identifier => {
+        let mut slot = None;
+        match &call.target {
+            None => {
                 // func => this.func => ThisClass.func
-                name = format!("{}.{}", self.class_name, name_pair.as_str());
+                name = format!("{}.{}", self.class_name, call.name.name);
+                slot = self.slot(&self.class_name, &call.name.name);
                 self.write("push pointer 0");
                 arg_count += 1;
             }
-            Rule::dotted_id => {
-                let id = name_pair.as_str().to_string();
-                let mut bits = id.split('.');
-                let left = bits.next().unwrap().to_string();
-                let func = bits.next().unwrap();
-
+            Some(target) if target.name == SUPER && self.lookup(SUPER).is_none() => {
+                name = self
+                    .super_call(&call.name.name)
+                    .expect("super checked by the type checker");
+                arg_count += 1;
+            }
+            Some(target) => {
                 // left hand side is either
                 // - a local variable
                 // - a global variable or field
@@ -477,72 +526,125 @@ impl<'pdb> Compiler<'pdb> {
                 // in the first 2 replace foo.bar with ClassName.bar
                 // Its the last one if its not a known symbol
 
-                let sym = {
-                    if let Some(symbol) = self.subroutine_symbols.get(&left) {
-                        Some(symbol.clone())
-                    } else {
-                        self.global_symbols.get(&left).cloned()
-                    }
-                };
+                let sym = self.lookup(&target.name);
                 let cl = if let Some(ref symbol) = sym {
                     match &symbol.var_type {
                         VarType::Instance(cl) => {
-                            self.push_symbol(symbol);
+                            self.push_object(symbol);
+                            slot = self.slot(cl, &call.name.name);
                             arg_count += 1;
                             cl
                         }
-                        _ => &left,
+                        _ => &target.name,
                     }
                 } else {
-                    &left
+                    &target.name
                 };
-                name = format!("{}.{}", cl, func);
+                name = format!("{}.{}", cl, call.name.name);
             }
-            _ => unreachable!(),
         }
         // now arguments
 
-        for arg in pair_iter.next().unwrap().into_inner() {
+        for arg in &call.args {
             arg_count += 1;
             self.do_expr(arg);
         }
-        self.write(&format!("call {} {}", name, arg_count));
+        match slot {
+            Some(slot) => self.write(&format!("call-virtual {} {} {}", name, arg_count, slot)),
+            None => self.write(&format!("call {} {}", name, arg_count)),
+        }
     }
 
-    fn do_while(&mut self, pair: Pair<Rule>) {
-        let mut pair_iter = pair.into_inner();
-        let label = format!("WHILE{}", self.code.len());
+    fn do_while(&mut self, condition: &Expression, body: &[Statement]) {
+        let id = self.next_label("WHILE");
+        let label = format!("WHILE_EXP{}", id);
+        let end_label = format!("WHILE_END{}", id);
         self.write(&format!("label {}", label));
-        let expr = pair_iter.next().unwrap();
-        self.do_expr(expr);
+        self.do_expr(condition);
         self.write("not");
-        let end_label = format!("ENDWHILE{}", self.code.len());
         self.write(&format!("if-goto {}", end_label));
-        let st = pair_iter.next().unwrap();
-        self.do_statments(st);
+        self.loops.push(LoopLabels {
+            break_label: end_label.clone(),
+            continue_label: Some(label.clone()),
+        });
+        self.do_statments(body);
+        self.loops.pop();
         self.write(&format!("goto {}", label));
         self.write(&format!("label {}", end_label));
     }
 
-    fn do_if(&mut self, pair: Pair<Rule>) {
-        let mut pair_iter = pair.into_inner();
-        let expr = pair_iter.next().unwrap();
-        self.do_expr(expr);
-        let true_label = format!("IFTRUE{}", self.code.len());
-        let false_label = format!("IFFALSE{}", self.code.len());
-        let end_label = format!("IFEND{}", self.code.len());
+    fn do_for(
+        &mut self,
+        init: Option<&Statement>,
+        condition: Option<&Expression>,
+        step: Option<&Statement>,
+        body: &[Statement],
+    ) {
+        // a while loop with continue going to the step
+        if let Some(init) = init {
+            self.do_statments(std::slice::from_ref(init));
+        }
+        let id = self.next_label("WHILE");
+        let label = format!("WHILE_EXP{}", id);
+        let step_label = format!("WHILE_STEP{}", id);
+        let end_label = format!("WHILE_END{}", id);
+        self.write(&format!("label {}", label));
+        if let Some(condition) = condition {
+            self.do_expr(condition);
+            self.write("not");
+            self.write(&format!("if-goto {}", end_label));
+        }
+        self.loops.push(LoopLabels {
+            break_label: end_label.clone(),
+            continue_label: Some(step_label.clone()),
+        });
+        self.do_statments(body);
+        self.loops.pop();
+        self.write(&format!("label {}", step_label));
+        if let Some(step) = step {
+            self.do_statments(std::slice::from_ref(step));
+        }
+        self.write(&format!("goto {}", label));
+        self.write(&format!("label {}", end_label));
+    }
+
+    // break or continue, the type checker has made sure there is a loop
+    fn do_jump(&mut self, span: Span, target: fn(&LoopLabels) -> Option<&String>) {
+        match self.loops.last().and_then(target).cloned() {
+            Some(label) => self.write(&format!("goto {}", label)),
+            None => self
+                .diagnostics
+                .error(&self.file_name, span, "not inside a loop"),
+        }
+    }
+
+    fn do_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &[Statement],
+        else_branch: Option<&[Statement]>,
+    ) {
+        // the same shape as the course compiler, numbered before the
+        // branches so nested ifs count up in source order
+        let id = self.next_label("IF");
+        let true_label = format!("IF_TRUE{}", id);
+        let false_label = format!("IF_FALSE{}", id);
+        let end_label = format!("IF_END{}", id);
+        self.do_expr(condition);
         self.write(&format!("if-goto {}", true_label));
         self.write(&format!("goto {}", false_label));
         self.write(&format!("label {}", true_label));
 
-        let st = pair_iter.next().unwrap();
-        self.do_statments(st);
+        self.do_statments(then_branch);
 
-        self.write(&format!("goto {}", end_label));
-        self.write(&format!("label {}", false_label));
-        if let Some(else_st) = pair_iter.next() {
-            self.do_statments(else_st);
+        match else_branch {
+            Some(else_st) => {
+                self.write(&format!("goto {}", end_label));
+                self.write(&format!("label {}", false_label));
+                self.do_statments(else_st);
+                self.write(&format!("label {}", end_label));
+            }
+            None => self.write(&format!("label {}", false_label)),
         }
-        self.write(&format!("label {}", end_label));
     }
 }
@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+
+use common::{
+    diagnostics::Span,
+    pdb::database::{FileType, Symbol, SymbolType},
+};
+
+use super::{
+    ast::{BinaryOp, Class, ExprKind, Expression, Ident, Radix, Statement, StatementKind, Term},
+    compiler::Compiler,
+    fold::{const_value, fold_in_place},
+    parse::binary,
+    symbols::VarType,
+};
+
+// class level constants, const int MAX = 100; and enum Dir { UP, DOWN }
+// they take no RAM, every use is replaced by its value before folding so
+// the rest of the compiler only ever sees a literal
+// other classes refer to them as Class.MAX and Class.Dir.UP, the values
+// come from the project index
+
+// one constant, enum members are flattened to Dir.UP etc
+#[derive(Debug, Clone)]
+pub(crate) struct ConstDecl {
+    pub(crate) name: String,
+    pub(crate) var_type: VarType,
+    pub(crate) value: Expression,
+    pub(crate) span: Span,
+}
+
+pub(crate) fn declarations(class: &Class) -> Vec<ConstDecl> {
+    let mut decls: Vec<ConstDecl> = class
+        .constants
+        .iter()
+        .map(|constant| ConstDecl {
+            name: constant.name.name.clone(),
+            var_type: constant.var_type.clone(),
+            value: constant.value.clone(),
+            span: constant.name.span,
+        })
+        .collect();
+    for enumeration in &class.enums {
+        let mut previous: Option<&Ident> = None;
+        for member in &enumeration.members {
+            let span = member.name.span;
+            let value = match (&member.value, previous) {
+                (Some(value), _) => value.clone(),
+                (None, None) => int(0, span),
+                // one more than the previous member
+                (None, Some(previous)) => binary(
+                    BinaryOp::Add,
+                    Expression {
+                        kind: ExprKind::Term(Term::Path(vec![
+                            enumeration.name.clone(),
+                            previous.clone(),
+                        ])),
+                        span,
+                    },
+                    int(1, span),
+                ),
+            };
+            decls.push(ConstDecl {
+                name: format!("{}.{}", enumeration.name.name, member.name.name),
+                var_type: VarType::Int,
+                value,
+                span,
+            });
+            previous = Some(&member.name);
+        }
+    }
+    decls
+}
+
+fn int(val: i16, span: Span) -> Expression {
+    Expression {
+        kind: ExprKind::Term(Term::Int(val as i32, Radix::Decimal)),
+        span,
+    }
+}
+
+pub(crate) fn path_name(path: &[Ident]) -> String {
+    path.iter()
+        .map(|part| part.name.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+// the class and constant a path refers to
+// Dir.UP is a member of enum Dir if this class has one, otherwise it is
+// constant UP of class Dir
+pub(crate) fn path_target(
+    class: &str,
+    path: &[Ident],
+    is_local: &dyn Fn(&str) -> bool,
+) -> (String, String) {
+    let full = path_name(path);
+    if is_local(&full) {
+        (class.to_string(), full)
+    } else {
+        (path[0].name.clone(), path_name(&path[1..]))
+    }
+}
+
+// value of a constant initialiser, lookup is given a class and constant name
+pub(crate) fn evaluate(
+    expr: &Expression,
+    class: &str,
+    is_local: &dyn Fn(&str) -> bool,
+    lookup: &mut dyn FnMut(&str, &str) -> Option<i16>,
+) -> Option<i16> {
+    let mut expr = expr.clone();
+    substitute(&mut expr, &mut |term| match term {
+        Term::Var(name) => lookup(class, name),
+        Term::Path(path) => {
+            let (target_class, name) = path_target(class, path, is_local);
+            lookup(&target_class, &name)
+        }
+        _ => None,
+    });
+    fold_in_place(&mut expr);
+    const_value(&expr)
+}
+
+// replace the terms f has a value for with that value
+fn substitute(expr: &mut Expression, f: &mut dyn FnMut(&Term) -> Option<i16>) {
+    match &mut expr.kind {
+        ExprKind::Term(term) => match f(term) {
+            Some(val) => *term = Term::Int(val as i32, Radix::Decimal),
+            None => match term {
+                Term::Index(_, index) => substitute(index, f),
+                Term::Call(call) => call.args.iter_mut().for_each(|arg| substitute(arg, f)),
+                Term::Paren(inner) => substitute(inner, f),
+                _ => {}
+            },
+        },
+        ExprKind::Unary(_, operand) => substitute(operand, f),
+        ExprKind::Binary(_, lhs, rhs) => {
+            substitute(lhs, f);
+            substitute(rhs, f);
+        }
+    }
+}
+
+// an f that always returns None just visits every term
+pub(crate) fn substitute_statements(
+    statements: &mut [Statement],
+    f: &mut dyn FnMut(&Term) -> Option<i16>,
+) {
+    for statement in statements {
+        match &mut statement.kind {
+            StatementKind::Let { index, value, .. } => {
+                if let Some(index) = index {
+                    substitute(index, f);
+                }
+                substitute(value, f);
+            }
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                substitute(condition, f);
+                substitute_statements(then_branch, f);
+                if let Some(else_branch) = else_branch {
+                    substitute_statements(else_branch, f);
+                }
+            }
+            StatementKind::While { condition, body } => {
+                substitute(condition, f);
+                substitute_statements(body, f);
+            }
+            StatementKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                for part in [init, step].into_iter().flatten() {
+                    substitute_statements(std::slice::from_mut(part), f);
+                }
+                if let Some(condition) = condition {
+                    substitute(condition, f);
+                }
+                substitute_statements(body, f);
+            }
+            StatementKind::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                substitute(scrutinee, f);
+                for case in cases.iter_mut().chain(default) {
+                    case.values
+                        .iter_mut()
+                        .for_each(|value| substitute(value, f));
+                    substitute_statements(&mut case.body, f);
+                }
+            }
+            StatementKind::Do(call) => call.args.iter_mut().for_each(|arg| substitute(arg, f)),
+            StatementKind::Return(Some(expr)) => substitute(expr, f),
+            StatementKind::Return(None) | StatementKind::Break | StatementKind::Continue => {}
+        }
+    }
+}
+
+impl Compiler<'_> {
+    // work out the value of every constant in the class, record them in
+    // the pdb and replace their uses in the subroutines
+    pub(crate) fn do_constants(&mut self, class: &mut Class) {
+        let decls = declarations(class);
+        if decls.is_empty() && self.index.is_none() {
+            return;
+        }
+        let class_name = class.name.name.clone();
+        let local: HashSet<String> = decls.iter().map(|decl| decl.name.clone()).collect();
+        let is_local = |name: &str| local.contains(name);
+        let index = self.index;
+        let lookup = |values: &HashMap<String, i16>, target: &str, name: &str| {
+            if target == class_name {
+                values.get(name).copied()
+            } else {
+                index.and_then(|index| index.constant(target, name))
+            }
+        };
+
+        // constants may use each other in any order, keep going until
+        // nothing more can be worked out, anything left is an error
+        let mut values: HashMap<String, i16> = HashMap::new();
+        loop {
+            let mut progress = false;
+            for decl in &decls {
+                if values.contains_key(&decl.name) {
+                    continue;
+                }
+                let value = evaluate(&decl.value, &class_name, &is_local, &mut |target, name| {
+                    lookup(&values, target, name)
+                });
+                if let Some(value) = value {
+                    values.insert(decl.name.clone(), value);
+                    progress = true;
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        for decl in &decls {
+            match values.get(&decl.name) {
+                Some(value) => self.pdb.symbols.push(Symbol {
+                    symbol_type: SymbolType::Const,
+                    name: format!("{}.{}", class_name, decl.name),
+                    func_type: 0,
+                    var_type: match decl.var_type {
+                        VarType::Char => 2,
+                        VarType::Bool => 3,
+                        _ => 1,
+                    },
+                    storage_class: 0,
+                    size: 0,
+                    address: *value as u16,
+                    instance_type: String::new(),
+                    file_type: FileType::Jack,
+                }),
+                None => self.diagnostics.error(
+                    &self.file_name,
+                    decl.value.span,
+                    &format!("the value of `{}` is not a constant expression", decl.name),
+                ),
+            }
+        }
+
+        for subroutine in &mut class.subroutines {
+            // locals and parameters hide constants with the same name
+            let hidden: HashSet<String> = subroutine
+                .params
+                .iter()
+                .map(|param| param.name.name.clone())
+                .chain(
+                    subroutine
+                        .locals
+                        .iter()
+                        .flat_map(|var_dec| var_dec.names.iter().map(|n| n.name.clone())),
+                )
+                .collect();
+            substitute_statements(&mut subroutine.body, &mut |term| match term {
+                Term::Var(name) if !hidden.contains(name) => values.get(name).copied(),
+                Term::Path(path) => {
+                    let (target, name) = path_target(&class_name, path, &is_local);
+                    lookup(&values, &target, &name)
+                }
+                _ => None,
+            });
+        }
+    }
+}
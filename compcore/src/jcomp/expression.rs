@@ -1,10 +1,15 @@
-use pest::iterators::Pair;
-
 use super::{
-    compiler::{Compiler, Rule},
+    ast::{BinaryOp, ExprKind, Expression, Ident, Keyword, Term, UnaryOp},
+    charset::decode,
+    compiler::Compiler,
+    consts::path_name,
     symbols::{Symbol, VarKind},
 };
 
+// the parser reports literals that do not decode, or a character literal
+// that is not one character, and no code is generated for a class with errors
+const BAD_LITERAL: &str = "literal checked by the parser";
+
 impl Compiler<'_> {
     pub(crate) fn push_symbol(&mut self, symbol: &Symbol) {
         match symbol.var_kind {
@@ -22,129 +27,151 @@ impl Compiler<'_> {
             }
         }
     }
-    pub(crate) fn lookup_push_symbol(&mut self, name: &str) {
-        let symbol = self.subroutine_symbols.get(name);
-        match symbol {
-            Some(symbol) => self.push_symbol(&symbol.clone()),
-
-            None => {
-                if let Some(symbol) = self.global_symbols.get(name) {
-                    self.push_symbol(&symbol.clone())
-                } else {
-                    println!("Symbol {} not found", name);
-                }
+    pub(crate) fn pop_symbol(&mut self, symbol: &Symbol) {
+        match symbol.var_kind {
+            VarKind::Local => {
+                self.write(&format!("pop local {}", symbol.number));
+            }
+            VarKind::Field => {
+                self.write(&format!("pop this {}", symbol.number));
+            }
+            VarKind::Static => {
+                self.write(&format!("pop static {}", symbol.number));
+            }
+            VarKind::Argument => {
+                self.write(&format!("pop argument {}", symbol.number));
             }
         }
     }
-    pub(crate) fn do_term(&mut self, term: Pair<Rule>) {
+    pub(crate) fn lookup_push_symbol(&mut self, name: &Ident) {
+        match self.lookup(&name.name) {
+            Some(symbol) => self.push_symbol(&symbol),
+            None => self.symbol_not_found(&name.name, name.span),
+        }
+    }
+    pub(crate) fn do_term(&mut self, term: &Term, expr: &Expression) {
         // a term of an expression
         // places the value of the term on the stack
         // term is an integer constant, string constant, keyword constant, var name, array entry, or subroutine call
         // or an expression in parentheses
 
-        //println!("term {:?} {:?}", term.as_str(), term.as_rule());
-        match term.as_rule() {
-            Rule::int => {
-                let val = term.as_str().trim().parse::<i32>().unwrap();
-                if val < 0 {
-                    self.write(&format!("push constant {}", -val));
-                    self.write("neg");
-                } else {
-                    self.write(&format!("push constant {}", val));
-                }
+        match term {
+            Term::Int(val, _) => self.push_constant(*val),
+            Term::Char(raw) => {
+                let code = decode(raw).expect(BAD_LITERAL);
+                self.push_constant(code[0]);
             }
-            Rule::subroutine_call => {
-                self.do_subcall(term);
+            Term::Call(call) => {
+                self.do_subcall(call);
             }
-            Rule::identifier => {
-                let name = term.as_str();
-                self.lookup_push_symbol(name);
+            Term::Var(name) => {
+                self.lookup_push_symbol(&Ident {
+                    name: name.clone(),
+                    span: expr.span,
+                });
             }
-            Rule::array_var => {
-                let mut array_var_iter = term.into_inner();
-                let name = array_var_iter.next().unwrap().as_str();
-                //println!("array_var {}", name);
-                self.lookup_push_symbol(name);
-                let index = array_var_iter.next().unwrap();
+            Term::Index(name, index) => {
+                // index first, as the course compiler does
                 self.do_expr(index);
-                self.write("add");
+                self.lookup_push_symbol(name);
+                self.element_address();
                 self.write("pop pointer 1");
                 self.write("push that 0");
             }
-            Rule::string => {
-                let s = term.as_str().trim_matches('"');
-                self.write(&format!("push constant {}", s.len()));
+            Term::Str(raw) => {
+                let codes = decode(raw).expect(BAD_LITERAL);
+                let reference = self.reference;
+                let pool = self.strings.as_mut().filter(|_| !reference);
+                if let Some(addr) = pool.and_then(|pool| pool.intern(&codes)) {
+                    self.write(&format!("push constant {}", addr));
+                    return;
+                }
+                self.write(&format!("push constant {}", codes.len()));
                 self.write("call String.new 1");
-                for c in s.chars() {
-                    self.write(&format!("push constant {}", c as u32));
+                for code in codes {
+                    self.write(&format!("push constant {}", code));
                     self.write("call String.appendChar 2");
                 }
             }
-            Rule::expression => {
-                self.do_expr(term);
+            Term::Paren(inner) => {
+                self.do_expr(inner);
             }
-            Rule::keyword => match term.as_str() {
-                "true" => {
-                    self.write("push constant 1");
-                    self.write("neg");
+            // constants the type checker let through but nothing could
+            // supply a value for, another class when compiling one file
+            Term::Path(path) => {
+                self.diagnostics.error(
+                    &self.file_name,
+                    expr.span,
+                    &format!(
+                        "cannot find constant `{}`, the whole project has to be compiled to use constants from other classes",
+                        path_name(path)
+                    ),
+                );
+            }
+            Term::Keyword(keyword) => match keyword {
+                Keyword::True => {
+                    self.write("push constant 0");
+                    self.write("not");
                 }
-                "false" => {
+                Keyword::False => {
                     self.write("push constant 0");
                 }
-                "null" => {
+                Keyword::Null => {
                     self.write("push constant 0");
                 }
-                "this" => {
+                Keyword::This => {
                     self.write("push pointer 0");
                 }
-                _ => unreachable!(),
             },
-            _ => {
-                println!("unknown term {:?},{}", term.as_rule(), term.as_str());
-            }
         }
     }
-    pub(crate) fn do_expr(&mut self, pair: Pair<Rule>) {
+    pub(crate) fn do_expr(&mut self, expr: &Expression) {
         // compiles an expression, places the resulting vaue at top of stack
-        // expression is term ~ (op ~ term)*
-
-        let mut pair_iter = pair.into_inner();
-        let first_term = pair_iter.next().unwrap();
 
-        //println!("ft {:?} {:?}", first_term.as_str(), first_term.as_rule());
-        match first_term.as_rule() {
-            Rule::unary_op => {
-                let term = pair_iter.next().unwrap();
-                self.do_term(term);
-                match first_term.as_str() {
-                    "-" => self.write("neg"),
-                    "~" => self.write("not"),
-                    _ => unreachable!(),
+        match &expr.kind {
+            ExprKind::Term(term) => self.do_term(term, expr),
+            ExprKind::Unary(op, operand) => {
+                self.do_expr(operand);
+                match op {
+                    UnaryOp::Neg => self.write("neg"),
+                    UnaryOp::Not => self.write("not"),
                 }
             }
-            _ => self.do_term(first_term),
-        }
-
-        while let Some(op) = pair_iter.next() {
-            //println!("op {:?}", op.as_str());
-            let term = pair_iter.next().unwrap();
-
-            self.do_term(term);
-            match op.as_str() {
-                "+" => self.write("add"),
-                "-" => self.write("sub"),
-                "<" => self.write("lt"),
-                ">" => self.write("gt"),
-                "&" => self.write("and"),
-                "|" => self.write("or"),
-                "*" => self.mul(),
-                "/" => self.div(),
-                "=" => self.write("eq"),
-                _ => unreachable!(),
+            ExprKind::Binary(op, lhs, rhs) => {
+                if self.strength_reduce(*op, lhs, rhs) {
+                    return;
+                }
+                self.do_expr(lhs);
+                self.do_expr(rhs);
+                match op {
+                    BinaryOp::Add => self.write("add"),
+                    BinaryOp::Sub => self.write("sub"),
+                    BinaryOp::Lt => self.write("lt"),
+                    BinaryOp::Gt => self.write("gt"),
+                    BinaryOp::And => self.write("and"),
+                    BinaryOp::Or => self.write("or"),
+                    BinaryOp::Mul => self.mul(),
+                    BinaryOp::Div => self.div(),
+                    BinaryOp::Eq => self.write("eq"),
+                }
             }
         }
     }
+    // push constant only takes 0..32767
+    pub(crate) fn push_constant(&mut self, val: i32) {
+        if val >= 0 {
+            self.write(&format!("push constant {}", val));
+        } else if val > -32768 {
+            self.write(&format!("push constant {}", -val));
+            self.write("neg");
+        } else {
+            // -32768 is ~32767
+            self.write("push constant 32767");
+            self.write("not");
+        }
+    }
     fn div(&mut self) {
+        self.divisor_check();
         self.write("call Math.divide 2");
     }
     fn mul(&mut self) {
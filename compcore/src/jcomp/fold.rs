@@ -0,0 +1,325 @@
+use common::diagnostics::Span;
+
+use super::{
+    ast::{
+        BinaryOp, Class, ExprKind, Expression, Keyword, Radix, Statement, StatementKind, Term,
+        UnaryOp,
+    },
+    charset::decode,
+};
+
+// compile time evaluation of constant expressions
+// arithmetic wraps at 16 bits like the hack alu, comparisons give -1 or 0
+// also removes identities such as x+0, x*1, ~~x
+// anything with a subroutine call is kept so side effects still happen
+
+pub fn fold_class(class: &mut Class) {
+    for subroutine in &mut class.subroutines {
+        fold_statements(&mut subroutine.body);
+    }
+}
+
+fn fold_statements(statements: &mut [Statement]) {
+    for statement in statements {
+        match &mut statement.kind {
+            StatementKind::Let { index, value, .. } => {
+                if let Some(index) = index {
+                    fold_in_place(index);
+                }
+                fold_in_place(value);
+            }
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                fold_in_place(condition);
+                fold_statements(then_branch);
+                if let Some(else_branch) = else_branch {
+                    fold_statements(else_branch);
+                }
+            }
+            StatementKind::While { condition, body } => {
+                fold_in_place(condition);
+                fold_statements(body);
+            }
+            StatementKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                for part in [init, step].into_iter().flatten() {
+                    fold_statements(std::slice::from_mut(part));
+                }
+                if let Some(condition) = condition {
+                    fold_in_place(condition);
+                }
+                fold_statements(body);
+            }
+            StatementKind::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                fold_in_place(scrutinee);
+                for case in cases.iter_mut().chain(default) {
+                    case.values.iter_mut().for_each(fold_in_place);
+                    fold_statements(&mut case.body);
+                }
+            }
+            StatementKind::Do(call) => call.args.iter_mut().for_each(fold_in_place),
+            StatementKind::Return(Some(expr)) => fold_in_place(expr),
+            StatementKind::Return(None) | StatementKind::Break | StatementKind::Continue => {}
+        }
+    }
+}
+
+pub(crate) fn fold_in_place(expr: &mut Expression) {
+    let span = expr.span;
+    let taken = std::mem::replace(expr, constant(0, span));
+    *expr = fold(taken);
+}
+
+// the value of a constant expression
+pub(crate) fn const_value(expr: &Expression) -> Option<i16> {
+    match &expr.kind {
+        ExprKind::Term(Term::Int(val, _)) => Some(*val as i16),
+        ExprKind::Term(Term::Char(raw)) => decode(raw).ok()?.first().map(|c| *c as i16),
+        ExprKind::Term(Term::Keyword(Keyword::True)) => Some(-1),
+        ExprKind::Term(Term::Keyword(Keyword::False | Keyword::Null)) => Some(0),
+        ExprKind::Term(Term::Paren(inner)) => const_value(inner),
+        _ => None,
+    }
+}
+
+// no subroutine calls anywhere inside, so it can be dropped
+pub(crate) fn is_pure(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExprKind::Term(Term::Call(_)) => false,
+        ExprKind::Term(Term::Index(_, index)) => is_pure(index),
+        ExprKind::Term(Term::Paren(inner)) => is_pure(inner),
+        ExprKind::Term(_) => true,
+        ExprKind::Unary(_, operand) => is_pure(operand),
+        ExprKind::Binary(_, lhs, rhs) => is_pure(lhs) && is_pure(rhs),
+    }
+}
+
+fn constant(val: i16, span: Span) -> Expression {
+    Expression {
+        kind: ExprKind::Term(Term::Int(val as i32, Radix::Decimal)),
+        span,
+    }
+}
+
+fn strip_paren(expr: Expression) -> Expression {
+    match expr.kind {
+        ExprKind::Term(Term::Paren(inner)) => strip_paren(*inner),
+        _ => expr,
+    }
+}
+
+fn fold(expr: Expression) -> Expression {
+    let span = expr.span;
+    match expr.kind {
+        ExprKind::Term(Term::Paren(inner)) => {
+            let inner = fold(*inner);
+            match const_value(&inner) {
+                Some(val) => constant(val, span),
+                None => Expression {
+                    kind: ExprKind::Term(Term::Paren(Box::new(inner))),
+                    span,
+                },
+            }
+        }
+        ExprKind::Term(Term::Index(name, index)) => Expression {
+            kind: ExprKind::Term(Term::Index(name, Box::new(fold(*index)))),
+            span,
+        },
+        ExprKind::Term(Term::Call(mut call)) => {
+            call.args.iter_mut().for_each(fold_in_place);
+            Expression {
+                kind: ExprKind::Term(Term::Call(call)),
+                span,
+            }
+        }
+        ExprKind::Term(_) => expr,
+        ExprKind::Unary(op, operand) => {
+            let operand = fold(*operand);
+            if let Some(val) = const_value(&operand) {
+                return constant(unary(op, val), span);
+            }
+            // --x and ~~x
+            let operand = strip_paren(operand);
+            match operand.kind {
+                ExprKind::Unary(inner_op, inner) if inner_op == op => *inner,
+                _ => Expression {
+                    kind: ExprKind::Unary(op, Box::new(operand)),
+                    span,
+                },
+            }
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let lhs = fold(*lhs);
+            let rhs = fold(*rhs);
+            match (const_value(&lhs), const_value(&rhs)) {
+                (Some(a), Some(b)) => match binary(op, a, b) {
+                    Some(val) => constant(val, span),
+                    None => rebuild(op, lhs, rhs, span),
+                },
+                (Some(a), None) => identity(op, lhs, a, rhs, true, span),
+                (None, Some(b)) => identity(op, rhs, b, lhs, false, span),
+                (None, None) => rebuild(op, lhs, rhs, span),
+            }
+        }
+    }
+}
+
+fn rebuild(op: BinaryOp, lhs: Expression, rhs: Expression, span: Span) -> Expression {
+    Expression {
+        kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        span,
+    }
+}
+
+// one side is the constant c, the other is x
+fn identity(
+    op: BinaryOp,
+    c_expr: Expression,
+    c: i16,
+    x: Expression,
+    c_on_left: bool,
+    span: Span,
+) -> Expression {
+    match (op, c, c_on_left) {
+        (BinaryOp::Add, 0, _) | (BinaryOp::Sub, 0, false) => x,
+        (BinaryOp::Mul, 1, _) | (BinaryOp::Div, 1, false) => x,
+        (BinaryOp::Or, 0, _) | (BinaryOp::And, -1, _) => x,
+        (BinaryOp::Sub, 0, true) | (BinaryOp::Mul, -1, _) => Expression {
+            kind: ExprKind::Unary(UnaryOp::Neg, Box::new(x)),
+            span,
+        },
+        (BinaryOp::Mul, 0, _) | (BinaryOp::And, 0, _) | (BinaryOp::Or, -1, _) if is_pure(&x) => {
+            constant(c, span)
+        }
+        _ if c_on_left => rebuild(op, c_expr, x, span),
+        _ => rebuild(op, x, c_expr, span),
+    }
+}
+
+fn unary(op: UnaryOp, val: i16) -> i16 {
+    match op {
+        UnaryOp::Neg => val.wrapping_neg(),
+        UnaryOp::Not => !val,
+    }
+}
+
+// None where run time behaviour has to be kept, dividing by zero calls Sys.error
+fn binary(op: BinaryOp, a: i16, b: i16) -> Option<i16> {
+    let truth = |cond: bool| if cond { -1 } else { 0 };
+    Some(match op {
+        BinaryOp::Add => a.wrapping_add(b),
+        BinaryOp::Sub => a.wrapping_sub(b),
+        BinaryOp::Mul => a.wrapping_mul(b),
+        // Math.divide works on magnitudes, -32768 has none
+        BinaryOp::Div if b == 0 || a == i16::MIN || b == i16::MIN => return None,
+        BinaryOp::Div => a / b,
+        BinaryOp::Lt => truth(a < b),
+        BinaryOp::Gt => truth(a > b),
+        BinaryOp::Eq => truth(a == b),
+        BinaryOp::And => a & b,
+        BinaryOp::Or => a | b,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jcomp::parse::AstBuilder;
+
+    // the expression f returns, folded and written back out with every
+    // binary operation in parentheses
+    fn folded(expr: &str) -> String {
+        let source = format!(
+            "class T {{ function int f(int a, int b) {{ return {}; }} }}",
+            expr
+        );
+        let mut class = AstBuilder::new("T.jack", false)
+            .parse_class(&source)
+            .expect("test source parses");
+        fold_class(&mut class);
+        let StatementKind::Return(Some(value)) = &class.subroutines[0].body[0].kind else {
+            panic!("f starts with a return");
+        };
+        show(value)
+    }
+
+    fn show(expr: &Expression) -> String {
+        match &expr.kind {
+            ExprKind::Term(Term::Int(value, _)) => value.to_string(),
+            ExprKind::Term(Term::Var(name)) => name.clone(),
+            ExprKind::Term(Term::Paren(inner)) => format!("({})", show(inner)),
+            ExprKind::Term(Term::Call(call)) => {
+                let args: Vec<String> = call.args.iter().map(show).collect();
+                format!("{}({})", call.name.name, args.join(", "))
+            }
+            ExprKind::Unary(op, operand) => format!("{}{}", op, show(operand)),
+            ExprKind::Binary(op, lhs, rhs) => format!("({} {} {})", show(lhs), op, show(rhs)),
+            _ => panic!("unexpected expression {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn folds_with_16_bit_wrapping() {
+        assert_eq!(folded("1 + 2 * 3"), "9");
+        assert_eq!(folded("32767 + 1"), "-32768");
+        assert_eq!(folded("0 - 32767 - 2"), "32767");
+        assert_eq!(folded("200 * 200"), "-25536");
+        assert_eq!(folded("-(32767 + 1)"), "-32768");
+        assert_eq!(folded("~0"), "-1");
+        assert_eq!(folded("7 / 2"), "3");
+        assert_eq!(folded("0 - 7 / 2"), "-3");
+    }
+
+    #[test]
+    fn folds_comparisons_and_logic_to_true_and_false() {
+        assert_eq!(folded("1 < 2"), "-1");
+        assert_eq!(folded("2 < 1"), "0");
+        assert_eq!(folded("3 = 3"), "-1");
+        assert_eq!(folded("true & false"), "0");
+        assert_eq!(folded("12 | 3"), "15");
+        assert_eq!(folded("'A' + 1"), "66");
+        assert_eq!(folded("(1 + 1) * a"), "(2 * a)");
+    }
+
+    #[test]
+    fn keeps_what_must_happen_at_run_time() {
+        // Math.divide reports dividing by zero, and cannot divide -32768
+        assert_eq!(folded("1 / 0"), "(1 / 0)");
+        assert_eq!(folded("(32767 + 1) / 2"), "(-32768 / 2)");
+        assert_eq!(folded("4 / (32767 + 1)"), "(4 / -32768)");
+        // the call still has to run
+        assert_eq!(folded("g() * 0"), "(g() * 0)");
+        assert_eq!(folded("0 & g()"), "(0 & g())");
+        assert_eq!(folded("a + b"), "(a + b)");
+        assert_eq!(folded("1 / a"), "(1 / a)");
+        assert_eq!(folded("0 / a"), "(0 / a)");
+    }
+
+    #[test]
+    fn removes_identities() {
+        assert_eq!(folded("a + 0"), "a");
+        assert_eq!(folded("0 + a"), "a");
+        assert_eq!(folded("a - 0"), "a");
+        assert_eq!(folded("0 - a"), "-a");
+        assert_eq!(folded("a * 1"), "a");
+        assert_eq!(folded("a / 1"), "a");
+        assert_eq!(folded("a * -1"), "-a");
+        assert_eq!(folded("a * 0"), "0");
+        assert_eq!(folded("a | -1"), "-1");
+        assert_eq!(folded("a & -1"), "a");
+        assert_eq!(folded("~~a"), "a");
+        assert_eq!(folded("-(-a)"), "a");
+        assert_eq!(folded("g(1 + 2, a * 1)"), "g(3, a)");
+    }
+}
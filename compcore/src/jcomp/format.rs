@@ -0,0 +1,593 @@
+use common::diagnostics::Diagnostics;
+
+use super::{
+    ast::{
+        Class, ClassVar, Constant, Enum, ExprKind, Expression, Radix, Statement, StatementKind,
+        Subroutine, SubroutineCall, Term,
+    },
+    compiler::SubroutineKind,
+    consts::path_name,
+    parse::AstBuilder,
+    symbols::VarKind,
+};
+
+// jackfmt, prints a class back out in the canonical style
+// - four space indents, opening braces on the same line
+// - one space either side of binary operators, none after unary ones
+// - one declaration per line, names stay grouped as written
+// - at most one blank line, always one between subroutines
+// the grammar throws comments away so they are picked out of the source
+// separately and put back by line number
+
+const INDENT: &str = "    ";
+
+// returns None if the source does not parse, the errors go into diagnostics
+pub fn format_source(
+    source: &str,
+    file_name: &str,
+    diagnostics: &mut Diagnostics,
+) -> Option<String> {
+    let mut builder = AstBuilder::new(file_name, false);
+    let class = builder.parse_class(source);
+    // only errors matter here, precedence warnings are for the compiler
+    let Some(class) = class.filter(|_| !builder.diagnostics.has_errors()) else {
+        diagnostics.append(&mut builder.diagnostics);
+        return None;
+    };
+    let mut formatter = Formatter::new(source);
+    formatter.class(&class, source);
+    Some(formatter.finish())
+}
+
+enum Member<'a> {
+    Const(&'a Constant),
+    Enum(&'a Enum),
+    Var(&'a ClassVar),
+}
+
+struct Comment {
+    line: usize,
+    end_line: usize,
+    text: String,
+    // code before it on the same line
+    trailing: bool,
+    used: bool,
+}
+
+struct Formatter {
+    comments: Vec<Comment>,
+    out: Vec<String>,
+    indent: usize,
+    // last source line that has been written out
+    last_line: usize,
+}
+
+impl Formatter {
+    fn new(source: &str) -> Self {
+        Self {
+            comments: scan_comments(source),
+            out: Vec::new(),
+            indent: 0,
+            last_line: 0,
+        }
+    }
+    fn finish(mut self) -> String {
+        // anything after the closing brace of the class
+        self.comments_before(usize::MAX);
+        let mut text = self.out.join("\n");
+        text.push('\n');
+        text
+    }
+
+    fn push(&mut self, text: &str) {
+        self.out
+            .push(format!("{}{}", INDENT.repeat(self.indent), text));
+    }
+    fn after_open(&self) -> bool {
+        self.out.last().is_none_or(|l| l.ends_with('{'))
+    }
+    fn blank_before(&mut self, line: usize) {
+        if line > self.last_line + 1
+            && !self.after_open()
+            && self.out.last().is_some_and(|l| !l.is_empty())
+        {
+            self.out.push(String::new());
+        }
+    }
+    // standalone comments that come before the given source line
+    fn comments_before(&mut self, line: usize) {
+        for i in 0..self.comments.len() {
+            if self.comments[i].used || self.comments[i].line >= line {
+                continue;
+            }
+            self.comments[i].used = true;
+            self.blank_before(self.comments[i].line);
+            let text = self.comments[i].text.clone();
+            for (n, text_line) in text.lines().enumerate() {
+                let text_line = text_line.trim();
+                if n > 0 && text_line.starts_with('*') {
+                    // line up the stars of a block comment
+                    self.push(&format!(" {}", text_line));
+                } else {
+                    self.push(text_line);
+                }
+            }
+            self.last_line = self.comments[i].end_line;
+        }
+    }
+    // a comment at the end of the given source line
+    fn trailing(&mut self, line: usize) -> String {
+        match self
+            .comments
+            .iter_mut()
+            .find(|c| !c.used && c.trailing && c.line == line && c.end_line == line)
+        {
+            Some(comment) => {
+                comment.used = true;
+                format!(" {}", comment.text)
+            }
+            None => String::new(),
+        }
+    }
+    // one line of output for a node that starts at line and ends at end_line
+    fn line(&mut self, text: &str, line: usize, end_line: usize) {
+        self.comments_before(line);
+        self.blank_before(line);
+        let trailing = self.trailing(end_line);
+        self.push(&format!("{}{}", text, trailing));
+        self.last_line = end_line;
+    }
+    fn close(&mut self, line: usize) {
+        self.comments_before(line);
+        self.indent -= 1;
+        let trailing = self.trailing(line);
+        self.push(&format!("}}{}", trailing));
+        self.last_line = line;
+    }
+
+    fn class(&mut self, class: &Class, source: &str) {
+        let line = class.name.span.line;
+        let header = match &class.parent {
+            Some(parent) => format!("class {} extends {} {{", class.name.name, parent.name),
+            None => format!("class {} {{", class.name.name),
+        };
+        self.line(&header, line, line);
+        self.indent += 1;
+        // constants, enums and variables can be mixed, keep them in source order
+        let mut members: Vec<(usize, Member)> = class
+            .constants
+            .iter()
+            .map(|c| (c.span.line, Member::Const(c)))
+            .chain(class.enums.iter().map(|e| (e.span.line, Member::Enum(e))))
+            .chain(class.vars.iter().map(|v| (v.span.line, Member::Var(v))))
+            .collect();
+        members.sort_by_key(|(line, _)| *line);
+        for (_, member) in members {
+            match member {
+                Member::Const(constant) => self.line(
+                    &format!(
+                        "const {} {} = {};",
+                        constant.var_type,
+                        constant.name.name,
+                        expr(&constant.value)
+                    ),
+                    constant.span.line,
+                    constant.span.end_line,
+                ),
+                Member::Enum(enumeration) => self.enumeration(enumeration),
+                Member::Var(var) => {
+                    let kind = match var.kind {
+                        VarKind::Static => "static",
+                        _ => "field",
+                    };
+                    let names: Vec<&str> = var.names.iter().map(|n| n.name.as_str()).collect();
+                    self.line(
+                        &format!("{} {} {};", kind, var.var_type, names.join(", ")),
+                        var.span.line,
+                        var.span.end_line,
+                    );
+                }
+            }
+        }
+        for subroutine in &class.subroutines {
+            if !self.after_open() {
+                self.out.push(String::new());
+            }
+            self.subroutine(subroutine);
+        }
+        // the class has no span of its own, its closing brace is the last one
+        let end = source.rfind('}').map_or(self.last_line, |pos| {
+            source[..pos].matches('\n').count() + 1
+        });
+        self.close(end);
+    }
+
+    // on one line if it was written on one, otherwise a member per line
+    fn enumeration(&mut self, enumeration: &Enum) {
+        let members: Vec<String> = enumeration
+            .members
+            .iter()
+            .map(|member| match &member.value {
+                Some(value) => format!("{} = {}", member.name.name, expr(value)),
+                None => member.name.name.clone(),
+            })
+            .collect();
+        let span = enumeration.span;
+        if span.line == span.end_line {
+            let text = format!(
+                "enum {} {{ {} }}",
+                enumeration.name.name,
+                members.join(", ")
+            );
+            self.line(&text, span.line, span.end_line);
+            return;
+        }
+        self.line(
+            &format!("enum {} {{", enumeration.name.name),
+            span.line,
+            span.line,
+        );
+        self.indent += 1;
+        for (member, text) in enumeration.members.iter().zip(&members) {
+            let line = member.name.span.line;
+            self.line(&format!("{},", text), line, line);
+        }
+        self.close(span.end_line);
+    }
+
+    fn subroutine(&mut self, subroutine: &Subroutine) {
+        let kind = match subroutine.kind {
+            SubroutineKind::Constructor => "constructor",
+            SubroutineKind::Method => "method",
+            _ => "function",
+        };
+        let return_type = subroutine
+            .return_type
+            .as_ref()
+            .map_or("void".to_string(), |t| t.to_string());
+        let params: Vec<String> = subroutine
+            .params
+            .iter()
+            .map(|p| format!("{} {}", p.var_type, p.name.name))
+            .collect();
+        let line = subroutine.span.line;
+        self.line(
+            &format!(
+                "{} {} {}({}) {{",
+                kind,
+                return_type,
+                subroutine.name.name,
+                params.join(", ")
+            ),
+            line,
+            line,
+        );
+        self.indent += 1;
+        for var_dec in &subroutine.locals {
+            let names: Vec<&str> = var_dec.names.iter().map(|n| n.name.as_str()).collect();
+            self.line(
+                &format!("var {} {};", var_dec.var_type, names.join(", ")),
+                var_dec.span.line,
+                var_dec.span.end_line,
+            );
+        }
+        self.statements(&subroutine.body);
+        self.close(subroutine.span.end_line);
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+    fn statement(&mut self, statement: &Statement) {
+        let span = statement.span;
+        match &statement.kind {
+            StatementKind::Let { .. } => self.line(
+                &format!("let {};", assignment(statement)),
+                span.line,
+                span.end_line,
+            ),
+            StatementKind::Do(call) => self.line(
+                &format!("do {};", call_text(call)),
+                span.line,
+                span.end_line,
+            ),
+            StatementKind::Return(None) => self.line("return;", span.line, span.end_line),
+            StatementKind::Return(Some(value)) => self.line(
+                &format!("return {};", expr(value)),
+                span.line,
+                span.end_line,
+            ),
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.line(
+                    &format!("if ({}) {{", expr(condition)),
+                    span.line,
+                    span.line,
+                );
+                self.indent += 1;
+                self.statements(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.indent -= 1;
+                    self.push("} else {");
+                    self.indent += 1;
+                    self.statements(else_branch);
+                }
+                self.close(span.end_line);
+            }
+            StatementKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                // for (i = 0; i < n; i = i + 1), empty parts leave no space
+                let init = init.as_deref().map_or(String::new(), assignment);
+                let condition = condition
+                    .as_ref()
+                    .map_or(String::new(), |c| format!(" {}", expr(c)));
+                let step = step
+                    .as_deref()
+                    .map_or(String::new(), |s| format!(" {}", assignment(s)));
+                let header = format!("for ({};{};{}) {{", init, condition, step);
+                self.line(&header, span.line, span.line);
+                self.indent += 1;
+                self.statements(body);
+                self.close(span.end_line);
+            }
+            StatementKind::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                self.line(
+                    &format!("switch ({}) {{", expr(scrutinee)),
+                    span.line,
+                    span.line,
+                );
+                self.indent += 1;
+                for case in cases.iter().chain(default) {
+                    let label = if case.values.is_empty() {
+                        "default:".to_string()
+                    } else {
+                        let values: Vec<String> = case.values.iter().map(expr).collect();
+                        format!("case {}:", values.join(", "))
+                    };
+                    self.line(&label, case.span.line, case.span.line);
+                    self.indent += 1;
+                    self.statements(&case.body);
+                    self.indent -= 1;
+                }
+                self.close(span.end_line);
+            }
+            StatementKind::Break => self.line("break;", span.line, span.end_line),
+            StatementKind::Continue => self.line("continue;", span.line, span.end_line),
+            StatementKind::While { condition, body } => {
+                self.line(
+                    &format!("while ({}) {{", expr(condition)),
+                    span.line,
+                    span.line,
+                );
+                self.indent += 1;
+                self.statements(body);
+                self.close(span.end_line);
+            }
+        }
+    }
+}
+
+// x = e or a[i] = e, a let without the keyword
+fn assignment(statement: &Statement) -> String {
+    match &statement.kind {
+        StatementKind::Let {
+            target,
+            index,
+            value,
+        } => {
+            let index = index
+                .as_ref()
+                .map_or(String::new(), |i| format!("[{}]", expr(i)));
+            format!("{}{} = {}", target.name, index, expr(value))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn call_text(call: &SubroutineCall) -> String {
+    let args: Vec<String> = call.args.iter().map(expr).collect();
+    match &call.target {
+        Some(target) => format!("{}.{}({})", target.name, call.name.name, args.join(", ")),
+        None => format!("{}({})", call.name.name, args.join(", ")),
+    }
+}
+
+pub(crate) fn expr(expression: &Expression) -> String {
+    match &expression.kind {
+        ExprKind::Term(term) => match term {
+            Term::Int(val, Radix::Decimal) => val.to_string(),
+            Term::Int(val, Radix::Hex) => format!("0x{:X}", *val as u16),
+            Term::Int(val, Radix::Binary) => format!("0b{:b}", *val as u16),
+            Term::Char(raw) => format!("'{}'", raw),
+            Term::Str(raw) => format!("\"{}\"", raw),
+            Term::Keyword(keyword) => keyword.to_string(),
+            Term::Var(name) => name.clone(),
+            Term::Index(name, index) => format!("{}[{}]", name.name, expr(index)),
+            Term::Call(call) => call_text(call),
+            Term::Paren(inner) => format!("({})", expr(inner)),
+            Term::Path(path) => path_name(path),
+        },
+        ExprKind::Unary(op, operand) => format!("{}{}", op, expr(operand)),
+        ExprKind::Binary(op, lhs, rhs) => format!("{} {} {}", expr(lhs), op, expr(rhs)),
+    }
+}
+
+// every // and /* */ comment with the line it starts on
+fn scan_comments(source: &str) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut line = 1;
+    let mut code_on_line = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '\n' => {
+                line += 1;
+                code_on_line = false;
+                i += 1;
+            }
+            '"' | '\'' => {
+                // skip the literal, escapes included
+                i += 1;
+                while i < chars.len() && chars[i] != c && chars[i] != '\n' {
+                    if chars[i] == '\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                i += 1;
+                code_on_line = true;
+            }
+            '/' if next == Some('/') || next == Some('*') => {
+                let start = i;
+                let start_line = line;
+                if next == Some('/') {
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                } else {
+                    i += 2;
+                    while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                        if chars[i] == '\n' {
+                            line += 1;
+                        }
+                        i += 1;
+                    }
+                    i = (i + 2).min(chars.len());
+                }
+                comments.push(Comment {
+                    line: start_line,
+                    end_line: line,
+                    text: chars[start..i]
+                        .iter()
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string(),
+                    trailing: code_on_line,
+                    used: false,
+                });
+            }
+            _ => {
+                if !c.is_whitespace() {
+                    code_on_line = true;
+                }
+                i += 1;
+            }
+        }
+    }
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "/** A counter. */
+class Main{
+  const int MAX=10;   // the limit
+field int count,step;
+    static   Array  seen;
+  constructor Main new(int s){let step=s;let count=0;
+      return this;}
+
+
+  /* bump
+   * it */
+  method void bump(){
+     if(count<MAX){let count=count+step;}else{let count=-count;}
+     while(~(count=0)){do Output.printInt(count);let seen[count]=count*2;}
+     return;
+  }
+}
+";
+
+    const FORMATTED: &str = "/** A counter. */
+class Main {
+    const int MAX = 10; // the limit
+    field int count, step;
+    static Array seen;
+
+    constructor Main new(int s) {
+        let step = s;
+        let count = 0;
+        return this;
+    }
+
+    /* bump
+     * it */
+    method void bump() {
+        if (count < MAX) {
+            let count = count + step;
+        } else {
+            let count = -count;
+        }
+        while (~(count = 0)) {
+            do Output.printInt(count);
+            let seen[count] = count * 2;
+        }
+        return;
+    }
+}
+";
+
+    fn format(source: &str) -> String {
+        let mut diagnostics = Diagnostics::new();
+        format_source(source, "Main.jack", &mut diagnostics).expect("test source parses")
+    }
+
+    // the ast without the positions, which formatting is free to move
+    fn shape(source: &str) -> String {
+        let class = AstBuilder::new("Main.jack", false)
+            .parse_class(source)
+            .expect("test source parses");
+        let mut text = format!("{:?}", class);
+        while let Some(start) = text.find("Span {") {
+            let end = start + text[start..].find('}').unwrap();
+            text.replace_range(start..=end, "");
+        }
+        text
+    }
+
+    #[test]
+    fn formats_in_the_canonical_style() {
+        assert_eq!(format(MESSY), FORMATTED);
+    }
+
+    #[test]
+    fn formatting_keeps_the_meaning() {
+        for source in [MESSY, include_str!("../../tests/square/Main.jack")] {
+            assert_eq!(shape(&format(source)), shape(source));
+        }
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for source in [MESSY, include_str!("../../tests/square/Main.jack")] {
+            let once = format(source);
+            assert_eq!(format(&once), once);
+        }
+        assert_eq!(format(FORMATTED), FORMATTED);
+    }
+
+    #[test]
+    fn broken_source_is_left_alone() {
+        let mut diagnostics = Diagnostics::new();
+        assert!(format_source("class Main {", "Main.jack", &mut diagnostics).is_none());
+        assert!(diagnostics.has_errors());
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use super::{
+    ast::Subroutine,
+    compiler::SubroutineKind,
+    consts::{declarations, evaluate, ConstDecl},
+    parse::parse_class,
+    symbols::{VarKind, VarType},
+};
+
+// project wide index of every class and the subroutines it declares
+// built before any class is compiled so that calls to other classes
+// can be checked at compile time rather than failing in the linker
+// it also holds every class's constants so Class.MAX can be inlined
+
+// constants referring to each other more deeply than this are a cycle
+const MAX_CONST_DEPTH: usize = 64;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SubroutineSig {
+    pub(crate) kind: SubroutineKind,
+    pub(crate) return_type: Option<VarType>,
+    pub(crate) params: Vec<VarType>,
+}
+
+impl SubroutineSig {
+    pub(crate) fn from_subroutine(subroutine: &Subroutine) -> Self {
+        Self {
+            kind: subroutine.kind,
+            return_type: subroutine.return_type.clone(),
+            params: subroutine
+                .params
+                .iter()
+                .map(|param| param.var_type.clone())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClassInfo {
+    // vm only classes (oslib) have no signatures, just names
+    subroutines: HashMap<String, Option<SubroutineSig>>,
+    constants: HashMap<String, ConstDecl>,
+    // what a subclass needs, only known for jack classes
+    jack: bool,
+    // from the oslib directory, the os relies on their layout
+    library: bool,
+    parent: Option<String>,
+    // own fields and methods in declaration order
+    fields: Vec<(String, VarType)>,
+    methods: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ClassIndex {
+    classes: HashMap<String, ClassInfo>,
+}
+
+impl ClassIndex {
+    pub fn new() -> Self {
+        Self {
+            classes: HashMap::new(),
+        }
+    }
+    // add the declarations from a jack source file
+    // parse errors are ignored here, the compiler reports them
+    pub fn add_jack(&mut self, source: &str) {
+        self.add_class(source, false);
+    }
+    // a jack class of the os library
+    pub fn add_library_jack(&mut self, source: &str) {
+        self.add_class(source, true);
+    }
+    fn add_class(&mut self, source: &str, library: bool) {
+        let Some(class) = parse_class(source) else {
+            return;
+        };
+        let info = self.classes.entry(class.name.name.clone()).or_default();
+        info.jack = true;
+        info.library = library;
+        info.parent = class.parent.as_ref().map(|parent| parent.name.clone());
+        for var in class.vars.iter().filter(|var| var.kind == VarKind::Field) {
+            for name in &var.names {
+                info.fields.push((name.name.clone(), var.var_type.clone()));
+            }
+        }
+        for subroutine in &class.subroutines {
+            info.subroutines.insert(
+                subroutine.name.name.clone(),
+                Some(SubroutineSig::from_subroutine(subroutine)),
+            );
+            if subroutine.kind == SubroutineKind::Method {
+                info.methods.push(subroutine.name.name.clone());
+            }
+        }
+        for decl in declarations(&class) {
+            info.constants.insert(decl.name.clone(), decl);
+        }
+    }
+    // add the functions declared in a vm file
+    pub fn add_vm(&mut self, source: &str) {
+        for line in source.lines() {
+            let mut parts = line.split_whitespace();
+            if parts.next() != Some("function") {
+                continue;
+            }
+            if let Some((class, func)) = parts.next().and_then(|name| name.split_once('.')) {
+                self.classes
+                    .entry(class.to_string())
+                    .or_default()
+                    .subroutines
+                    .entry(func.to_string())
+                    .or_insert(None);
+            }
+        }
+    }
+    // everything a class can see of the others, in a fixed order, so a
+    // build cache can tell when a class has to be compiled again
+    pub fn fingerprint(&self) -> String {
+        let mut classes: Vec<&String> = self.classes.keys().collect();
+        classes.sort();
+        let mut out = String::new();
+        for class in classes {
+            let info = &self.classes[class];
+            out.push_str(&format!("class {}\n", class));
+            if let Some(parent) = &info.parent {
+                out.push_str(&format!("  extends {}\n", parent));
+            }
+            for (name, var_type) in &info.fields {
+                out.push_str(&format!("  field {} {}\n", var_type, name));
+            }
+            out.push_str(&format!("  methods {:?}\n", info.methods));
+            let mut subroutines: Vec<_> = info.subroutines.iter().collect();
+            subroutines.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, sig) in subroutines {
+                out.push_str(&format!("  {} {:?}\n", name, sig));
+            }
+            let mut constants: Vec<&String> = info.constants.keys().collect();
+            constants.sort();
+            for name in constants {
+                out.push_str(&format!("  {} = {:?}\n", name, self.constant(class, name)));
+            }
+        }
+        out
+    }
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes.contains_key(class)
+    }
+    // None if the subroutine does not exist
+    // Some(None) if it exists but nothing is known about its signature
+    // methods are looked for in the parent classes too
+    pub(crate) fn get(&self, class: &str, name: &str) -> Option<Option<&SubroutineSig>> {
+        let own = self
+            .classes
+            .get(class)
+            .and_then(|c| c.subroutines.get(name))
+            .map(|sig| sig.as_ref());
+        own.or_else(|| self.inherited(class, name).map(Some))
+    }
+    // a method of one of the parent classes, the nearest
+    pub(crate) fn inherited(&self, class: &str, name: &str) -> Option<&SubroutineSig> {
+        self.ancestors(class).into_iter().find_map(|ancestor| {
+            self.classes[ancestor]
+                .subroutines
+                .get(name)?
+                .as_ref()
+                .filter(|sig| sig.kind == SubroutineKind::Method)
+        })
+    }
+
+    pub(crate) fn is_jack(&self, class: &str) -> bool {
+        self.classes.get(class).is_some_and(|info| info.jack)
+    }
+    pub(crate) fn is_library(&self, class: &str) -> bool {
+        self.classes.get(class).is_some_and(|info| info.library)
+    }
+    pub(crate) fn parent(&self, class: &str) -> Option<&str> {
+        self.classes.get(class)?.parent.as_deref()
+    }
+    // the parent, its parent and so on, nearest first, stopping short of
+    // an unknown class or of going round a cycle
+    pub(crate) fn ancestors(&self, class: &str) -> Vec<&str> {
+        let mut ancestors: Vec<&str> = Vec::new();
+        let mut next = self.parent(class);
+        while let Some(ancestor) = next {
+            if ancestor == class || ancestors.contains(&ancestor) || !self.has_class(ancestor) {
+                break;
+            }
+            ancestors.push(ancestor);
+            next = self.parent(ancestor);
+        }
+        ancestors
+    }
+    // whether the parent chain comes back round to the class
+    pub(crate) fn in_cycle(&self, class: &str) -> bool {
+        let mut seen: Vec<&str> = Vec::new();
+        let mut next = self.parent(class);
+        while let Some(ancestor) = next {
+            if ancestor == class {
+                return true;
+            }
+            if seen.contains(&ancestor) {
+                return false;
+            }
+            seen.push(ancestor);
+            next = self.parent(ancestor);
+        }
+        false
+    }
+    pub(crate) fn is_subclass(&self, class: &str, ancestor: &str) -> bool {
+        self.ancestors(class).contains(&ancestor)
+    }
+    // objects of classes in a hierarchy start with a word pointing at
+    // their class's vtable and their methods are called through it
+    pub(crate) fn is_polymorphic(&self, class: &str) -> bool {
+        self.parent(class).is_some() || self.is_extended(class)
+    }
+    pub(crate) fn is_extended(&self, class: &str) -> bool {
+        self.classes
+            .values()
+            .any(|info| info.parent.as_deref() == Some(class))
+    }
+    // the fields of the parent classes, in the order they are laid out
+    pub(crate) fn inherited_fields(&self, class: &str) -> Vec<(String, VarType)> {
+        self.ancestors(class)
+            .into_iter()
+            .rev()
+            .flat_map(|ancestor| self.classes[ancestor].fields.iter().cloned())
+            .collect()
+    }
+    // Class.method for each slot, the parents' methods come first and
+    // keep their slots when they are overridden
+    pub(crate) fn vtable(&self, class: &str) -> Vec<String> {
+        let mut slots: Vec<(&str, &str)> = Vec::new();
+        let chain = self.ancestors(class).into_iter().rev().chain([class]);
+        for owner in chain {
+            let Some(info) = self.classes.get(owner) else {
+                continue;
+            };
+            for method in &info.methods {
+                match slots.iter_mut().find(|(name, _)| name == method) {
+                    Some(slot) => slot.1 = owner,
+                    None => slots.push((method, owner)),
+                }
+            }
+        }
+        slots
+            .into_iter()
+            .map(|(name, owner)| format!("{}.{}", owner, name))
+            .collect()
+    }
+    pub(crate) fn slot(&self, class: &str, method: &str) -> Option<usize> {
+        self.vtable(class)
+            .iter()
+            .position(|entry| entry.rsplit('.').next() == Some(method))
+    }
+    pub(crate) fn constant_type(&self, class: &str, name: &str) -> Option<VarType> {
+        self.classes
+            .get(class)
+            .and_then(|c| c.constants.get(name))
+            .map(|decl| decl.var_type.clone())
+    }
+    // the value of a constant, worked out when first asked for since it
+    // may depend on classes added later
+    pub(crate) fn constant(&self, class: &str, name: &str) -> Option<i16> {
+        self.constant_within(class, name, 0)
+    }
+    fn constant_within(&self, class: &str, name: &str, depth: usize) -> Option<i16> {
+        if depth > MAX_CONST_DEPTH {
+            return None;
+        }
+        let info = self.classes.get(class)?;
+        let decl = info.constants.get(name)?;
+        evaluate(
+            &decl.value,
+            class,
+            &|name| info.constants.contains_key(name),
+            &mut |target, name| self.constant_within(target, name, depth + 1),
+        )
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+
+use super::{
+    ast::Subroutine,
+    compiler::{Compiler, SubroutineKind},
+    symbols::{VarKind, VarType},
+};
+
+// single inheritance, class Circle extends Shape
+// objects of a class in a hierarchy look like
+//   this 0       the class's vtable
+//   this 1..     the root's fields, then each subclass's in turn
+// so a Circle can be used wherever a Shape is expected. the vtable is a
+// run of jumps in rom, one per method, the parents' slots first with an
+// override taking over its parent's slot. methods called on a class in a
+// hierarchy go through the object's vtable with call-virtual, everything
+// else is an ordinary call
+// needs the project index, the type checker stops a lone class extending
+// another
+// a constructor runs its parent's with do super.new(...), so a constructor
+// of a class that is extended is compiled twice over
+//   Shape.new        allocates the object, sets its vtable, calls the next
+//   Shape.new$init   the body as a method on this, which a subclass's
+//                    constructor calls on its own object
+// nothing runs a parent's constructor unless super is called
+
+// the header field, not a name jack can write
+const VTABLE_FIELD: &str = "$vtable";
+// the target of a call to the parent's constructor
+pub(crate) const SUPER: &str = "super";
+
+// the method with a constructor's body, not a name jack can write
+pub(crate) fn init_name(constructor: &str) -> String {
+    format!("{}$init", constructor)
+}
+
+// the body of a chained constructor, compiled as a method
+pub(crate) fn init_method(subroutine: &Subroutine) -> Subroutine {
+    let mut init = subroutine.clone();
+    init.kind = SubroutineKind::Method;
+    init.name.name = init_name(&subroutine.name.name);
+    init
+}
+
+impl Compiler<'_> {
+    fn is_polymorphic(&self) -> bool {
+        self.index
+            .is_some_and(|index| index.is_polymorphic(&self.class_name))
+    }
+
+    // the header and the parents' fields take the first field numbers
+    pub(crate) fn inherited_fields(&mut self) -> Result<()> {
+        let Some(index) = self.index.filter(|_| self.is_polymorphic()) else {
+            return Ok(());
+        };
+        self.global_symbols
+            .insert(VTABLE_FIELD.to_string(), VarType::Int, VarKind::Field)?;
+        for (name, var_type) in index.inherited_fields(&self.class_name) {
+            self.global_symbols.insert(name, var_type, VarKind::Field)?;
+        }
+        Ok(())
+    }
+
+    // a constructor points the new object at its class's vtable
+    pub(crate) fn set_vtable(&mut self) {
+        if !self.is_polymorphic() {
+            return;
+        }
+        self.write(&format!("push vtable {}", self.class_name));
+        self.write("pop this 0");
+    }
+
+    // constructors of an extended class leave their body to a method
+    pub(crate) fn is_chained(&self, subroutine: &Subroutine) -> bool {
+        subroutine.kind == SubroutineKind::Constructor
+            && self
+                .index
+                .is_some_and(|index| index.is_extended(&self.class_name))
+    }
+
+    // Shape.new with the body left to Shape.new$init
+    pub(crate) fn constructor_stub(&mut self, subroutine: &Subroutine) {
+        let name = &subroutine.name.name;
+        let arg_count = subroutine.params.len();
+        self.write(&format!("function {}.{} 0", self.class_name, name));
+        self.allocate();
+        self.write("push pointer 0");
+        for n in 0..arg_count {
+            self.write(&format!("push argument {}", n));
+        }
+        self.write(&format!(
+            "call {}.{} {}",
+            self.class_name,
+            init_name(name),
+            arg_count + 1
+        ));
+        self.write("return");
+    }
+
+    // super.new(...) is the parent's new$init called on this
+    pub(crate) fn super_call(&mut self, constructor: &str) -> Option<String> {
+        let parent = self.index?.parent(&self.class_name)?.to_string();
+        self.write("push pointer 0");
+        Some(format!("{}.{}", parent, init_name(constructor)))
+    }
+
+    // after the class's last subroutine
+    pub(crate) fn do_vtable(&mut self) {
+        let Some(index) = self.index.filter(|_| self.is_polymorphic()) else {
+            return;
+        };
+        let mut line = format!("vtable {}", self.class_name);
+        for method in index.vtable(&self.class_name) {
+            line.push(' ');
+            line.push_str(&method);
+        }
+        self.write(&line);
+    }
+
+    // the vtable slot of a method called on an instance of class, None
+    // for an ordinary call
+    pub(crate) fn slot(&self, class: &str, method: &str) -> Option<usize> {
+        let index = self.index?;
+        if !index.is_polymorphic(class) {
+            return None;
+        }
+        index.slot(class, method)
+    }
+}
@@ -0,0 +1,574 @@
+use common::diagnostics::Diagnostics;
+
+use super::{
+    ast::{Class, ClassVar, Constant, Enum, Subroutine},
+    compiler::SubroutineKind,
+    format::expr,
+    parse::AstBuilder,
+    symbols::{VarKind, VarType},
+};
+
+// jackdoc, api pages built from the /** */ comments
+// one html and one markdown page per class and an index of the classes,
+// a page lists the constants, enums, fields, constructors, methods and
+// functions with their signatures and comments
+// class types in signatures, and `Class` or `Class.member` written in
+// backticks in a comment, link to the other documented classes
+// @param name and @return lines are pulled out of a comment and listed
+// after its text
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 50em; margin: 2em auto; } \
+code { background: #f4f4f4; } h3 { margin-bottom: 0.2em; } h3 code { background: none; }";
+
+pub struct JackDoc {
+    classes: Vec<Class>,
+}
+
+#[derive(Clone, Copy)]
+enum Markup {
+    Html,
+    Markdown,
+}
+
+// a signature in pieces so the types can link
+enum Piece {
+    Text(String),
+    Type(String),
+    Name(String),
+}
+
+struct Entry {
+    anchor: String,
+    signature: Vec<Piece>,
+    doc: Option<Doc>,
+}
+
+// a comment split into its text and tags
+struct Doc {
+    text: String,
+    params: Vec<(String, String)>,
+    returns: Option<String>,
+}
+
+impl Default for JackDoc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JackDoc {
+    pub fn new() -> Self {
+        Self {
+            classes: Vec::new(),
+        }
+    }
+    // false if the source does not parse, the errors go into diagnostics
+    pub fn add(&mut self, source: &str, file_name: &str, diagnostics: &mut Diagnostics) -> bool {
+        let mut builder = AstBuilder::new(file_name, false);
+        let class = builder.parse_class(source);
+        let Some(class) = class.filter(|_| !builder.diagnostics.has_errors()) else {
+            diagnostics.append(&mut builder.diagnostics);
+            return false;
+        };
+        self.classes.push(class);
+        true
+    }
+
+    // file name and contents of every page
+    pub fn pages(&mut self) -> Vec<(String, String)> {
+        self.classes.sort_by(|a, b| a.name.name.cmp(&b.name.name));
+        let mut pages = Vec::new();
+        for markup in [Markup::Html, Markup::Markdown] {
+            pages.push((format!("index.{}", markup.extension()), self.index(markup)));
+            for class in &self.classes {
+                pages.push((
+                    format!("{}.{}", class.name.name, markup.extension()),
+                    self.page(class, markup),
+                ));
+            }
+        }
+        pages
+    }
+
+    fn index(&self, markup: Markup) -> String {
+        let mut out = Vec::new();
+        match markup {
+            Markup::Html => {
+                out.push(header("Classes"));
+                out.push("<h1>Classes</h1>".to_string());
+                out.push("<ul>".to_string());
+                for class in &self.classes {
+                    let name = &class.name.name;
+                    let summary = summary(class.doc.as_deref())
+                        .map(|text| format!(" - {}", self.inline(text, markup)))
+                        .unwrap_or_default();
+                    out.push(format!(
+                        "<li><a href=\"{}.html\"><code>{}</code></a>{}</li>",
+                        name, name, summary
+                    ));
+                }
+                out.push("</ul>".to_string());
+                out.push("</body>\n</html>".to_string());
+            }
+            Markup::Markdown => {
+                out.push("# Classes\n".to_string());
+                for class in &self.classes {
+                    let name = &class.name.name;
+                    let summary = summary(class.doc.as_deref())
+                        .map(|text| format!(" - {}", self.inline(text, markup)))
+                        .unwrap_or_default();
+                    out.push(format!("- [`{}`]({}.md){}", name, name, summary));
+                }
+            }
+        }
+        out.join("\n") + "\n"
+    }
+
+    fn page(&self, class: &Class, markup: Markup) -> String {
+        let name = &class.name.name;
+        let mut out = Vec::new();
+        match markup {
+            Markup::Html => {
+                out.push(header(name));
+                out.push("<p><a href=\"index.html\">Classes</a></p>".to_string());
+                out.push(format!(
+                    "<h1>class {}{}</h1>",
+                    name,
+                    self.extends(class, markup)
+                ));
+            }
+            Markup::Markdown => {
+                out.push("[Classes](index.md)\n".to_string());
+                out.push(format!("# class {}{}\n", name, self.extends(class, markup)));
+            }
+        }
+        if let Some(doc) = &class.doc {
+            out.push(self.doc(&parse_doc(doc), markup));
+        }
+        let fields: Vec<Entry> = class.vars.iter().map(field).collect();
+        let subroutines = |kind: SubroutineKind| -> Vec<Entry> {
+            class
+                .subroutines
+                .iter()
+                .filter(|subroutine| subroutine.kind == kind)
+                .map(subroutine)
+                .collect()
+        };
+        let sections = [
+            ("Constants", class.constants.iter().map(constant).collect()),
+            ("Enums", class.enums.iter().map(enumeration).collect()),
+            ("Fields", fields),
+            ("Constructors", subroutines(SubroutineKind::Constructor)),
+            ("Methods", subroutines(SubroutineKind::Method)),
+            ("Functions", subroutines(SubroutineKind::Function)),
+        ];
+        for (title, entries) in sections {
+            if entries.is_empty() {
+                continue;
+            }
+            out.push(match markup {
+                Markup::Html => format!("<h2>{}</h2>", title),
+                Markup::Markdown => format!("## {}\n", title),
+            });
+            for entry in entries {
+                out.push(self.entry(&entry, markup));
+            }
+        }
+        if let Markup::Html = markup {
+            out.push("</body>\n</html>".to_string());
+        }
+        out.join("\n") + "\n"
+    }
+
+    // " extends Shape", linked when Shape is documented
+    fn extends(&self, class: &Class, markup: Markup) -> String {
+        let Some(parent) = &class.parent else {
+            return String::new();
+        };
+        let piece = Piece::Type(parent.name.clone());
+        format!(" extends {}", self.piece(&piece, markup))
+    }
+
+    fn entry(&self, entry: &Entry, markup: Markup) -> String {
+        let signature: String = entry
+            .signature
+            .iter()
+            .map(|piece| self.piece(piece, markup))
+            .collect();
+        let mut out = match markup {
+            Markup::Html => format!(
+                "<h3 id=\"{}\"><code>{}</code></h3>",
+                entry.anchor, signature
+            ),
+            Markup::Markdown => format!("<a id=\"{}\"></a>\n{}\n", entry.anchor, signature),
+        };
+        if let Some(doc) = &entry.doc {
+            out.push('\n');
+            out.push_str(&self.doc(doc, markup));
+        }
+        out
+    }
+
+    fn piece(&self, piece: &Piece, markup: Markup) -> String {
+        match (piece, markup) {
+            (Piece::Text(text), Markup::Html) => escape(text),
+            (Piece::Text(text), Markup::Markdown) => markdown_escape(text),
+            (Piece::Name(name), Markup::Html) => format!("<b>{}</b>", name),
+            (Piece::Name(name), Markup::Markdown) => {
+                format!("**{}**", markdown_escape(name))
+            }
+            (Piece::Type(class), _) if self.class(class).is_some() => {
+                markup.link(&markup.page(class, None), class)
+            }
+            (Piece::Type(class), Markup::Html) => class.clone(),
+            (Piece::Type(class), Markup::Markdown) => markdown_escape(class),
+        }
+    }
+
+    fn doc(&self, doc: &Doc, markup: Markup) -> String {
+        let mut out = Vec::new();
+        let paragraphs = doc
+            .text
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|paragraph| !paragraph.is_empty());
+        for paragraph in paragraphs {
+            let text = self.inline(paragraph, markup);
+            out.push(match markup {
+                Markup::Html => format!("<p>{}</p>", text),
+                Markup::Markdown => format!("{}\n", text),
+            });
+        }
+        let params: Vec<(Option<&str>, &str)> = doc
+            .params
+            .iter()
+            .map(|(name, text)| (Some(name.as_str()), text.as_str()))
+            .collect();
+        let returns: Vec<(Option<&str>, &str)> = doc
+            .returns
+            .iter()
+            .map(|text| (None, text.as_str()))
+            .collect();
+        for (title, items) in [("Parameters", params), ("Returns", returns)] {
+            if items.is_empty() {
+                continue;
+            }
+            match markup {
+                Markup::Html => out.push(format!("<h4>{}</h4>\n<ul>", title)),
+                Markup::Markdown => out.push(format!("**{}**\n", title)),
+            }
+            for (name, text) in items {
+                let name = name.map_or(String::new(), |name| match markup {
+                    Markup::Html => format!("<code>{}</code> ", escape(name)),
+                    Markup::Markdown => format!("`{}` ", name),
+                });
+                let text = self.inline(text, markup);
+                out.push(match markup {
+                    Markup::Html => format!("<li>{}{}</li>", name, text),
+                    Markup::Markdown => format!("- {}{}", name, text),
+                });
+            }
+            out.push(match markup {
+                Markup::Html => "</ul>".to_string(),
+                Markup::Markdown => String::new(),
+            });
+        }
+        out.join("\n")
+    }
+
+    // comment text, `code` spans link when they name a class or a member of one
+    fn inline(&self, text: &str, markup: Markup) -> String {
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('`') {
+            let Some(len) = rest[start + 1..].find('`') else {
+                break;
+            };
+            let code = &rest[start + 1..start + 1 + len];
+            out.push_str(&markup.text(&rest[..start]));
+            let shown = match markup {
+                Markup::Html => format!("<code>{}</code>", escape(code)),
+                Markup::Markdown => format!("`{}`", code),
+            };
+            out.push_str(&match self.resolve(code) {
+                Some((class, anchor)) => {
+                    markup.link(&markup.page(class, anchor.as_deref()), &shown)
+                }
+                None => shown,
+            });
+            rest = &rest[start + 2 + len..];
+        }
+        out.push_str(&markup.text(rest));
+        out
+    }
+
+    // the page and anchor of Class, Class.member or Class.Enum.MEMBER
+    fn resolve(&self, reference: &str) -> Option<(&str, Option<String>)> {
+        let reference = reference.trim_end_matches("()");
+        let mut parts = reference.split('.');
+        let class = self.class(parts.next()?)?;
+        let name = &class.name.name;
+        let Some(member) = parts.next() else {
+            return Some((name, None));
+        };
+        let anchor = if class.subroutines.iter().any(|s| s.name.name == member) {
+            member.to_string()
+        } else if class.constants.iter().any(|c| c.name.name == member) {
+            format!("const-{}", member)
+        } else if class.enums.iter().any(|e| e.name.name == member) {
+            format!("enum-{}", member)
+        } else {
+            return None;
+        };
+        Some((name, Some(anchor)))
+    }
+
+    fn class(&self, name: &str) -> Option<&Class> {
+        self.classes.iter().find(|class| class.name.name == name)
+    }
+}
+
+impl Markup {
+    fn extension(self) -> &'static str {
+        match self {
+            Markup::Html => "html",
+            Markup::Markdown => "md",
+        }
+    }
+    fn page(self, class: &str, anchor: Option<&str>) -> String {
+        match anchor {
+            Some(anchor) => format!("{}.{}#{}", class, self.extension(), anchor),
+            None => format!("{}.{}", class, self.extension()),
+        }
+    }
+    fn link(self, target: &str, label: &str) -> String {
+        match self {
+            Markup::Html => format!("<a href=\"{}\">{}</a>", target, label),
+            Markup::Markdown => format!("[{}]({})", label, target),
+        }
+    }
+    // markdown comments are left as written, apart from what would be html
+    fn text(self, text: &str) -> String {
+        match self {
+            Markup::Html => escape(text),
+            Markup::Markdown => markdown_entities(text),
+        }
+    }
+}
+
+fn header(title: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>{}</style>\n</head>\n<body>",
+        escape(title),
+        STYLE
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// markdown passes html through, so a < in a comment could start a tag
+fn markdown_entities(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// signatures are code, not emphasis
+fn markdown_escape(text: &str) -> String {
+    markdown_entities(text)
+        .replace('_', "\\_")
+        .replace('*', "\\*")
+}
+
+// the first line of a comment, for the index
+fn summary(doc: Option<&str>) -> Option<&str> {
+    doc?.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('@'))
+}
+
+fn parse_doc(doc: &str) -> Doc {
+    enum Tag {
+        Text,
+        Param,
+        Return,
+    }
+    let mut parsed = Doc {
+        text: String::new(),
+        params: Vec::new(),
+        returns: None,
+    };
+    let mut tag = Tag::Text;
+    for line in doc.lines() {
+        let trimmed = line.trim();
+        if let Some(rest) = trimmed.strip_prefix("@param") {
+            let rest = rest.trim();
+            let (name, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            parsed
+                .params
+                .push((name.to_string(), text.trim().to_string()));
+            tag = Tag::Param;
+            continue;
+        }
+        let returns = trimmed
+            .strip_prefix("@returns")
+            .or_else(|| trimmed.strip_prefix("@return"));
+        if let Some(rest) = returns {
+            parsed.returns = Some(rest.trim().to_string());
+            tag = Tag::Return;
+            continue;
+        }
+        // a tag runs on to the next blank line
+        let continued = match tag {
+            Tag::Param => parsed.params.last_mut().map(|(_, text)| text),
+            Tag::Return => parsed.returns.as_mut(),
+            Tag::Text => None,
+        };
+        match continued {
+            Some(text) if !trimmed.is_empty() => {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(trimmed);
+            }
+            _ => {
+                tag = Tag::Text;
+                parsed.text.push_str(line);
+                parsed.text.push('\n');
+            }
+        }
+    }
+    parsed
+}
+
+fn type_piece(var_type: &VarType) -> Piece {
+    match var_type {
+        VarType::Instance(class) => Piece::Type(class.clone()),
+        _ => Piece::Text(var_type.to_string()),
+    }
+}
+
+fn constant(constant: &Constant) -> Entry {
+    let name = &constant.name.name;
+    Entry {
+        anchor: format!("const-{}", name),
+        signature: vec![
+            Piece::Text("const ".to_string()),
+            type_piece(&constant.var_type),
+            Piece::Text(" ".to_string()),
+            Piece::Name(name.clone()),
+            Piece::Text(format!(" = {}", expr(&constant.value))),
+        ],
+        doc: constant.doc.as_deref().map(parse_doc),
+    }
+}
+
+fn enumeration(enumeration: &Enum) -> Entry {
+    let members: Vec<String> = enumeration
+        .members
+        .iter()
+        .map(|member| match &member.value {
+            Some(value) => format!("{} = {}", member.name.name, expr(value)),
+            None => member.name.name.clone(),
+        })
+        .collect();
+    let name = &enumeration.name.name;
+    Entry {
+        anchor: format!("enum-{}", name),
+        signature: vec![
+            Piece::Text("enum ".to_string()),
+            Piece::Name(name.clone()),
+            Piece::Text(format!(" {{ {} }}", members.join(", "))),
+        ],
+        doc: enumeration.doc.as_deref().map(parse_doc),
+    }
+}
+
+fn field(var: &ClassVar) -> Entry {
+    let kind = match var.kind {
+        VarKind::Static => "static ",
+        _ => "field ",
+    };
+    let names: Vec<&str> = var.names.iter().map(|n| n.name.as_str()).collect();
+    Entry {
+        anchor: format!("var-{}", names[0]),
+        signature: vec![
+            Piece::Text(kind.to_string()),
+            type_piece(&var.var_type),
+            Piece::Text(" ".to_string()),
+            Piece::Name(names.join(", ")),
+        ],
+        doc: var.doc.as_deref().map(parse_doc),
+    }
+}
+
+fn subroutine(subroutine: &Subroutine) -> Entry {
+    let kind = match subroutine.kind {
+        SubroutineKind::Constructor => "constructor ",
+        SubroutineKind::Method => "method ",
+        _ => "function ",
+    };
+    let mut signature = vec![Piece::Text(kind.to_string())];
+    match &subroutine.return_type {
+        Some(return_type) => signature.push(type_piece(return_type)),
+        None => signature.push(Piece::Text("void".to_string())),
+    }
+    signature.push(Piece::Text(" ".to_string()));
+    signature.push(Piece::Name(subroutine.name.name.clone()));
+    signature.push(Piece::Text("(".to_string()));
+    for (n, param) in subroutine.params.iter().enumerate() {
+        if n > 0 {
+            signature.push(Piece::Text(", ".to_string()));
+        }
+        signature.push(type_piece(&param.var_type));
+        signature.push(Piece::Text(format!(" {}", param.name.name)));
+    }
+    signature.push(Piece::Text(")".to_string()));
+    Entry {
+        anchor: subroutine.name.name.clone(),
+        signature,
+        doc: subroutine.doc.as_deref().map(parse_doc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(source: &str, name: &str) -> String {
+        let mut docs = JackDoc::new();
+        let mut diagnostics = Diagnostics::new();
+        assert!(docs.add(source, "Main.jack", &mut diagnostics));
+        docs.pages()
+            .into_iter()
+            .find(|(page, _)| page == name)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn markdown_escapes_html_in_comments_and_signatures() {
+        let source = "/** keeps <b>a & b</b> apart */
+class Main {
+    /** true when a < b and b > 0, see `a<b` */
+    function boolean less_than(int a_b, int b) {
+        return a_b < b;
+    }
+}
+";
+        let md = page(source, "Main.md");
+        assert!(md.contains("keeps &lt;b&gt;a &amp; b&lt;/b&gt; apart"));
+        assert!(md.contains("true when a &lt; b and b &gt; 0, see `a<b`"));
+        assert!(md.contains("**less\\_than**(int a\\_b, int b)"));
+        assert!(!md.contains("<b>"));
+
+        let html = page(source, "Main.html");
+        assert!(html.contains("true when a &lt; b and b &gt; 0, see <code>a&lt;b</code>"));
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use common::diagnostics::{Diagnostics, Span};
+use serde::Deserialize;
+
+use super::{
+    ast::{
+        Class, ExprKind, Expression, Ident, Statement, StatementKind, Subroutine, SubroutineCall,
+        Term,
+    },
+    compiler::subroutine_symbols,
+    fold::const_value,
+    symbols::{SymbolTable, VarKind},
+};
+
+// warnings for code that compiles but is probably wrong
+// runs after the type checker, on the tree as written (before constants
+// are substituted or anything is folded away)
+//
+// a single walk over each subroutine does everything, carrying the set
+// of locals that have definitely been assigned on every path to the
+// current statement, None once the statement cannot be reached
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Lint {
+    // locals that are never read
+    UnusedVariable,
+    UnusedParameter,
+    UnusedStatic,
+    // fields that are never read, assigned or not
+    UnreadField,
+    // statements after return, break, continue or an endless loop
+    UnreachableCode,
+    // while (true) or for (;;) with no break or return
+    InfiniteLoop,
+    // locals read before a let on some path
+    Uninitialized,
+    // subroutines that can run off the end
+    MissingReturn,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 8] = [
+        Lint::UnusedVariable,
+        Lint::UnusedParameter,
+        Lint::UnusedStatic,
+        Lint::UnreadField,
+        Lint::UnreachableCode,
+        Lint::InfiniteLoop,
+        Lint::Uninitialized,
+        Lint::MissingReturn,
+    ];
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused-variable",
+            Lint::UnusedParameter => "unused-parameter",
+            Lint::UnusedStatic => "unused-static",
+            Lint::UnreadField => "unread-field",
+            Lint::UnreachableCode => "unreachable-code",
+            Lint::InfiniteLoop => "infinite-loop",
+            Lint::Uninitialized => "uninitialized",
+            Lint::MissingReturn => "missing-return",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+// locals assigned on every path so far, None where nothing can get to
+type Assigned = Option<HashSet<String>>;
+
+pub(crate) struct Linter<'a> {
+    file_name: &'a str,
+    diagnostics: &'a mut Diagnostics,
+    allow: &'a [Lint],
+    // a subclass may be what reads the fields
+    extended: bool,
+    // the compiler's tables, so a name means here what it means there
+    global_symbols: &'a SymbolTable,
+    class_name: String,
+    subroutine_symbols: SymbolTable,
+    // where each variable was declared, for the unused warnings
+    global_spans: Vec<(String, Span)>,
+    subroutine_spans: Vec<(String, Span)>,
+    global_reads: HashSet<String>,
+    global_writes: HashSet<String>,
+    subroutine_reads: HashSet<String>,
+    subroutine_writes: HashSet<String>,
+    // only the first read of an uninitialised local is reported
+    uninitialized: HashSet<String>,
+    warnings: Vec<(Span, Lint, String)>,
+}
+
+impl<'a> Linter<'a> {
+    pub(crate) fn new(
+        file_name: &'a str,
+        diagnostics: &'a mut Diagnostics,
+        allow: &'a [Lint],
+        extended: bool,
+        global_symbols: &'a SymbolTable,
+    ) -> Self {
+        Self {
+            file_name,
+            diagnostics,
+            allow,
+            extended,
+            global_symbols,
+            class_name: String::new(),
+            subroutine_symbols: SymbolTable::new(),
+            global_spans: Vec::new(),
+            subroutine_spans: Vec::new(),
+            global_reads: HashSet::new(),
+            global_writes: HashSet::new(),
+            subroutine_reads: HashSet::new(),
+            subroutine_writes: HashSet::new(),
+            uninitialized: HashSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn check(mut self, class: &Class) -> Result<()> {
+        if Lint::ALL.iter().all(|lint| self.allow.contains(lint)) {
+            return Ok(());
+        }
+        self.class_name = class.name.name.clone();
+        for var in &class.vars {
+            for name in &var.names {
+                self.global_spans.push((name.name.clone(), name.span));
+            }
+        }
+        for subroutine in &class.subroutines {
+            self.check_subroutine(subroutine)?;
+        }
+        for (name, span) in std::mem::take(&mut self.global_spans) {
+            if self.global_reads.contains(&name) {
+                continue;
+            }
+            let written = self.global_writes.contains(&name);
+            match self.global_symbols.get(&name).map(|s| s.var_kind.clone()) {
+                Some(VarKind::Static) if written => self.warn(
+                    span,
+                    Lint::UnusedStatic,
+                    format!("static `{}` is assigned but never read", name),
+                ),
+                Some(VarKind::Static) => self.warn(
+                    span,
+                    Lint::UnusedStatic,
+                    format!("unused static `{}`", name),
+                ),
+                Some(_) if self.extended => {}
+                Some(_) if written => self.warn(
+                    span,
+                    Lint::UnreadField,
+                    format!("field `{}` is assigned but never read", name),
+                ),
+                Some(_) => self.warn(span, Lint::UnreadField, format!("unused field `{}`", name)),
+                None => {}
+            }
+        }
+
+        self.warnings
+            .sort_by_key(|(span, ..)| (span.line, span.col));
+        for (span, lint, msg) in std::mem::take(&mut self.warnings) {
+            self.diagnostics.warning(self.file_name, span, &msg);
+            self.diagnostics
+                .note(&format!("`--allow {}` turns this warning off", lint.name()));
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, span: Span, lint: Lint, msg: String) {
+        if !self.allow.contains(&lint) {
+            self.warnings.push((span, lint, msg));
+        }
+    }
+
+    fn check_subroutine(&mut self, subroutine: &Subroutine) -> Result<()> {
+        self.subroutine_symbols = subroutine_symbols(&self.class_name, subroutine)?;
+        self.subroutine_spans.clear();
+        self.subroutine_reads.clear();
+        self.subroutine_writes.clear();
+        self.uninitialized.clear();
+        for param in &subroutine.params {
+            self.subroutine_spans
+                .push((param.name.name.clone(), param.name.span));
+        }
+        for name in subroutine.locals.iter().flat_map(|var_dec| &var_dec.names) {
+            self.subroutine_spans.push((name.name.clone(), name.span));
+        }
+
+        let end = self.statements(&subroutine.body, Some(HashSet::new()));
+        if end.is_some() {
+            self.warn(
+                subroutine.name.span,
+                Lint::MissingReturn,
+                format!(
+                    "`{}` can reach the end without a return",
+                    subroutine.name.name
+                ),
+            );
+        }
+
+        for (name, span) in std::mem::take(&mut self.subroutine_spans) {
+            if self.subroutine_reads.contains(&name) {
+                continue;
+            }
+            let written = self.subroutine_writes.contains(&name);
+            match self
+                .subroutine_symbols
+                .get(&name)
+                .map(|s| s.var_kind.clone())
+            {
+                Some(VarKind::Argument) => self.warn(
+                    span,
+                    Lint::UnusedParameter,
+                    format!("unused parameter `{}`", name),
+                ),
+                Some(_) if written => self.warn(
+                    span,
+                    Lint::UnusedVariable,
+                    format!("variable `{}` is assigned but never read", name),
+                ),
+                Some(_) => self.warn(
+                    span,
+                    Lint::UnusedVariable,
+                    format!("unused variable `{}`", name),
+                ),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &[Statement], mut assigned: Assigned) -> Assigned {
+        // only the first dead statement of a block is reported
+        let mut reported = assigned.is_none();
+        for statement in statements {
+            if assigned.is_none() && !reported {
+                self.warn(
+                    statement.span,
+                    Lint::UnreachableCode,
+                    "unreachable statement".to_string(),
+                );
+                reported = true;
+            }
+            assigned = self.statement(statement, assigned);
+        }
+        assigned
+    }
+
+    fn statement(&mut self, statement: &Statement, assigned: Assigned) -> Assigned {
+        match &statement.kind {
+            StatementKind::Let {
+                target,
+                index,
+                value,
+            } => {
+                if let Some(index) = index {
+                    self.expr(index, &assigned);
+                }
+                self.expr(value, &assigned);
+                if index.is_some() {
+                    // a[i] = x reads the pointer a
+                    self.read(target, &assigned);
+                    return assigned;
+                }
+                self.write(&target.name);
+                assigned.map(|mut set| {
+                    set.insert(target.name.clone());
+                    set
+                })
+            }
+            StatementKind::Do(call) => {
+                self.call(call, &assigned);
+                assigned
+            }
+            StatementKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value, &assigned);
+                }
+                None
+            }
+            StatementKind::Break | StatementKind::Continue => None,
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition, &assigned);
+                let then_end = self.statements(then_branch, assigned.clone());
+                let else_end = match else_branch {
+                    Some(else_branch) => self.statements(else_branch, assigned.clone()),
+                    None => assigned,
+                };
+                merge(then_end, else_end)
+            }
+            StatementKind::While { condition, body } => {
+                self.expr(condition, &assigned);
+                self.statements(body, assigned.clone());
+                let endless = const_value(condition).is_some_and(|v| v != 0);
+                self.after_loop(statement.span, endless, body, assigned)
+            }
+            StatementKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                let mut assigned = assigned;
+                if let Some(init) = init {
+                    assigned = self.statement(init, assigned);
+                }
+                if let Some(condition) = condition {
+                    self.expr(condition, &assigned);
+                }
+                self.statements(body, assigned.clone());
+                if let Some(step) = step {
+                    self.statement(step, assigned.clone());
+                }
+                let endless = condition
+                    .as_ref()
+                    .is_none_or(|c| const_value(c).is_some_and(|v| v != 0));
+                self.after_loop(statement.span, endless, body, assigned)
+            }
+            StatementKind::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                self.expr(scrutinee, &assigned);
+                for value in cases.iter().flat_map(|case| &case.values) {
+                    self.expr(value, &assigned);
+                }
+                // without a default nothing might match, and a break
+                // leaves with whatever was assigned up to that point
+                let mut end = match default {
+                    Some(_) => None,
+                    None => assigned.clone(),
+                };
+                for case in cases.iter().chain(default) {
+                    let case_end = self.statements(&case.body, assigned.clone());
+                    end = merge(end, case_end);
+                    if has_break(&case.body) {
+                        end = merge(end, assigned.clone());
+                    }
+                }
+                end
+            }
+        }
+    }
+
+    // what follows a loop, nothing if it can never exit
+    fn after_loop(
+        &mut self,
+        span: Span,
+        endless: bool,
+        body: &[Statement],
+        assigned: Assigned,
+    ) -> Assigned {
+        if !endless || has_break(body) {
+            return assigned;
+        }
+        if !has_return(body) {
+            self.warn(
+                span,
+                Lint::InfiniteLoop,
+                "this loop never exits".to_string(),
+            );
+        }
+        None
+    }
+
+    fn expr(&mut self, expr: &Expression, assigned: &Assigned) {
+        match &expr.kind {
+            ExprKind::Term(term) => match term {
+                Term::Var(name) => self.read(
+                    &Ident {
+                        name: name.clone(),
+                        span: expr.span,
+                    },
+                    assigned,
+                ),
+                Term::Index(name, index) => {
+                    self.read(name, assigned);
+                    self.expr(index, assigned);
+                }
+                Term::Call(call) => self.call(call, assigned),
+                Term::Paren(inner) => self.expr(inner, assigned),
+                _ => {}
+            },
+            ExprKind::Unary(_, operand) => self.expr(operand, assigned),
+            ExprKind::Binary(_, lhs, rhs) => {
+                self.expr(lhs, assigned);
+                self.expr(rhs, assigned);
+            }
+        }
+    }
+    fn call(&mut self, call: &SubroutineCall, assigned: &Assigned) {
+        // obj.f() reads obj, Class.f() is not a variable at all
+        if let Some(target) = &call.target {
+            self.read(target, assigned);
+        }
+        for arg in &call.args {
+            self.expr(arg, assigned);
+        }
+    }
+
+    fn read(&mut self, name: &Ident, assigned: &Assigned) {
+        if let Some(symbol) = self.subroutine_symbols.get(&name.name) {
+            let local = symbol.var_kind == VarKind::Local;
+            self.subroutine_reads.insert(name.name.clone());
+            let unset = assigned
+                .as_ref()
+                .is_some_and(|set| !set.contains(&name.name));
+            if local && unset && self.uninitialized.insert(name.name.clone()) {
+                self.warn(
+                    name.span,
+                    Lint::Uninitialized,
+                    format!("`{}` may be used before it is assigned", name.name),
+                );
+            }
+        } else if self.global_symbols.get(&name.name).is_some() {
+            self.global_reads.insert(name.name.clone());
+        }
+    }
+    fn write(&mut self, name: &str) {
+        if self.subroutine_symbols.get(name).is_some() {
+            self.subroutine_writes.insert(name.to_string());
+        } else if self.global_symbols.get(name).is_some() {
+            self.global_writes.insert(name.to_string());
+        }
+    }
+}
+
+// where two paths join only what both assigned is certain
+fn merge(a: Assigned, b: Assigned) -> Assigned {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// a break that leaves this loop or switch, not one belonging to a nested one
+fn has_break(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StatementKind::Break => true,
+        StatementKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => has_break(then_branch) || else_branch.as_deref().is_some_and(has_break),
+        _ => false,
+    })
+}
+
+// a return anywhere inside
+fn has_return(statements: &[Statement]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StatementKind::Return(_) => true,
+        StatementKind::If {
+            then_branch,
+            else_branch,
+            ..
+        } => has_return(then_branch) || else_branch.as_deref().is_some_and(has_return),
+        StatementKind::While { body, .. } | StatementKind::For { body, .. } => has_return(body),
+        StatementKind::Switch { cases, default, .. } => cases
+            .iter()
+            .chain(default)
+            .any(|case| has_return(&case.body)),
+        _ => false,
+    })
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use super::{
+    ast::{Class, Subroutine},
+    compiler::SubroutineKind,
+    parse::parse_class,
+    recover::repair,
+    symbols::{SymbolTable, VarKind, VarType},
+};
+use common::diagnostics::Span;
+
+// what an editor needs to know about one class, where each name is
+// declared and what a name means at a given position
+// the scoping is the compiler's, a subroutine's arguments and locals hide
+// the class's fields and statics, methods have `this`
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub var_type: VarType,
+    pub var_kind: VarKind,
+    // the name in its declaration, `this` has the class name
+    pub span: Span,
+}
+
+pub struct Outline {
+    pub class: Class,
+    globals: SymbolTable,
+    // arguments and locals, one table per subroutine in class order
+    scopes: Vec<SymbolTable>,
+    // where the names in globals (None) and scopes (Some(n)) come from
+    declared: HashMap<(Option<usize>, String), Span>,
+}
+
+impl Outline {
+    pub fn new(class: Class) -> Self {
+        let mut outline = Self {
+            class,
+            globals: SymbolTable::new(),
+            scopes: Vec::new(),
+            declared: HashMap::new(),
+        };
+        outline.collect();
+        outline
+    }
+    // the statements with syntax errors are left out, None if even that
+    // does not parse
+    pub fn parse(source: &str) -> Option<Self> {
+        parse_class(source)
+            .or_else(|| parse_class(&repair(source)))
+            .map(Self::new)
+    }
+
+    fn collect(&mut self) {
+        let class = &self.class;
+        // duplicates are the compiler's to report, the first one counts
+        for var in &class.vars {
+            for name in &var.names {
+                if self
+                    .globals
+                    .insert(name.name.clone(), var.var_type.clone(), var.kind.clone())
+                    .is_ok()
+                {
+                    self.declared.insert((None, name.name.clone()), name.span);
+                }
+            }
+        }
+        for (n, subroutine) in class.subroutines.iter().enumerate() {
+            let mut scope = SymbolTable::new();
+            let mut declare = |name: &str, var_type: &VarType, var_kind: VarKind, span: Span| {
+                if scope
+                    .insert(name.to_string(), var_type.clone(), var_kind)
+                    .is_ok()
+                {
+                    self.declared.insert((Some(n), name.to_string()), span);
+                }
+            };
+            if subroutine.kind == SubroutineKind::Method {
+                let this = VarType::Instance(class.name.name.clone());
+                declare("this", &this, VarKind::Argument, class.name.span);
+            }
+            for param in &subroutine.params {
+                declare(
+                    &param.name.name,
+                    &param.var_type,
+                    VarKind::Argument,
+                    param.name.span,
+                );
+            }
+            for local in &subroutine.locals {
+                for name in &local.names {
+                    declare(&name.name, &local.var_type, VarKind::Local, name.span);
+                }
+            }
+            self.scopes.push(scope);
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.class.name.name
+    }
+    // the subroutine a 1 based line and column is in
+    fn scope_at(&self, line: usize, col: usize) -> Option<usize> {
+        self.class
+            .subroutines
+            .iter()
+            .position(|subroutine| contains(subroutine.span, line, col))
+    }
+    pub fn subroutine(&self, name: &str) -> Option<&Subroutine> {
+        self.class
+            .subroutines
+            .iter()
+            .find(|subroutine| subroutine.name.name == name)
+    }
+
+    // what a variable name means at a position
+    pub fn variable(&self, line: usize, col: usize, name: &str) -> Option<Variable> {
+        let scope = self.scope_at(line, col);
+        let local = scope.and_then(|n| self.scopes[n].get(name).map(|symbol| (Some(n), symbol)));
+        let (scope, symbol) =
+            local.or_else(|| self.globals.get(name).map(|symbol| (None, symbol)))?;
+        Some(Variable {
+            name: name.to_string(),
+            var_type: symbol.var_type.clone(),
+            var_kind: symbol.var_kind.clone(),
+            span: self.declared[&(scope, name.to_string())],
+        })
+    }
+    // the arguments and locals of a subroutine in declaration order
+    pub fn subroutine_variables(&self, subroutine: &Subroutine) -> Vec<Variable> {
+        self.class
+            .subroutines
+            .iter()
+            .position(|s| std::ptr::eq(s, subroutine))
+            .map(|n| self.sorted(n))
+            .unwrap_or_default()
+    }
+    fn sorted(&self, scope: usize) -> Vec<Variable> {
+        let mut variables: Vec<Variable> = self.scopes[scope]
+            .table
+            .iter()
+            .map(|(name, symbol)| Variable {
+                name: name.clone(),
+                var_type: symbol.var_type.clone(),
+                var_kind: symbol.var_kind.clone(),
+                span: self.declared[&(Some(scope), name.clone())],
+            })
+            .collect();
+        variables.sort_by_key(|variable| (variable.span.line, variable.span.col));
+        variables
+    }
+
+    // a constant or an enum of the class
+    pub fn constant(&self, name: &str) -> Option<Span> {
+        let constant = self
+            .class
+            .constants
+            .iter()
+            .find(|constant| constant.name.name == name)
+            .map(|constant| constant.name.span);
+        constant.or_else(|| {
+            self.class
+                .enums
+                .iter()
+                .find(|e| e.name.name == name)
+                .map(|e| e.name.span)
+        })
+    }
+    pub fn enum_member(&self, enum_name: &str, name: &str) -> Option<Span> {
+        self.class
+            .enums
+            .iter()
+            .find(|e| e.name.name == enum_name)?
+            .members
+            .iter()
+            .find(|member| member.name.name == name)
+            .map(|member| member.name.span)
+    }
+}
+
+// whether a span holds a 1 based line and column
+pub fn contains(span: Span, line: usize, col: usize) -> bool {
+    (span.line, span.col) <= (line, col) && (line, col) < (span.end_line, span.end_col)
+}
+
+// method int size(int a, Array b)
+pub fn signature(subroutine: &Subroutine) -> String {
+    let kind = match subroutine.kind {
+        SubroutineKind::Constructor => "constructor",
+        SubroutineKind::Method => "method",
+        SubroutineKind::Function | SubroutineKind::None => "function",
+    };
+    let return_type = subroutine
+        .return_type
+        .as_ref()
+        .map_or("void".to_string(), |t| t.to_string());
+    let params: Vec<String> = subroutine
+        .params
+        .iter()
+        .map(|param| format!("{} {}", param.var_type, param.name.name))
+        .collect();
+    format!(
+        "{} {} {}({})",
+        kind,
+        return_type,
+        subroutine.name.name,
+        params.join(", ")
+    )
+}
+
+impl Variable {
+    // field int count, var Array a, ...
+    pub fn declaration(&self) -> String {
+        let kind = match self.var_kind {
+            VarKind::Static => "static",
+            VarKind::Field => "field",
+            VarKind::Local => "var",
+            VarKind::Argument => "argument",
+        };
+        format!("{} {} {}", kind, self.var_type, self.name)
+    }
+}
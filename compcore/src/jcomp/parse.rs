@@ -0,0 +1,728 @@
+use pest::{
+    iterators::{Pair, Pairs},
+    Parser,
+};
+
+use super::{
+    ast::{
+        BinaryOp, Case, Class, ClassVar, Constant, Enum, EnumMember, ExprKind, Expression, Ident,
+        Keyword, Parameter, Radix, Statement, StatementKind, Subroutine, SubroutineCall, Term,
+        UnaryOp, VarDec,
+    },
+    charset::decode,
+    compiler::{JackParser, Rule, SubroutineKind},
+    recover::syntax_errors,
+    symbols::{VarKind, VarType},
+};
+use crate::utils::span_of;
+use common::diagnostics::{Diagnostics, Span};
+
+// builds the typed ast from the JackParser output
+
+// None if the source does not parse, without working out why
+pub fn parse_class(source: &str) -> Option<Class> {
+    let pairs = JackParser::parse(Rule::class_file, source).ok()?;
+    Some(AstBuilder::new("", false).build_class(pairs))
+}
+
+pub struct AstBuilder {
+    file_name: String,
+    // conventional operator precedence rather than jack's left to right
+    precedence: bool,
+    // problems the grammar cannot catch, bad literals etc
+    pub diagnostics: Diagnostics,
+}
+
+impl AstBuilder {
+    pub fn new(file_name: &str, precedence: bool) -> Self {
+        Self {
+            file_name: file_name.to_string(),
+            precedence,
+            diagnostics: Diagnostics::new(),
+        }
+    }
+    // None on syntax errors, all of them go into diagnostics
+    pub fn parse_class(&mut self, source: &str) -> Option<Class> {
+        match JackParser::parse(Rule::class_file, source) {
+            Ok(pairs) => Some(self.build_class(pairs)),
+            Err(_) => {
+                for (span, msg) in syntax_errors(source) {
+                    self.error(span, &msg);
+                }
+                None
+            }
+        }
+    }
+
+    fn build_class(&mut self, pairs: Pairs<Rule>) -> Class {
+        let mut class = Class {
+            doc: None,
+            name: Ident {
+                name: String::new(),
+                span: Span::default(),
+            },
+            parent: None,
+            constants: Vec::new(),
+            enums: Vec::new(),
+            vars: Vec::new(),
+            subroutines: Vec::new(),
+        };
+        for pair in pairs {
+            match pair.as_rule() {
+                Rule::doc => class.doc = Some(doc_text(pair.as_str())),
+                Rule::class_name => class.name = ident(&pair),
+                Rule::extends => class.parent = pair.into_inner().next().map(|name| ident(&name)),
+                Rule::class_const => class.constants.push(self.build_constant(pair)),
+                Rule::class_enum => class.enums.push(self.build_enum(pair)),
+                Rule::class_var => class.vars.push(build_class_var(pair)),
+                Rule::subroutine => class.subroutines.push(self.build_subroutine(pair)),
+                _ => {}
+            }
+        }
+        class
+    }
+
+    fn build_constant(&mut self, pair: Pair<Rule>) -> Constant {
+        let (doc, span) = doc_comment(&pair);
+        let mut pair_iter = declaration(pair);
+        let var_type = VarType::from(pair_iter.next().unwrap().as_str());
+        let name = ident(&pair_iter.next().unwrap());
+        Constant {
+            doc,
+            var_type,
+            name,
+            value: self.build_expression(pair_iter.next().unwrap()),
+            span,
+        }
+    }
+    fn build_enum(&mut self, pair: Pair<Rule>) -> Enum {
+        let (doc, span) = doc_comment(&pair);
+        let mut pair_iter = declaration(pair);
+        let name = ident(&pair_iter.next().unwrap());
+        let members = pair_iter
+            .map(|member| {
+                let mut member_iter = member.into_inner();
+                EnumMember {
+                    name: ident(&member_iter.next().unwrap()),
+                    value: member_iter.next().map(|p| self.build_expression(p)),
+                }
+            })
+            .collect();
+        Enum {
+            doc,
+            name,
+            members,
+            span,
+        }
+    }
+
+    fn build_subroutine(&mut self, pair: Pair<Rule>) -> Subroutine {
+        let (doc, span) = doc_comment(&pair);
+        let mut pair_iter = declaration(pair);
+        let kind = match pair_iter.next().unwrap().as_str() {
+            "constructor" => SubroutineKind::Constructor,
+            "method" => SubroutineKind::Method,
+            "function" => SubroutineKind::Function,
+            _ => unreachable!(),
+        };
+        let return_pair = pair_iter.next().unwrap();
+        let return_type = match return_pair.as_rule() {
+            Rule::void => None,
+            _ => Some(VarType::from(return_pair.as_str())),
+        };
+        let name = ident(&pair_iter.next().unwrap());
+        let params = pair_iter
+            .next()
+            .unwrap()
+            .into_inner()
+            .map(|param| {
+                let mut param_iter = param.into_inner();
+                let var_type = VarType::from(param_iter.next().unwrap().as_str());
+                Parameter {
+                    var_type,
+                    name: ident(&param_iter.next().unwrap()),
+                }
+            })
+            .collect();
+        let var_pair = pair_iter.next().unwrap();
+        let body_span = span_of(&var_pair.as_span());
+        let locals = var_pair
+            .into_inner()
+            .map(|var_dec| {
+                let span = span_of(&var_dec.as_span());
+                let mut var_iter = var_dec.into_inner();
+                let var_type = VarType::from(var_iter.next().unwrap().as_str());
+                VarDec {
+                    var_type,
+                    names: var_iter.map(|p| ident(&p)).collect(),
+                    span,
+                }
+            })
+            .collect();
+        let body = self.build_statements(pair_iter.next().unwrap());
+        Subroutine {
+            doc,
+            kind,
+            return_type,
+            name,
+            params,
+            locals,
+            body,
+            span,
+            body_span,
+        }
+    }
+
+    fn build_statements(&mut self, pair: Pair<Rule>) -> Vec<Statement> {
+        pair.into_inner().map(|p| self.build_statement(p)).collect()
+    }
+
+    fn build_statement(&mut self, pair: Pair<Rule>) -> Statement {
+        let span = span_of(&pair.as_span());
+        let kind = match pair.as_rule() {
+            Rule::let_st | Rule::assignment => {
+                // for loops may leave out the let
+                let mut pair_iter = pair.into_inner().filter(|p| p.as_rule() != Rule::let_kw);
+                let lhs = pair_iter.next().unwrap();
+                let (target, index) = match lhs.as_rule() {
+                    Rule::array_var => {
+                        let mut lhs_iter = lhs.into_inner();
+                        let target = ident(&lhs_iter.next().unwrap());
+                        let index = self.array_index(lhs_iter.next().unwrap());
+                        (target, Some(index))
+                    }
+                    Rule::identifier => (ident(&lhs), None),
+                    _ => unreachable!(),
+                };
+                StatementKind::Let {
+                    target,
+                    index,
+                    value: self.build_expression(pair_iter.next().unwrap()),
+                }
+            }
+            Rule::if_st => {
+                let mut pair_iter = pair.into_inner();
+                StatementKind::If {
+                    condition: self.build_expression(pair_iter.next().unwrap()),
+                    then_branch: self.build_statements(pair_iter.next().unwrap()),
+                    else_branch: pair_iter.next().map(|p| self.build_statements(p)),
+                }
+            }
+            Rule::while_st => {
+                let mut pair_iter = pair.into_inner();
+                StatementKind::While {
+                    condition: self.build_expression(pair_iter.next().unwrap()),
+                    body: self.build_statements(pair_iter.next().unwrap()),
+                }
+            }
+            Rule::for_st => {
+                let mut pair_iter = pair.into_inner();
+                let mut part = || pair_iter.next().unwrap().into_inner().next();
+                let init = part().map(|p| Box::new(self.build_statement(p)));
+                let condition = part().map(|p| self.build_expression(p));
+                let step = part().map(|p| Box::new(self.build_statement(p)));
+                StatementKind::For {
+                    init,
+                    condition,
+                    step,
+                    body: self.build_statements(pair_iter.next().unwrap()),
+                }
+            }
+            Rule::switch_st => {
+                let mut pair_iter = pair.into_inner();
+                let scrutinee = self.build_expression(pair_iter.next().unwrap());
+                let mut cases = Vec::new();
+                let mut default = None;
+                for clause in pair_iter {
+                    let rule = clause.as_rule();
+                    let case = self.build_case(clause);
+                    match rule {
+                        Rule::case_clause => cases.push(case),
+                        _ => default = Some(case),
+                    }
+                }
+                StatementKind::Switch {
+                    scrutinee,
+                    cases,
+                    default,
+                }
+            }
+            Rule::break_st => StatementKind::Break,
+            Rule::continue_st => StatementKind::Continue,
+            Rule::do_st => StatementKind::Do(self.build_call(pair.into_inner().next().unwrap())),
+            Rule::return_st => {
+                StatementKind::Return(pair.into_inner().next().map(|p| self.build_expression(p)))
+            }
+            _ => unreachable!("{:?}", pair.as_rule()),
+        };
+        Statement { kind, span }
+    }
+
+    fn build_case(&mut self, pair: Pair<Rule>) -> Case {
+        // the values then the statements, a default has no values
+        let span = span_of(&pair.as_span());
+        let mut values = Vec::new();
+        let mut body = Vec::new();
+        for part in pair.into_inner() {
+            match part.as_rule() {
+                Rule::expression => values.push(self.build_expression(part)),
+                _ => body = self.build_statements(part),
+            }
+        }
+        Case { values, body, span }
+    }
+
+    fn array_index(&mut self, pair: Pair<Rule>) -> Expression {
+        // array_index is "[" ~ expression ~ "]"
+        self.build_expression(pair.into_inner().next().unwrap())
+    }
+
+    fn build_call(&mut self, pair: Pair<Rule>) -> SubroutineCall {
+        let span = span_of(&pair.as_span());
+        let mut pair_iter = pair.into_inner();
+        let name_pair = pair_iter.next().unwrap();
+        let (target, name) = match name_pair.as_rule() {
+            Rule::identifier => (None, ident(&name_pair)),
+            Rule::dotted_id => {
+                let mut parts = split_dotted(&name_pair);
+                let name = parts.pop().unwrap();
+                (parts.pop(), name)
+            }
+            _ => unreachable!(),
+        };
+        let args = pair_iter
+            .next()
+            .unwrap()
+            .into_inner()
+            .map(|p| self.build_expression(p))
+            .collect();
+        SubroutineCall {
+            target,
+            name,
+            args,
+            span,
+        }
+    }
+
+    pub(crate) fn build_expression(&mut self, pair: Pair<Rule>) -> Expression {
+        // expression is term ~ (op ~ term)*, jack evaluates strictly left to right
+        // so a op b op c is (a op b) op c
+        // in precedence mode the usual rules apply instead
+
+        let span = span_of(&pair.as_span());
+        let mut pair_iter = pair.into_inner();
+        let mut operands = vec![self.build_operand(&mut pair_iter)];
+        let mut ops = Vec::new();
+        while let Some(op) = pair_iter.next() {
+            ops.push(binary_op(op.as_str()));
+            operands.push(self.build_operand(&mut pair_iter));
+        }
+        if self.precedence {
+            return by_precedence(operands, &ops);
+        }
+        let expr = left_to_right(operands.clone(), &ops);
+        if ops.len() > 1 && !same_grouping(&expr, &by_precedence(operands, &ops)) {
+            self.diagnostics.warning(
+                &self.file_name,
+                span,
+                "this expression evaluates left to right, conventional precedence would give a different result",
+            );
+            self.diagnostics
+                .note("add parentheses to make the order explicit, or build with --precedence");
+        }
+        expr
+    }
+
+    pub(crate) fn build_operand(&mut self, pair_iter: &mut Pairs<Rule>) -> Expression {
+        // a term, possibly preceded by unary operators
+        // the grammar flattens (unary_op ~ term) so the operator
+        // and its term arrive as siblings
+
+        let pair = pair_iter.next().unwrap();
+        match pair.as_rule() {
+            Rule::unary_op => {
+                let operand = self.build_operand(pair_iter);
+                let op_span = span_of(&pair.as_span());
+                let op = match pair.as_str() {
+                    "-" => UnaryOp::Neg,
+                    "~" => UnaryOp::Not,
+                    _ => unreachable!(),
+                };
+                Expression {
+                    span: Span::new(
+                        op_span.line,
+                        op_span.col,
+                        operand.span.end_line,
+                        operand.span.end_col,
+                    ),
+                    kind: ExprKind::Unary(op, Box::new(operand)),
+                }
+            }
+            _ => self.build_term(pair),
+        }
+    }
+
+    fn build_term(&mut self, pair: Pair<Rule>) -> Expression {
+        let span = span_of(&pair.as_span());
+        let term = match pair.as_rule() {
+            Rule::int => {
+                // the course spec's 0..32767, and -32768 when the sign is
+                // written as part of the literal
+                let text = pair.as_str();
+                let value = self.literal(text, text.parse::<i64>().ok(), -32768, 32767, span);
+                Term::Int(value, Radix::Decimal)
+            }
+            Rule::hex_num => {
+                let text = pair.as_str();
+                let value = i64::from_str_radix(&text[2..], 16).ok();
+                Term::Int(self.literal(text, value, 0, 0xFFFF, span), Radix::Hex)
+            }
+            Rule::bin_num => {
+                let text = pair.as_str();
+                let value = i64::from_str_radix(&text[2..], 2).ok();
+                Term::Int(self.literal(text, value, 0, 0xFFFF, span), Radix::Binary)
+            }
+            Rule::char_lit => {
+                let raw = unquote(pair.as_str());
+                match decode(raw) {
+                    Ok(codes) if codes.len() == 1 => {}
+                    Ok(_) => self.error(span, "character literal must be a single character"),
+                    Err(msg) => self.error(span, &msg),
+                }
+                Term::Char(raw.to_string())
+            }
+            Rule::string => {
+                let raw = unquote(pair.as_str());
+                if let Err(msg) = decode(raw) {
+                    self.error(span, &msg);
+                }
+                Term::Str(raw.to_string())
+            }
+            Rule::keyword => Term::Keyword(match pair.as_str() {
+                "true" => Keyword::True,
+                "false" => Keyword::False,
+                "null" => Keyword::Null,
+                "this" => Keyword::This,
+                _ => unreachable!(),
+            }),
+            Rule::identifier => Term::Var(pair.as_str().to_string()),
+            Rule::array_var => {
+                let mut pair_iter = pair.into_inner();
+                let name = ident(&pair_iter.next().unwrap());
+                Term::Index(name, Box::new(self.array_index(pair_iter.next().unwrap())))
+            }
+            Rule::path => Term::Path(split_dotted(&pair)),
+            Rule::subroutine_call => Term::Call(Box::new(self.build_call(pair))),
+            Rule::expression => Term::Paren(Box::new(self.build_expression(pair))),
+            _ => unreachable!("unknown term {:?},{}", pair.as_rule(), pair.as_str()),
+        };
+        Expression {
+            kind: ExprKind::Term(term),
+            span,
+        }
+    }
+    fn error(&mut self, span: Span, msg: &str) {
+        self.diagnostics.error(&self.file_name, span, msg);
+    }
+    // range check an integer literal and wrap it to 16 bits
+    fn literal(&mut self, text: &str, value: Option<i64>, min: i64, max: i64, span: Span) -> i32 {
+        match value {
+            Some(value) if (min..=max).contains(&value) => value as u16 as i16 as i32,
+            _ => {
+                self.error(span, &format!("integer literal `{}` is out of range", text));
+                0
+            }
+        }
+    }
+}
+
+// strip the quotes from a string or character literal
+fn unquote(text: &str) -> &str {
+    &text[1..text.len() - 1]
+}
+
+fn ident(pair: &Pair<Rule>) -> Ident {
+    Ident {
+        name: pair.as_str().to_string(),
+        span: span_of(&pair.as_span()),
+    }
+}
+
+// dotted_id and path are atomic, split them into their identifiers
+fn split_dotted(pair: &Pair<Rule>) -> Vec<Ident> {
+    let input = pair.get_input();
+    let mut start = pair.as_span().start();
+    pair.as_str()
+        .split('.')
+        .map(|part| {
+            let span = pest::Span::new(input, start, start + part.len()).unwrap();
+            start += part.len() + 1;
+            Ident {
+                name: part.to_string(),
+                span: span_of(&span),
+            }
+        })
+        .collect()
+}
+
+// the /** */ comment of a declaration and its span from the keyword on,
+// so it lines up with declarations without one
+fn doc_comment(pair: &Pair<Rule>) -> (Option<String>, Span) {
+    let span = pair.as_span();
+    let doc = pair
+        .clone()
+        .into_inner()
+        .next()
+        .filter(|p| p.as_rule() == Rule::doc);
+    let Some(doc) = doc else {
+        return (None, span_of(&span));
+    };
+    let input = pair.get_input();
+    let rest = &input[doc.as_span().end()..span.end()];
+    let start = span.end() - skip_comments(rest).len();
+    let span = pest::Span::new(input, start, span.end()).unwrap_or(span);
+    (Some(doc_text(doc.as_str())), span_of(&span))
+}
+// the parts of a declaration after its doc comment
+fn declaration(pair: Pair<Rule>) -> impl Iterator<Item = Pair<Rule>> {
+    pair.into_inner().filter(|p| p.as_rule() != Rule::doc)
+}
+fn skip_comments(mut text: &str) -> &str {
+    loop {
+        text = text.trim_start();
+        if let Some(comment) = text.strip_prefix("//") {
+            text = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if let Some(comment) = text.strip_prefix("/*") {
+            text = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+        } else {
+            return text;
+        }
+    }
+}
+// /** and */ taken off, and the * that starts each line and the
+// indentation the lines have in common
+fn doc_text(comment: &str) -> String {
+    let inner = &comment[3..comment.len() - 2];
+    let lines: Vec<&str> = inner
+        .lines()
+        .map(|line| {
+            let line = line.trim_end();
+            let trimmed = line.trim_start();
+            trimmed.strip_prefix('*').unwrap_or(line)
+        })
+        .collect();
+    // the first line follows /** so its indentation does not count
+    let indent = lines
+        .iter()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = lines
+        .iter()
+        .enumerate()
+        .map(|(n, line)| match n {
+            0 => line.trim_start(),
+            _ => line.get(indent..).unwrap_or("").trim_end(),
+        })
+        .collect();
+    lines.join("\n").trim().to_string()
+}
+
+fn build_class_var(pair: Pair<Rule>) -> ClassVar {
+    let (doc, span) = doc_comment(&pair);
+    let mut pair_iter = declaration(pair);
+    let kind = match pair_iter.next().unwrap().as_str() {
+        "field" => VarKind::Field,
+        "static" => VarKind::Static,
+        _ => unreachable!(),
+    };
+    let var_type = VarType::from(pair_iter.next().unwrap().as_str());
+    ClassVar {
+        doc,
+        kind,
+        var_type,
+        names: pair_iter.map(|p| ident(&p)).collect(),
+        span,
+    }
+}
+
+fn left_to_right(operands: Vec<Expression>, ops: &[BinaryOp]) -> Expression {
+    let mut operands = operands.into_iter();
+    let mut expr = operands.next().unwrap();
+    for (op, rhs) in ops.iter().zip(operands) {
+        expr = binary(*op, expr, rhs);
+    }
+    expr
+}
+
+fn by_precedence(operands: Vec<Expression>, ops: &[BinaryOp]) -> Expression {
+    // shunting yard, all operators are left associative
+
+    let mut operands = operands.into_iter();
+    let mut output = vec![operands.next().unwrap()];
+    let mut pending: Vec<BinaryOp> = Vec::new();
+    for (op, rhs) in ops.iter().zip(operands) {
+        while pending
+            .last()
+            .is_some_and(|top| top.precedence() >= op.precedence())
+        {
+            reduce(&mut output, pending.pop().unwrap());
+        }
+        pending.push(*op);
+        output.push(rhs);
+    }
+    while let Some(op) = pending.pop() {
+        reduce(&mut output, op);
+    }
+    output.pop().unwrap()
+}
+
+fn reduce(output: &mut Vec<Expression>, op: BinaryOp) {
+    let rhs = output.pop().unwrap();
+    let lhs = output.pop().unwrap();
+    output.push(binary(op, lhs, rhs));
+}
+
+// both trees are built from the same operands, so they only differ
+// in how the binary operators are nested
+fn same_grouping(a: &Expression, b: &Expression) -> bool {
+    match (&a.kind, &b.kind) {
+        (ExprKind::Binary(op_a, lhs_a, rhs_a), ExprKind::Binary(op_b, lhs_b, rhs_b)) => {
+            op_a == op_b && same_grouping(lhs_a, lhs_b) && same_grouping(rhs_a, rhs_b)
+        }
+        (ExprKind::Binary(..), _) | (_, ExprKind::Binary(..)) => false,
+        _ => a.span == b.span,
+    }
+}
+
+pub(crate) fn binary(op: BinaryOp, lhs: Expression, rhs: Expression) -> Expression {
+    let span = Span::new(
+        lhs.span.line,
+        lhs.span.col,
+        rhs.span.end_line,
+        rhs.span.end_col,
+    );
+    Expression {
+        kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        span,
+    }
+}
+
+pub(crate) fn binary_op(op: &str) -> BinaryOp {
+    match op {
+        "+" => BinaryOp::Add,
+        "-" => BinaryOp::Sub,
+        "*" => BinaryOp::Mul,
+        "/" => BinaryOp::Div,
+        "<" => BinaryOp::Lt,
+        ">" => BinaryOp::Gt,
+        "=" => BinaryOp::Eq,
+        "&" => BinaryOp::And,
+        "|" => BinaryOp::Or,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the expression f returns and how many warnings parsing it gave
+    fn parse_return(expr: &str, precedence: bool) -> (Expression, usize) {
+        let source = format!(
+            "class T {{ function int f(int a, int b, int c) {{ return {}; }} }}",
+            expr
+        );
+        let mut builder = AstBuilder::new("T.jack", precedence);
+        let class = builder.parse_class(&source).expect("test source parses");
+        let StatementKind::Return(Some(value)) = &class.subroutines[0].body[0].kind else {
+            panic!("f starts with a return");
+        };
+        (value.clone(), builder.diagnostics.warning_count())
+    }
+
+    // fully parenthesised
+    fn grouping(expr: &Expression) -> String {
+        match &expr.kind {
+            ExprKind::Binary(op, lhs, rhs) => {
+                format!("({} {} {})", grouping(lhs), op, grouping(rhs))
+            }
+            ExprKind::Term(Term::Var(name)) => name.clone(),
+            ExprKind::Term(Term::Int(value, _)) => value.to_string(),
+            ExprKind::Term(Term::Paren(inner)) => grouping(inner),
+            _ => panic!("unexpected expression {:?}", expr),
+        }
+    }
+
+    // a, b, c ... at columns 1, 3, 5 ...
+    fn operands(count: usize) -> Vec<Expression> {
+        (0..count)
+            .map(|n| Expression {
+                kind: ExprKind::Term(Term::Var(((b'a' + n as u8) as char).to_string())),
+                span: Span::new(1, 2 * n + 1, 1, 2 * n + 2),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn by_precedence_binds_tighter_operators_first() {
+        let (expr, warnings) = parse_return("a + b * c", true);
+        assert_eq!(grouping(&expr), "(a + (b * c))");
+        assert_eq!(warnings, 0);
+
+        let (expr, _) = parse_return("a | b & c < a + b * c", true);
+        assert_eq!(grouping(&expr), "(a | (b & (c < (a + (b * c)))))");
+    }
+
+    #[test]
+    fn by_precedence_is_left_associative() {
+        let (expr, _) = parse_return("a - b - c", true);
+        assert_eq!(grouping(&expr), "((a - b) - c)");
+        let (expr, _) = parse_return("a / b * c", true);
+        assert_eq!(grouping(&expr), "((a / b) * c)");
+        let (expr, _) = parse_return("a * b + c * a", true);
+        assert_eq!(grouping(&expr), "((a * b) + (c * a))");
+    }
+
+    #[test]
+    fn left_to_right_warns_when_precedence_differs() {
+        let (expr, warnings) = parse_return("a + b * c", false);
+        assert_eq!(grouping(&expr), "((a + b) * c)");
+        assert_eq!(warnings, 1);
+
+        for same in [
+            "a * b + c",
+            "a + b - c",
+            "(a + b) * c",
+            "a + (b * c)",
+            "a < b & c",
+        ] {
+            assert_eq!(parse_return(same, false).1, 0, "{}", same);
+        }
+    }
+
+    #[test]
+    fn same_grouping_compares_nesting() {
+        let add_mul = [BinaryOp::Add, BinaryOp::Mul];
+        let ltr = left_to_right(operands(3), &add_mul);
+        let prec = by_precedence(operands(3), &add_mul);
+        assert!(!same_grouping(&ltr, &prec));
+        assert!(same_grouping(&ltr, &ltr));
+
+        let mul_add = [BinaryOp::Mul, BinaryOp::Add];
+        assert!(same_grouping(
+            &left_to_right(operands(3), &mul_add),
+            &by_precedence(operands(3), &mul_add)
+        ));
+
+        // same shape, different operands
+        let mut moved = operands(3);
+        moved.swap(0, 1);
+        assert!(!same_grouping(
+            &left_to_right(moved, &mul_add),
+            &left_to_right(operands(3), &mul_add)
+        ));
+    }
+}
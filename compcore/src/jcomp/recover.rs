@@ -0,0 +1,479 @@
+use pest::{
+    error::{Error, ErrorVariant, InputLocation},
+    Parser,
+};
+
+use super::compiler::{JackParser, Rule};
+use common::diagnostics::Span;
+
+// syntax errors, as many as possible per file
+// pest stops at the first error, so after each one the statement (or
+// declaration) it is in is blanked out and the source parsed again
+// - a statement runs back to the previous ; { or } and on to the next ;
+//   or, if a block opens first, to the end of that block
+// - blanking keeps newlines so the lines and columns stay right
+// the messages are worked out from the tokens pest was expecting and the
+// keyword the broken statement starts with
+
+// enough for a badly broken file, after that it is mostly noise
+const MAX_ERRORS: usize = 20;
+
+// every syntax error in the source, nothing if it parses
+pub(crate) fn syntax_errors(source: &str) -> Vec<(Span, String)> {
+    recover(source).0
+}
+
+// the source with the broken statements blanked out, for an editor that
+// wants to make what sense it can of a file being typed
+pub fn repair(source: &str) -> String {
+    recover(source).1
+}
+
+fn recover(source: &str) -> (Vec<(Span, String)>, String) {
+    if JackParser::parse(Rule::class_file, source).is_ok() {
+        return (Vec::new(), source.to_string());
+    }
+    // the expected tokens are only tracked when asked for
+    pest::set_error_detail(true);
+    let mut text = source.to_string();
+    let mut errors = Vec::new();
+    while errors.len() < MAX_ERRORS {
+        let err = match JackParser::parse(Rule::class_file, &text) {
+            Ok(_) => break,
+            Err(err) => err,
+        };
+        // the furthest pest got, the error itself can be back at the
+        // start of the declaration that failed
+        let pos = match (err.parse_attempts(), &err.location) {
+            (Some(attempts), _) => attempts.max_position,
+            (None, InputLocation::Pos(pos)) => *pos,
+            (None, InputLocation::Span((pos, _))) => *pos,
+        };
+        let (start, mut end) = statement_around(&text, pos);
+        let (span, msg, ended) = describe(&err, &text, pos, start);
+        // with the ; missing the statement ended before pos and what
+        // follows is likely the next one, so only the part before goes
+        if ended {
+            end = end_of_previous(&text, pos, start);
+        }
+        errors.push((span, msg));
+        // nothing to resume from in a broken class header or at the end
+        if start == 0 || text[pos..].trim().is_empty() {
+            break;
+        }
+        if !blank(&mut text, start, end) && !blank_token(&mut text, pos) {
+            break;
+        }
+    }
+    (errors, text)
+}
+
+// the ; { } that are not in comments, literals or the ( ) of a for
+fn structure(text: &str) -> Vec<(usize, u8)> {
+    let bytes = text.as_bytes();
+    let mut found = Vec::new();
+    // depth inside the header of a for
+    let mut parens = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'(' if parens > 0 || ends_with_word(&text[..i], "for") => parens += 1,
+            b')' if parens > 0 => parens -= 1,
+            b';' if parens > 0 => {}
+            c @ (b';' | b'{' | b'}') => {
+                // an unclosed header does not reach past a brace
+                if c != b';' {
+                    parens = 0;
+                }
+                found.push((i, c));
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    found
+}
+
+fn ends_with_word(text: &str, word: &str) -> bool {
+    let text = text.trim_end();
+    text.strip_suffix(word)
+        .is_some_and(|rest| !rest.ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_'))
+}
+
+// start and end of the statement or declaration pos is in
+fn statement_around(text: &str, pos: usize) -> (usize, usize) {
+    let marks = structure(text);
+    let start = marks
+        .iter()
+        .rev()
+        .find(|(i, _)| *i < pos)
+        .map_or(0, |(i, _)| i + 1);
+    let mut end = text.len();
+    let mut depth = 0;
+    for &(i, c) in marks.iter().filter(|(i, _)| *i >= pos) {
+        match c {
+            b'{' => depth += 1,
+            b'}' if depth == 0 => {
+                end = i;
+                break;
+            }
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = i + 1;
+                    break;
+                }
+            }
+            _ if depth == 0 => {
+                end = i + 1;
+                break;
+            }
+            _ => {}
+        }
+    }
+    (start, end)
+}
+
+// spaces over start..end, false if there was nothing there to remove
+fn blank(text: &mut String, start: usize, end: usize) -> bool {
+    if text[start..end].trim().is_empty() {
+        return false;
+    }
+    let blanked: String = text[start..end]
+        .chars()
+        .map(|c| match c {
+            '\n' | '\r' => c.to_string(),
+            _ => " ".repeat(c.len_utf8()),
+        })
+        .collect();
+    text.replace_range(start..end, &blanked);
+    true
+}
+fn blank_token(text: &mut String, pos: usize) -> bool {
+    let end = pos + token_at(text, pos).len();
+    blank(text, pos, end)
+}
+
+// the word or single character at pos
+fn token_at(text: &str, pos: usize) -> &str {
+    let rest = &text[pos..];
+    let word = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    match rest.chars().next() {
+        Some(_) if word > 0 => &rest[..word],
+        Some(c) => &rest[..c.len_utf8()],
+        None => "",
+    }
+}
+
+// the first word of the statement, skipping comments
+fn first_word(text: &str, start: usize) -> &str {
+    let mut rest = &text[start..];
+    loop {
+        rest = rest.trim_start();
+        if let Some(comment) = rest.strip_prefix("//") {
+            rest = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+        } else {
+            break;
+        }
+    }
+    let end = rest
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len());
+    &rest[..end]
+}
+
+// what the statement is, for the messages
+fn context(word: &str) -> &'static str {
+    match word {
+        "let" => "let statement",
+        "do" => "do statement",
+        "return" => "return statement",
+        "if" | "else" => "if statement",
+        "while" => "while loop",
+        "for" => "for loop",
+        "switch" | "case" | "default" => "switch statement",
+        "break" => "break",
+        "continue" => "continue",
+        "var" => "variable declaration",
+        "field" | "static" => "class variable declaration",
+        "const" => "constant declaration",
+        "enum" => "enum declaration",
+        "function" | "method" | "constructor" => "subroutine declaration",
+        "class" => "class declaration",
+        _ => "statement",
+    }
+}
+
+fn line_col(text: &str, pos: usize) -> (usize, usize) {
+    let before = &text[..pos];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, col)
+}
+
+// just after the last token before pos, where a missing ; belongs
+fn end_of_previous(text: &str, pos: usize, start: usize) -> usize {
+    let trimmed = text[start..pos].trim_end();
+    if trimmed.is_empty() {
+        pos
+    } else {
+        start + trimmed.len()
+    }
+}
+
+const OPERATORS: [&str; 11] = ["+", "-", "*", "/", "&", "|", "<", ">", "=", "[", "."];
+
+// the message, where it points and whether the statement ended before pos
+fn describe(err: &Error<Rule>, text: &str, pos: usize, start: usize) -> (Span, String, bool) {
+    let expected: Vec<String> = err
+        .parse_attempts()
+        .map(|attempts| attempts.expected_tokens())
+        .unwrap_or_default()
+        .iter()
+        .map(|token| token.to_string())
+        // whitespace and comments fit anywhere, character ranges print as
+        // 0..9 and like _ they are parts of names and numbers
+        .filter(|token| !token.trim().is_empty() && !token.contains(".."))
+        .filter(|token| !["/*", "/**", "//", "_", "BUILTIN_RULE"].contains(&token.as_str()))
+        .collect();
+    let rules: Vec<Rule> = match &err.variant {
+        ErrorVariant::ParsingError { positives, .. } => positives.clone(),
+        _ => Vec::new(),
+    };
+    let what = context(first_word(text, start));
+    let found = token_at(text, pos);
+    let unexpected_token = match found {
+        "" => "unexpected end of file".to_string(),
+        "\n" | "\r" => "unexpected end of line".to_string(),
+        _ if found.trim().is_empty() => "unexpected space".to_string(),
+        _ => format!("unexpected `{}`", found),
+    };
+    let expects = |token: &str| expected.iter().any(|t| t == token);
+    let expression = rules.iter().any(|rule| {
+        matches!(
+            rule,
+            Rule::expression
+                | Rule::subroutine_call
+                | Rule::int
+                | Rule::hex_num
+                | Rule::bin_num
+                | Rule::char_lit
+                | Rule::string
+                | Rule::keyword
+                | Rule::unary_op
+        )
+    });
+
+    let at = {
+        let (line, col) = line_col(text, pos);
+        Span::new(line, col, line, col + found.len().max(1))
+    };
+    let previous = end_of_previous(text, pos, start);
+    // whether what comes after looks like the next statement
+    let new_line = text[previous..pos].contains('\n');
+    let next_starts = new_line || found == "}" || context(found) != "statement";
+    let missing = |token: &str| {
+        let (line, col) = line_col(text, previous);
+        // the ;s in the header of a for do not end it
+        let after = token == ";" && what != "for loop";
+        let msg = match after {
+            true => format!("missing `;` after {}", what),
+            false => format!("missing `{}` in {}", token, what),
+        };
+        (
+            Span::new(line, col, line, col + 1),
+            msg,
+            after && next_starts,
+        )
+    };
+    let unexpected = |msg: String| (at, msg, false);
+
+    if expects("class") {
+        return unexpected(format!("{}, expected `class`", unexpected_token));
+    }
+    if found.is_empty() && expects("}") {
+        let (span, _, _) = missing("}");
+        return (
+            span,
+            "missing `}` at the end of the file".to_string(),
+            false,
+        );
+    }
+    // a ; could also have been an expression, but not when the statement
+    // so far is followed by the end of the block or a new line
+    if expects(";") && (!expression || found == "}" || new_line) {
+        if !next_starts {
+            return unexpected(format!("{} in {}, expected `;`", unexpected_token, what));
+        }
+        return missing(";");
+    }
+    if expression {
+        return unexpected(format!(
+            "{}, expected an expression in {}",
+            unexpected_token, what
+        ));
+    }
+    // the punctuation that would have fitted, operators and the ( of a
+    // call can follow almost any name so they are left out
+    let mut punctuation: Vec<&String> = expected
+        .iter()
+        .filter(|token| !OPERATORS.contains(&token.as_str()))
+        .filter(|token| !token.chars().all(|c| c.is_ascii_alphabetic()))
+        .collect();
+    if punctuation.len() > 1 {
+        punctuation.retain(|token| *token != "(");
+    }
+    punctuation.sort();
+    if rules.contains(&Rule::r#type) || expects("int") {
+        return unexpected(format!("{}, expected a type in {}", unexpected_token, what));
+    }
+    if rules.contains(&Rule::identifier) && punctuation.is_empty() {
+        return unexpected(format!("{}, expected a name in {}", unexpected_token, what));
+    }
+    if what == "statement" && expects("let") {
+        return unexpected(format!(
+            "{}, expected a statement (let, do, if, while, for, switch, return, break or continue)",
+            unexpected_token
+        ));
+    }
+    if expects("function") || expects("field") {
+        return unexpected(format!(
+            "{}, expected a subroutine or class variable declaration",
+            unexpected_token
+        ));
+    }
+    match punctuation.as_slice() {
+        [] => unexpected(format!("{} in {}", unexpected_token, what)),
+        [only] => missing(only),
+        list => {
+            let list: Vec<String> = list.iter().map(|token| format!("`{}`", token)).collect();
+            unexpected(format!(
+                "{} in {}, expected {}",
+                unexpected_token,
+                what,
+                list.join(" or ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the source is the body of Main.main, each error as line:col message
+    fn errors_in_main(body: &str) -> Vec<String> {
+        let source = format!(
+            "class Main {{\n    function void main() {{\n{}\n        return;\n    }}\n}}\n",
+            body
+        );
+        errors(&source)
+    }
+    fn errors(source: &str) -> Vec<String> {
+        syntax_errors(source)
+            .into_iter()
+            .map(|(span, msg)| format!("{}:{} {}", span.line, span.col, msg))
+            .collect()
+    }
+
+    #[test]
+    fn a_clean_file_has_no_errors() {
+        assert!(errors_in_main("        let x = 1;").is_empty());
+        assert_eq!(repair("class Main {}"), "class Main {}");
+    }
+
+    #[test]
+    fn statement_messages() {
+        assert_eq!(
+            errors_in_main("        let x = 1"),
+            ["3:18 missing `;` after let statement"]
+        );
+        assert_eq!(
+            errors_in_main("        let x = ;"),
+            ["3:17 unexpected `;`, expected an expression in let statement"]
+        );
+        assert_eq!(
+            errors_in_main("        do Output.printInt(1;"),
+            ["3:29 unexpected `;` in do statement, expected `)` or `,`"]
+        );
+        assert_eq!(
+            errors_in_main("        retrun;"),
+            ["3:9 unexpected `retrun`, expected a statement (let, do, if, while, for, switch, return, break or continue)"]
+        );
+        assert_eq!(
+            errors_in_main("        if (x {\n        }"),
+            ["3:14 missing `)` in if statement"]
+        );
+        assert_eq!(
+            errors_in_main("        var 3 x;"),
+            ["3:13 unexpected `3`, expected a type in variable declaration"]
+        );
+    }
+
+    #[test]
+    fn declaration_messages() {
+        assert_eq!(
+            errors("clas Main {\n}\n"),
+            ["1:1 unexpected `clas`, expected `class`"]
+        );
+        assert_eq!(
+            errors("class Main {\n    function main() {\n        return;\n    }\n}\n"),
+            ["2:18 unexpected `(`, expected a name in subroutine declaration"]
+        );
+        assert_eq!(
+            errors("class Main {\n    var int x;\n}\n"),
+            ["2:5 unexpected `var`, expected a subroutine or class variable declaration"]
+        );
+        assert_eq!(
+            errors("class Main {\n    function void main() {\n        return;\n    }\n"),
+            ["5:1 missing `}` at the end of the file"]
+        );
+    }
+
+    #[test]
+    fn every_broken_statement_is_reported() {
+        assert_eq!(
+            errors_in_main("        let x = 1\n        let y = 2\n        let z = ;"),
+            [
+                "3:18 missing `;` after let statement",
+                "4:18 missing `;` after let statement",
+                "5:17 unexpected `;`, expected an expression in let statement",
+            ]
+        );
+    }
+
+    #[test]
+    fn repair_blanks_broken_statements_in_place() {
+        let source = "class Main {\n    function void main() {\n        let x = ;\n        return;\n    }\n}\n";
+        let repaired = repair(source);
+        assert_eq!(repaired.len(), source.len());
+        assert_eq!(repaired.lines().count(), source.lines().count());
+        assert!(!repaired.contains("let"));
+        assert!(JackParser::parse(Rule::class_file, &repaired).is_ok());
+    }
+}
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ast::{BinaryOp, Expression},
+    compiler::Compiler,
+    fold::const_value,
+};
+
+// strength reduction, multiply and divide by constants without
+// calling Math.multiply / Math.divide
+//
+// x * c doubles and adds: pop the operand into temp 6, then walk the
+// bits of c from the top, doubling the running total (via temp 7)
+// and adding temp 6 for every set bit
+//
+// x / 2^k is a shift right, the hack cpu has none so it is a loop that
+// copies bits k..15 of |x| down to 0..15-k, then puts the sign back.
+// working on the magnitude keeps Math.divide's round towards zero
+// (-7 / 2 is -3, a plain shift would give -4)
+//
+// temps 4 to 7 are only live inside these sequences, the operand has
+// already been evaluated so nested expressions cannot clobber them
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OptimizeFor {
+    // only inline multiplies that are about as small as the call
+    Size,
+    // inline every multiply by a constant and shift loops for divides
+    Speed,
+}
+
+// doublings and additions a size build is prepared to inline
+const SIZE_MUL_STEPS: u32 = 2;
+
+impl Compiler<'_> {
+    // emits the reduced form and returns true, or leaves it to the caller
+    pub(crate) fn strength_reduce(
+        &mut self,
+        op: BinaryOp,
+        lhs: &Expression,
+        rhs: &Expression,
+    ) -> bool {
+        if self.reference {
+            return false;
+        }
+        match op {
+            BinaryOp::Mul => {
+                let (operand, c) = match (const_value(lhs), const_value(rhs)) {
+                    (_, Some(c)) => (lhs, c),
+                    (Some(c), None) => (rhs, c),
+                    (None, None) => return false,
+                };
+                if !self.mul_worth_inlining(c) {
+                    return false;
+                }
+                self.do_expr(operand);
+                self.mul_const(c);
+                true
+            }
+            BinaryOp::Div => {
+                let Some(c) = const_value(rhs) else {
+                    return false;
+                };
+                let magnitude = c.unsigned_abs();
+                if self.optimize_for != OptimizeFor::Speed
+                    || c == i16::MIN
+                    || magnitude < 2
+                    || !magnitude.is_power_of_two()
+                {
+                    return false;
+                }
+                self.do_expr(lhs);
+                self.shift_right(magnitude.trailing_zeros());
+                if c < 0 {
+                    self.write("neg");
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn mul_steps(c: i16) -> u32 {
+        let bits = if c == i16::MIN {
+            0x8000
+        } else {
+            c.unsigned_abs()
+        };
+        // one doubling per bit below the top one, one add per extra set bit
+        (15 - bits.leading_zeros()) + bits.count_ones() - 1
+    }
+    fn mul_worth_inlining(&self, c: i16) -> bool {
+        if c == 0 {
+            // only reached when the operand has side effects
+            return false;
+        }
+        match self.optimize_for {
+            OptimizeFor::Speed => true,
+            OptimizeFor::Size => Self::mul_steps(c) <= SIZE_MUL_STEPS,
+        }
+    }
+
+    // operand is on the stack, leaves operand * c
+    fn mul_const(&mut self, c: i16) {
+        let bits = if c == i16::MIN {
+            0x8000
+        } else {
+            c.unsigned_abs()
+        };
+        let top = 15 - bits.leading_zeros();
+        self.write("pop temp 6");
+        self.write("push temp 6");
+        for bit in (0..top).rev() {
+            self.write("pop temp 7");
+            self.write("push temp 7");
+            self.write("push temp 7");
+            self.write("add");
+            if bits & (1 << bit) != 0 {
+                self.write("push temp 6");
+                self.write("add");
+            }
+        }
+        if c < 0 && c != i16::MIN {
+            self.write("neg");
+        }
+    }
+
+    // operand is on the stack, leaves operand / 2^k rounded towards zero
+    fn shift_right(&mut self, k: u32) {
+        let id = self.next_label("SHR");
+        // temp 4 sign, temp 5 magnitude, temp 6 source bit, temp 7 result bit
+        self.write("pop temp 5");
+        self.write("push temp 5");
+        self.write("push constant 0");
+        self.write("lt");
+        self.write("pop temp 4");
+        self.write("push temp 4");
+        self.write(&format!("if-goto SHRNEG{}", id));
+        self.write(&format!("goto SHRABS{}", id));
+        self.write(&format!("label SHRNEG{}", id));
+        self.write("push temp 5");
+        self.write("neg");
+        self.write("pop temp 5");
+        self.write(&format!("label SHRABS{}", id));
+        self.write("push constant 0");
+        self.push_constant(1 << k);
+        self.write("pop temp 6");
+        self.write("push constant 1");
+        self.write("pop temp 7");
+        self.write(&format!("label SHRLOOP{}", id));
+        self.write("push temp 5");
+        self.write("push temp 6");
+        self.write("and");
+        self.write("push constant 0");
+        self.write("eq");
+        self.write(&format!("if-goto SHRSKIP{}", id));
+        self.write("push temp 7");
+        self.write("add");
+        self.write(&format!("label SHRSKIP{}", id));
+        self.write("push temp 7");
+        self.write("push temp 7");
+        self.write("add");
+        self.write("pop temp 7");
+        self.write("push temp 6");
+        self.write("push temp 6");
+        self.write("add");
+        self.write("pop temp 6");
+        // the source bit falls off the top after bit 15
+        self.write("push temp 6");
+        self.write(&format!("if-goto SHRLOOP{}", id));
+        self.write("push temp 4");
+        self.write(&format!("if-goto SHRFIX{}", id));
+        self.write(&format!("goto SHREND{}", id));
+        self.write(&format!("label SHRFIX{}", id));
+        self.write("neg");
+        self.write(&format!("label SHREND{}", id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use common::{diagnostics::Span, pdb::database::Pdb};
+
+    use super::*;
+    use crate::jcomp::{
+        ast::{ExprKind, Radix, Term},
+        symbols::{VarKind, VarType},
+    };
+
+    fn term(term: Term) -> Expression {
+        Expression {
+            kind: ExprKind::Term(term),
+            span: Span::default(),
+        }
+    }
+
+    // the vm for x op c, with x as argument 0, None when it is left to
+    // Math.multiply or Math.divide
+    fn reduced(optimize_for: OptimizeFor, op: BinaryOp, c: i16) -> Option<Vec<String>> {
+        let mut pdb = Pdb::new();
+        let mut compiler = Compiler::new(false, &mut pdb);
+        compiler.set_optimize_for(optimize_for);
+        compiler
+            .subroutine_symbols
+            .insert("x".to_string(), VarType::Int, VarKind::Argument)
+            .unwrap();
+        let x = term(Term::Var("x".to_string()));
+        let c = term(Term::Int(c as i32, Radix::Decimal));
+        compiler
+            .strength_reduce(op, &x, &c)
+            .then(|| compiler.code.clone())
+    }
+
+    // just enough of the vm to run what strength_reduce writes
+    fn run(code: &[String], x: i16) -> i16 {
+        let labels: HashMap<&str, usize> = code
+            .iter()
+            .enumerate()
+            .filter_map(|(n, line)| line.strip_prefix("label ").map(|label| (label, n)))
+            .collect();
+        let mut stack: Vec<i16> = Vec::new();
+        let mut temp = [0i16; 8];
+        let mut pc = 0;
+        while let Some(line) = code.get(pc) {
+            pc += 1;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                ["push", "argument", "0"] => stack.push(x),
+                ["push", "constant", n] => stack.push(n.parse::<u16>().unwrap() as i16),
+                ["push", "temp", n] => stack.push(temp[n.parse::<usize>().unwrap()]),
+                ["pop", "temp", n] => temp[n.parse::<usize>().unwrap()] = stack.pop().unwrap(),
+                ["neg"] => {
+                    let a = stack.pop().unwrap();
+                    stack.push(a.wrapping_neg());
+                }
+                ["not"] => {
+                    let a = stack.pop().unwrap();
+                    stack.push(!a);
+                }
+                [op @ ("add" | "and" | "eq" | "lt")] => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    stack.push(match op {
+                        "add" => a.wrapping_add(b),
+                        "and" => a & b,
+                        "eq" => -((a == b) as i16),
+                        _ => -((a < b) as i16),
+                    });
+                }
+                ["label", _] => {}
+                ["goto", label] => pc = labels[label],
+                ["if-goto", label] => {
+                    if stack.pop().unwrap() != 0 {
+                        pc = labels[label];
+                    }
+                }
+                _ => panic!("unexpected vm line {}", line),
+            }
+        }
+        assert_eq!(stack.len(), 1, "leaves just the result");
+        stack[0]
+    }
+
+    const XS: [i16; 11] = [0, 1, -1, 7, -7, 100, -100, 12345, 32767, -32767, -32768];
+
+    #[test]
+    fn multiplies_by_constants() {
+        for c in [2, 3, 5, 10, 255, 1024, 32767, -1, -2, -3, -7, -32768] {
+            let code = reduced(OptimizeFor::Speed, BinaryOp::Mul, c).expect("inlined");
+            for x in XS {
+                assert_eq!(run(&code, x), x.wrapping_mul(c), "{} * {}", x, c);
+            }
+        }
+    }
+
+    #[test]
+    fn size_builds_only_inline_short_multiplies() {
+        // at most two doublings and additions
+        for c in [2, 3, 4, -2, -3, -4] {
+            assert!(
+                reduced(OptimizeFor::Size, BinaryOp::Mul, c).is_some(),
+                "{}",
+                c
+            );
+        }
+        for c in [5, 6, 7, 10, 255, -7, -32768] {
+            assert!(
+                reduced(OptimizeFor::Size, BinaryOp::Mul, c).is_none(),
+                "{}",
+                c
+            );
+        }
+        // x * 0 is only left for folding to miss when x has side effects
+        assert!(reduced(OptimizeFor::Speed, BinaryOp::Mul, 0).is_none());
+    }
+
+    #[test]
+    fn divides_by_powers_of_two_towards_zero() {
+        for c in [2, 4, 8, 256, 16384, -2, -4, -16384] {
+            let code = reduced(OptimizeFor::Speed, BinaryOp::Div, c).expect("inlined");
+            for x in XS {
+                // Math.divide rounds towards zero, as i32 division does
+                let expected = (x as i32 / c as i32) as i16;
+                assert_eq!(run(&code, x), expected, "{} / {}", x, c);
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_other_divides_to_math_divide() {
+        for c in [0, 1, -1, 3, 6, -6, -32768] {
+            assert!(
+                reduced(OptimizeFor::Speed, BinaryOp::Div, c).is_none(),
+                "{}",
+                c
+            );
+        }
+        assert!(reduced(OptimizeFor::Size, BinaryOp::Div, 2).is_none());
+    }
+}
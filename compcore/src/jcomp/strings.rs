@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use super::{ast::Term, charset::decode, consts::substitute_statements, parse::parse_class};
+
+// project wide pool of string literals, preinitialised in RAM
+// each literal is laid out as a complete String object
+//   addr+0  maxLen
+//   addr+1  pointer to the characters (addr+3)
+//   addr+2  length
+//   addr+3  the characters
+// so a literal compiles to a single push constant instead of
+// String.new plus one String.appendChar per character
+//
+// the pool sits at the top of the stack segment and grows down
+// from 2047, it never gets below POOL_FLOOR so the stack keeps
+// at least 768 words. the os puts the heap at 2048 so the pool cannot
+// go there. every class is added before any is compiled, so floor() is
+// final while code is generated. every subroutine of a pooled build checks
+// the stack on entry and stops with Sys.error 103 before it reaches the pool
+// pooled strings must not be disposed, and the String methods that
+// modify a string in place change the literal for every later use
+
+const POOL_TOP: u16 = 2048;
+const POOL_FLOOR: u16 = 1024;
+
+#[derive(Debug)]
+pub struct StringPool {
+    next: u16,
+    lookup: HashMap<Vec<i32>, u16>,
+}
+
+impl Default for StringPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StringPool {
+    pub fn new() -> Self {
+        Self {
+            next: POOL_TOP,
+            lookup: HashMap::new(),
+        }
+    }
+    // address of the String object for these characters
+    // None once the pool is full
+    pub fn intern(&mut self, codes: &[i32]) -> Option<u16> {
+        if let Some(addr) = self.lookup.get(codes) {
+            return Some(*addr);
+        }
+        let size = codes.len() as u16 + 3;
+        if self.next - POOL_FLOOR < size {
+            return None;
+        }
+        self.next -= size;
+        self.lookup.insert(codes.to_vec(), self.next);
+        Some(self.next)
+    }
+    // every literal in a jack class, in the order the compiler meets them
+    // parse errors are ignored here, the compiler reports them
+    pub fn add_class(&mut self, source: &str) {
+        let Some(mut class) = parse_class(source) else {
+            return;
+        };
+        for subroutine in &mut class.subroutines {
+            substitute_statements(&mut subroutine.body, &mut |term| {
+                if let Term::Str(raw) = term {
+                    if let Ok(codes) = decode(raw) {
+                        self.intern(&codes);
+                    }
+                }
+                None
+            });
+        }
+    }
+    // the lowest address the pool uses, POOL_TOP while it is empty
+    pub fn floor(&self) -> u16 {
+        self.next
+    }
+    pub fn is_empty(&self) -> bool {
+        self.lookup.is_empty()
+    }
+    // start address and contents of the pool, ready for the assembler
+    pub fn data(&self) -> (u16, Vec<u16>) {
+        let mut words = vec![0; (POOL_TOP - self.next) as usize];
+        for (codes, addr) in &self.lookup {
+            let offset = (addr - self.next) as usize;
+            let len = codes.len() as u16;
+            words[offset] = len;
+            words[offset + 1] = addr + 3;
+            words[offset + 2] = len;
+            for (i, code) in codes.iter().enumerate() {
+                words[offset + 3 + i] = *code as u16;
+            }
+        }
+        (self.next, words)
+    }
+}
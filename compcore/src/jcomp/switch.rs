@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use super::{
+    ast::{Case, Expression},
+    compiler::{Compiler, LoopLabels},
+    fold::{const_value, fold_in_place},
+};
+
+// switch (x) { case 1: ... case 2, 3: ... default: ... }
+// x is evaluated once into temp 1, then either
+// - a jump table when the case values are dense, x - min indexes a
+//   goto-table whose entries are the case labels (the default for gaps)
+// - a chain of compares otherwise
+// each case ends with a goto to the end, there is no fall through
+
+// fewer values than this are always a compare chain
+const MIN_TABLE_CASES: usize = 4;
+// and a table may be at most this many times bigger than the values
+const MAX_TABLE_SPREAD: usize = 2;
+
+impl Compiler<'_> {
+    pub(crate) fn do_switch(
+        &mut self,
+        scrutinee: &Expression,
+        cases: &[Case],
+        default: Option<&Case>,
+    ) {
+        let id = self.next_label("SWITCH");
+        let end_label = format!("SWITCH_END{}", id);
+        let default_label = match default {
+            Some(_) => format!("DEFAULT{}", id),
+            None => end_label.clone(),
+        };
+        let Some(values) = self.case_values(cases) else {
+            return;
+        };
+
+        self.do_expr(scrutinee);
+        self.write("pop temp 1");
+        let min = values.keys().min().copied().unwrap_or(0) as i32;
+        let max = values.keys().max().copied().unwrap_or(0) as i32;
+        let spread = (max - min + 1) as usize;
+        if values.len() >= MIN_TABLE_CASES && spread <= values.len() * MAX_TABLE_SPREAD {
+            self.jump_table(id, &values, min, spread, &default_label);
+        } else {
+            let mut ordered: Vec<(&i16, &usize)> = values.iter().collect();
+            // in the order they were written
+            ordered.sort_by_key(|(value, case)| (**case, **value));
+            for (value, case) in ordered {
+                self.write("push temp 1");
+                self.push_constant(*value as i32);
+                self.write("eq");
+                self.write(&format!("if-goto CASE{}_{}", id, case));
+            }
+            self.write(&format!("goto {}", default_label));
+        }
+
+        // break leaves the switch, continue still belongs to the loop
+        let continue_label = self
+            .loops
+            .last()
+            .and_then(|labels| labels.continue_label.clone());
+        self.loops.push(LoopLabels {
+            break_label: end_label.clone(),
+            continue_label,
+        });
+        for (n, case) in cases.iter().enumerate() {
+            self.write(&format!("label CASE{}_{}", id, n));
+            self.do_statments(&case.body);
+            self.write(&format!("goto {}", end_label));
+        }
+        if let Some(default) = default {
+            self.write(&format!("label {}", default_label));
+            self.do_statments(&default.body);
+        }
+        self.loops.pop();
+        self.write(&format!("label {}", end_label));
+    }
+
+    // every case value mapped to the case it belongs to
+    fn case_values(&mut self, cases: &[Case]) -> Option<HashMap<i16, usize>> {
+        let mut values = HashMap::new();
+        let mut ok = true;
+        for (n, case) in cases.iter().enumerate() {
+            for value in &case.values {
+                let mut folded = value.clone();
+                fold_in_place(&mut folded);
+                match const_value(&folded) {
+                    Some(v) => {
+                        if values.insert(v, n).is_some() {
+                            self.diagnostics.error(
+                                &self.file_name,
+                                value.span,
+                                &format!("duplicate case value {}", v),
+                            );
+                            ok = false;
+                        }
+                    }
+                    None => {
+                        self.diagnostics.error(
+                            &self.file_name,
+                            value.span,
+                            "case value is not a constant",
+                        );
+                        ok = false;
+                    }
+                }
+            }
+        }
+        ok.then_some(values)
+    }
+
+    fn jump_table(
+        &mut self,
+        id: usize,
+        values: &HashMap<i16, usize>,
+        min: i32,
+        spread: usize,
+        default_label: &str,
+    ) {
+        // index = x - min, anything outside 0..spread is the default
+        // x - min wraps for far away x but can only land in range when
+        // x really is one of the values
+        // cases starting at 0 index the table with x as it is
+        if min != 0 {
+            self.write("push temp 1");
+            self.push_constant(min);
+            self.write("sub");
+            self.write("pop temp 1");
+        }
+        self.write("push temp 1");
+        self.write("push constant 0");
+        self.write("lt");
+        self.write(&format!("if-goto {}", default_label));
+        self.write("push temp 1");
+        self.push_constant(spread as i32 - 1);
+        self.write("gt");
+        self.write(&format!("if-goto {}", default_label));
+        let labels: Vec<String> = (0..spread)
+            .map(|offset| match values.get(&((min + offset as i32) as i16)) {
+                Some(case) => format!("CASE{}_{}", id, case),
+                None => default_label.to_string(),
+            })
+            .collect();
+        self.write("push temp 1");
+        self.write(&format!("goto-table {}", labels.join(" ")));
+    }
+}
@@ -1,13 +1,34 @@
 use anyhow::Result;
-use std::collections::HashMap;
-#[derive(Debug, PartialEq, Clone)]
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum VarType {
     Int,
     Bool,
     Char,
     Instance(String),
 }
-#[derive(Debug, PartialEq, Clone)]
+impl From<&str> for VarType {
+    fn from(type_str: &str) -> Self {
+        match type_str {
+            "int" => VarType::Int,
+            "char" => VarType::Char,
+            "boolean" => VarType::Bool,
+            _ => VarType::Instance(type_str.to_string()),
+        }
+    }
+}
+impl fmt::Display for VarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarType::Int => write!(f, "int"),
+            VarType::Char => write!(f, "char"),
+            VarType::Bool => write!(f, "boolean"),
+            VarType::Instance(cl) => write!(f, "{}", cl),
+        }
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum VarKind {
     Static,
     Field,
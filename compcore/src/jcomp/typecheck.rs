@@ -0,0 +1,942 @@
+use std::collections::HashMap;
+
+use common::diagnostics::{Diagnostics, Span};
+
+use super::{
+    ast::{
+        BinaryOp, Case, Class, ExprKind, Expression, Ident, Keyword, Statement, StatementKind,
+        Subroutine, SubroutineCall, Term, UnaryOp,
+    },
+    compiler::SubroutineKind,
+    consts::{declarations, path_name, path_target},
+    index::{ClassIndex, SubroutineSig},
+    inherit::SUPER,
+    symbols::{SymbolTable, VarKind, VarType},
+};
+
+// semantic checks run over the syntax tree before any vm code is emitted
+// jack is loosely typed, so
+// - int and char are interchangeable
+// - null can be assigned to any instance
+// - Array is an untyped pointer and matches any instance
+// anything else that looks wrong is either a warning (int used as boolean etc)
+// or an error (String assigned to int, method called on an int etc)
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Known(VarType),
+    Null,
+    Void,
+    Unknown,
+}
+
+#[derive(PartialEq)]
+enum Compat {
+    Ok,
+    Warn,
+    Error,
+}
+
+pub(crate) struct TypeChecker<'a> {
+    file_name: &'a str,
+    diagnostics: &'a mut Diagnostics,
+    index: Option<&'a ClassIndex>,
+    class_name: String,
+    global_symbols: SymbolTable,
+    subroutine_symbols: SymbolTable,
+    subroutines: HashMap<String, SubroutineSig>,
+    constants: HashMap<String, VarType>,
+    subroutine_kind: SubroutineKind,
+    return_type: Option<VarType>,
+    // how many loops deep, continue needs at least one
+    loop_depth: usize,
+    // break also works in a switch
+    switch_depth: usize,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub(crate) fn new(
+        file_name: &'a str,
+        diagnostics: &'a mut Diagnostics,
+        index: Option<&'a ClassIndex>,
+    ) -> Self {
+        Self {
+            file_name,
+            diagnostics,
+            index,
+            class_name: String::new(),
+            global_symbols: SymbolTable::new(),
+            subroutine_symbols: SymbolTable::new(),
+            subroutines: HashMap::new(),
+            constants: HashMap::new(),
+            subroutine_kind: SubroutineKind::None,
+            return_type: None,
+            loop_depth: 0,
+            switch_depth: 0,
+        }
+    }
+
+    pub(crate) fn check(&mut self, class: &Class) {
+        // first pass collects the class level declarations so that
+        // subroutines can call each other regardless of order
+
+        self.class_name = class.name.name.clone();
+        let constants = declarations(class);
+        for decl in &constants {
+            if !matches!(decl.var_type, VarType::Int | VarType::Char | VarType::Bool) {
+                self.error(
+                    decl.span,
+                    &format!("constant `{}` must be int, char or boolean", decl.name),
+                );
+            }
+            if self
+                .constants
+                .insert(decl.name.clone(), decl.var_type.clone())
+                .is_some()
+            {
+                self.error(decl.span, &format!("`{}` is already declared", decl.name));
+            }
+        }
+        if let Some(parent) = &class.parent {
+            self.check_parent(parent);
+        }
+        for var in &class.vars {
+            for name in &var.names {
+                self.declare(name, &var.var_type, var.kind.clone(), true);
+            }
+        }
+        // initialisers can see the class variables, the compiler
+        // reports those as not constant
+        for decl in &constants {
+            let value = self.check_expr(&decl.value);
+            let compat = self.assignable(&decl.var_type, &value);
+            self.report(
+                compat,
+                decl.value.span,
+                &format!(
+                    "cannot assign {} to constant `{}` of type {}",
+                    Self::describe(&value),
+                    decl.name,
+                    decl.var_type
+                ),
+            );
+        }
+        for subroutine in &class.subroutines {
+            let name = &subroutine.name;
+            if self.subroutines.contains_key(&name.name) {
+                self.error(name.span, &format!("`{}` is already declared", name.name));
+            }
+            self.subroutines.insert(
+                name.name.clone(),
+                SubroutineSig::from_subroutine(subroutine),
+            );
+        }
+        for subroutine in &class.subroutines {
+            self.check_override(subroutine);
+        }
+        for subroutine in &class.subroutines {
+            self.check_subroutine(subroutine);
+        }
+    }
+    // the parent has to be a jack class of the project, its fields come
+    // before the class's own
+    fn check_parent(&mut self, parent: &Ident) {
+        let Some(index) = self.index else {
+            self.error(
+                parent.span,
+                &format!(
+                    "cannot extend `{}`, the whole project has to be compiled to use inheritance",
+                    parent.name
+                ),
+            );
+            return;
+        };
+        if !index.has_class(&parent.name) {
+            self.error(parent.span, &format!("cannot find class `{}`", parent.name));
+        } else if !index.is_jack(&parent.name) {
+            self.error(
+                parent.span,
+                &format!(
+                    "cannot extend `{}`, only classes compiled from jack can be extended",
+                    parent.name
+                ),
+            );
+        } else if index.is_library(&parent.name) && !index.is_library(&self.class_name) {
+            self.error(
+                parent.span,
+                &format!(
+                    "cannot extend `{}`, classes of the os library cannot be extended",
+                    parent.name
+                ),
+            );
+        } else if index.in_cycle(&self.class_name) {
+            self.error(
+                parent.span,
+                &format!("class `{}` extends itself", self.class_name),
+            );
+        } else {
+            for (name, var_type) in index.inherited_fields(&self.class_name) {
+                let name = Ident {
+                    name,
+                    span: parent.span,
+                };
+                self.declare(&name, &var_type, VarKind::Field, true);
+            }
+        }
+    }
+    // a method replacing one of the parent's has to be called the same way
+    fn check_override(&mut self, subroutine: &Subroutine) {
+        let name = &subroutine.name;
+        let Some(inherited) = self.inherited(&name.name) else {
+            return;
+        };
+        if subroutine.kind != SubroutineKind::Method {
+            self.error(
+                name.span,
+                &format!(
+                    "`{}` hides an inherited method, only a method can override it",
+                    name.name
+                ),
+            );
+            return;
+        }
+        let sig = SubroutineSig::from_subroutine(subroutine);
+        if sig.params != inherited.params || sig.return_type != inherited.return_type {
+            self.error(
+                name.span,
+                &format!(
+                    "`{}` overrides an inherited method with a different signature",
+                    name.name
+                ),
+            );
+        }
+    }
+    // a method of the parent classes that this one can call as its own
+    fn inherited(&self, name: &str) -> Option<SubroutineSig> {
+        self.index?.inherited(&self.class_name, name).cloned()
+    }
+
+    fn error(&mut self, span: Span, msg: &str) {
+        self.diagnostics.error(self.file_name, span, msg);
+    }
+    fn warning(&mut self, span: Span, msg: &str) {
+        self.diagnostics.warning(self.file_name, span, msg);
+    }
+    fn report(&mut self, compat: Compat, span: Span, msg: &str) {
+        match compat {
+            Compat::Ok => {}
+            Compat::Warn => self.warning(span, msg),
+            Compat::Error => self.error(span, msg),
+        }
+    }
+
+    fn declare(&mut self, name: &Ident, vtype: &VarType, kind: VarKind, global: bool) {
+        if global && self.constants.contains_key(&name.name) {
+            self.error(name.span, &format!("`{}` is already declared", name.name));
+            return;
+        }
+        let table = if global {
+            &mut self.global_symbols
+        } else {
+            &mut self.subroutine_symbols
+        };
+        if table
+            .insert(name.name.clone(), vtype.clone(), kind)
+            .is_err()
+        {
+            self.error(name.span, &format!("`{}` is already declared", name.name));
+        }
+    }
+    fn check_subroutine(&mut self, subroutine: &Subroutine) {
+        self.subroutine_symbols = SymbolTable::new();
+        self.subroutine_kind = subroutine.kind;
+        self.return_type = subroutine.return_type.clone();
+
+        if self.subroutine_kind == SubroutineKind::Method {
+            self.declare(
+                &Ident {
+                    name: "this".to_string(),
+                    span: subroutine.span,
+                },
+                &VarType::Instance(self.class_name.clone()),
+                VarKind::Argument,
+                false,
+            );
+        }
+        for param in &subroutine.params {
+            self.declare(&param.name, &param.var_type, VarKind::Argument, false);
+        }
+        for var_dec in &subroutine.locals {
+            for name in &var_dec.names {
+                self.declare(name, &var_dec.var_type, VarKind::Local, false);
+            }
+        }
+        self.check_statements(&subroutine.body);
+    }
+
+    fn lookup(&self, name: &str) -> Option<VarType> {
+        self.subroutine_symbols
+            .get(name)
+            .or_else(|| self.global_symbols.get(name))
+            .map(|symbol| symbol.var_type.clone())
+    }
+
+    // a variable the code uses, a function has no this to reach fields by
+    fn variable(&mut self, name: &Ident) -> Option<VarType> {
+        if self.subroutine_kind == SubroutineKind::Function
+            && self.subroutine_symbols.get(&name.name).is_none()
+            && self
+                .global_symbols
+                .get(&name.name)
+                .is_some_and(|symbol| symbol.var_kind == VarKind::Field)
+        {
+            self.error(
+                name.span,
+                &format!("field `{}` used in a function", name.name),
+            );
+        }
+        self.lookup(&name.name)
+    }
+
+    fn check_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::Let {
+                    target,
+                    index,
+                    value,
+                } => self.check_let(target, index.as_ref(), value),
+                StatementKind::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    self.check_condition(condition);
+                    self.check_statements(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.check_statements(else_branch);
+                    }
+                }
+                StatementKind::While { condition, body } => {
+                    self.check_condition(condition);
+                    self.check_loop_body(body);
+                }
+                StatementKind::For {
+                    init,
+                    condition,
+                    step,
+                    body,
+                } => {
+                    for part in [init, step].into_iter().flatten() {
+                        self.check_statements(std::slice::from_ref(part));
+                    }
+                    if let Some(condition) = condition {
+                        self.check_condition(condition);
+                    }
+                    self.check_loop_body(body);
+                }
+                StatementKind::Switch {
+                    scrutinee,
+                    cases,
+                    default,
+                } => self.check_switch(scrutinee, cases, default.as_ref()),
+                StatementKind::Break if self.switch_depth + self.loop_depth == 0 => {
+                    self.error(statement.span, "`break` outside of a loop or switch");
+                }
+                StatementKind::Continue if self.loop_depth == 0 => {
+                    self.error(statement.span, "`continue` outside of a loop");
+                }
+                StatementKind::Break | StatementKind::Continue => {}
+                StatementKind::Do(call) => {
+                    self.check_subcall(call);
+                }
+                StatementKind::Return(expr) => self.check_return(statement.span, expr.as_ref()),
+            }
+        }
+    }
+    fn check_loop_body(&mut self, body: &[Statement]) {
+        self.loop_depth += 1;
+        self.check_statements(body);
+        self.loop_depth -= 1;
+    }
+    fn check_switch(&mut self, scrutinee: &Expression, cases: &[Case], default: Option<&Case>) {
+        // the values must be constants, the compiler checks that once
+        // they have been worked out
+        let scrutinee_type = self.check_expr(scrutinee);
+        if !Self::is_numeric(&scrutinee_type) {
+            self.error(
+                scrutinee.span,
+                &format!(
+                    "switch on {}, expected int or char",
+                    Self::describe(&scrutinee_type)
+                ),
+            );
+        }
+        self.switch_depth += 1;
+        for case in cases.iter().chain(default) {
+            for value in &case.values {
+                let value_type = self.check_expr(value);
+                if !Self::is_numeric(&value_type) {
+                    self.error(
+                        value.span,
+                        &format!(
+                            "case value is {}, expected int or char",
+                            Self::describe(&value_type)
+                        ),
+                    );
+                }
+            }
+            self.check_statements(&case.body);
+        }
+        self.switch_depth -= 1;
+    }
+    fn check_let(&mut self, target: &Ident, index: Option<&Expression>, expr: &Expression) {
+        let value = self.check_expr(expr);
+
+        if let Some(index) = index {
+            // array elements are untyped
+            self.check_array_var(target, index);
+        } else if let Some(target_type) = self.variable(target) {
+            let compat = self.assignable(&target_type, &value);
+            self.report(
+                compat,
+                expr.span,
+                &format!(
+                    "cannot assign {} to variable `{}` of type {}",
+                    Self::describe(&value),
+                    target.name,
+                    target_type
+                ),
+            );
+        } else if self.constants.contains_key(&target.name) {
+            self.error(
+                target.span,
+                &format!("cannot assign to constant `{}`", target.name),
+            );
+        } else {
+            self.error(
+                target.span,
+                &format!("cannot find variable `{}`", target.name),
+            );
+        }
+    }
+    fn check_return(&mut self, span: Span, expr: Option<&Expression>) {
+        match (self.return_type.clone(), expr) {
+            (None, Some(expr)) => {
+                self.error(expr.span, "void subroutine cannot return a value");
+            }
+            (Some(target), None) => {
+                self.error(span, &format!("missing return value, expected {}", target));
+            }
+            (Some(target), Some(expr)) => {
+                let value = self.check_expr(expr);
+                let compat = self.assignable(&target, &value);
+                self.report(
+                    compat,
+                    expr.span,
+                    &format!(
+                        "cannot return {} from subroutine returning {}",
+                        Self::describe(&value),
+                        target
+                    ),
+                );
+            }
+            (None, None) => {}
+        }
+    }
+    fn check_condition(&mut self, expr: &Expression) {
+        let value = self.check_expr(expr);
+        let compat = match &value {
+            Type::Known(VarType::Bool) | Type::Unknown => Compat::Ok,
+            Type::Known(VarType::Int) | Type::Known(VarType::Char) => Compat::Warn,
+            _ => Compat::Error,
+        };
+        self.report(
+            compat,
+            expr.span,
+            &format!("condition is {}, expected boolean", Self::describe(&value)),
+        );
+    }
+
+    fn check_subcall(&mut self, call: &SubroutineCall) -> Type {
+        let arg_types: Vec<Type> = call.args.iter().map(|a| self.check_expr(a)).collect();
+        let name = &call.name.name;
+
+        match &call.target {
+            None => {
+                let own = self.subroutines.get(name).cloned();
+                let Some(sig) = own.or_else(|| self.inherited(name)) else {
+                    self.error(
+                        call.name.span,
+                        &format!("no subroutine `{}` in class {}", name, self.class_name),
+                    );
+                    return Type::Unknown;
+                };
+                let params = sig.params.clone();
+                let return_type = sig.return_type.clone();
+                if sig.kind != SubroutineKind::Method {
+                    self.error(
+                        call.name.span,
+                        &format!(
+                            "`{}` is not a method, call it as {}.{}",
+                            name, self.class_name, name
+                        ),
+                    );
+                } else if self.subroutine_kind == SubroutineKind::Function {
+                    self.error(
+                        call.name.span,
+                        &format!("cannot call method `{}` from a function", name),
+                    );
+                }
+                self.check_args(call, &params, &arg_types);
+                return_type.map_or(Type::Void, Type::Known)
+            }
+            Some(left) if left.name == SUPER && self.lookup(SUPER).is_none() => {
+                self.check_super(call, &arg_types);
+                Type::Void
+            }
+            Some(left) => match self.variable(left) {
+                Some(VarType::Instance(cl)) => self.check_call(call, &cl, true, &arg_types),
+                Some(vtype) => {
+                    self.error(
+                        Self::callee_span(call),
+                        &format!(
+                            "cannot call method `{}` on `{}` of type {}",
+                            name, left.name, vtype
+                        ),
+                    );
+                    Type::Unknown
+                }
+                None => self.check_call(call, &left.name, false, &arg_types),
+            },
+        }
+    }
+    // super.new(...) runs one of the parent's constructors on this
+    fn check_super(&mut self, call: &SubroutineCall, arg_types: &[Type]) {
+        let span = Self::callee_span(call);
+        let name = &call.name.name;
+        if self.subroutine_kind != SubroutineKind::Constructor {
+            self.error(span, "super can only be called from a constructor");
+            return;
+        }
+        let index = self.index;
+        let Some(parent) = index.and_then(|index| index.parent(&self.class_name)) else {
+            self.error(
+                span,
+                &format!("class {} does not extend another class", self.class_name),
+            );
+            return;
+        };
+        match index.and_then(|index| index.get(parent, name)) {
+            Some(Some(sig)) if sig.kind == SubroutineKind::Constructor => {
+                let params = sig.params.clone();
+                self.check_args(call, &params, arg_types);
+            }
+            _ => self.error(
+                span,
+                &format!("no constructor `{}` in class {}", name, parent),
+            ),
+        }
+    }
+    // Class.func(...) or obj.func(...) where obj is an instance of class
+    fn check_call(
+        &mut self,
+        call: &SubroutineCall,
+        class: &str,
+        on_instance: bool,
+        arg_types: &[Type],
+    ) -> Type {
+        let func = call.name.name.as_str();
+        let span = Self::callee_span(call);
+        let sig = if class == self.class_name {
+            let own = self.subroutines.get(func).cloned();
+            match own.or_else(|| self.inherited(func)) {
+                Some(sig) => Some(sig),
+                None => {
+                    self.error(
+                        span,
+                        &format!("no subroutine `{}` in class {}", func, class),
+                    );
+                    return Type::Unknown;
+                }
+            }
+        } else if let Some(index) = self.index {
+            if !index.has_class(class) {
+                self.error(span, &format!("cannot find class `{}`", class));
+                return Type::Unknown;
+            }
+            match index.get(class, func) {
+                Some(sig) => sig.cloned(),
+                None => {
+                    self.error(
+                        span,
+                        &format!("no subroutine `{}` in class {}", func, class),
+                    );
+                    return Type::Unknown;
+                }
+            }
+        } else {
+            // compiling a lone class, nothing known about the others
+            None
+        };
+
+        // vm only subroutines have no signature to check against
+        let Some(sig) = sig else {
+            return Type::Unknown;
+        };
+        if on_instance && sig.kind != SubroutineKind::Method {
+            self.error(
+                span,
+                &format!("`{}` is not a method, call it as {}.{}", func, class, func),
+            );
+        } else if !on_instance && sig.kind == SubroutineKind::Method {
+            self.error(span, &format!("method `{}` called as a function", func));
+        }
+        self.check_args(call, &sig.params, arg_types);
+        sig.return_type.map_or(Type::Void, Type::Known)
+    }
+    fn check_args(&mut self, call: &SubroutineCall, params: &[VarType], arg_types: &[Type]) {
+        let callee = Self::callee_name(call);
+        if params.len() != call.args.len() {
+            self.error(
+                Self::callee_span(call),
+                &format!(
+                    "`{}` takes {} argument(s) but {} were supplied",
+                    callee,
+                    params.len(),
+                    call.args.len()
+                ),
+            );
+            return;
+        }
+        for (i, (param, (arg, arg_type))) in params
+            .iter()
+            .zip(call.args.iter().zip(arg_types))
+            .enumerate()
+        {
+            let compat = self.assignable(param, arg_type);
+            self.report(
+                compat,
+                arg.span,
+                &format!(
+                    "argument {} of `{}` expects {}, found {}",
+                    i + 1,
+                    callee,
+                    param,
+                    Self::describe(arg_type)
+                ),
+            );
+        }
+    }
+    fn callee_name(call: &SubroutineCall) -> String {
+        match &call.target {
+            Some(target) => format!("{}.{}", target.name, call.name.name),
+            None => call.name.name.clone(),
+        }
+    }
+    fn callee_span(call: &SubroutineCall) -> Span {
+        match &call.target {
+            Some(target) => Span::new(
+                target.span.line,
+                target.span.col,
+                call.name.span.end_line,
+                call.name.span.end_col,
+            ),
+            None => call.name.span,
+        }
+    }
+    fn check_array_var(&mut self, name: &Ident, index: &Expression) {
+        match self.variable(name) {
+            Some(VarType::Bool) | Some(VarType::Char) => {
+                self.warning(name.span, &format!("`{}` is not an array", name.name));
+            }
+            Some(_) => {}
+            None => self.error(name.span, &format!("cannot find variable `{}`", name.name)),
+        }
+        let index_type = self.check_expr(index);
+        if !Self::is_numeric(&index_type) {
+            self.warning(
+                index.span,
+                &format!(
+                    "array index is {}, expected int",
+                    Self::describe(&index_type)
+                ),
+            );
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expression) -> Type {
+        match &expr.kind {
+            ExprKind::Term(term) => self.check_term(term, expr.span),
+            ExprKind::Unary(op, operand_expr) => {
+                let operand = self.check_expr(operand_expr);
+                match op {
+                    UnaryOp::Neg => {
+                        if !Self::is_numeric(&operand) {
+                            self.warning(
+                                operand_expr.span,
+                                &format!("cannot negate {}", Self::describe(&operand)),
+                            );
+                        }
+                        Type::Known(VarType::Int)
+                    }
+                    UnaryOp::Not => match operand {
+                        Type::Known(VarType::Bool) => operand,
+                        _ => Type::Known(VarType::Int),
+                    },
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let left = self.check_expr(lhs);
+                let right = self.check_expr(rhs);
+                self.check_binary(*op, rhs.span, left, right)
+            }
+        }
+    }
+    fn check_term(&mut self, term: &Term, span: Span) -> Type {
+        match term {
+            Term::Int(..) => Type::Known(VarType::Int),
+            Term::Char(_) => Type::Known(VarType::Char),
+            Term::Str(_) => Type::Known(VarType::Instance("String".to_string())),
+            Term::Keyword(keyword) => match keyword {
+                Keyword::True | Keyword::False => Type::Known(VarType::Bool),
+                Keyword::Null => Type::Null,
+                Keyword::This => {
+                    if self.subroutine_kind == SubroutineKind::Function {
+                        self.error(span, "`this` cannot be used in a function");
+                    }
+                    Type::Known(VarType::Instance(self.class_name.clone()))
+                }
+            },
+            Term::Var(name) => match self
+                .variable(&Ident {
+                    name: name.clone(),
+                    span,
+                })
+                .or_else(|| self.constants.get(name).cloned())
+            {
+                Some(vtype) => Type::Known(vtype),
+                None => {
+                    self.error(span, &format!("cannot find variable `{}`", name));
+                    Type::Unknown
+                }
+            },
+            Term::Index(name, index) => {
+                self.check_array_var(name, index);
+                Type::Unknown
+            }
+            Term::Call(call) => {
+                let call_type = self.check_subcall(call);
+                if call_type == Type::Void {
+                    self.error(span, "void subroutine used in an expression");
+                    return Type::Unknown;
+                }
+                call_type
+            }
+            Term::Paren(inner) => self.check_expr(inner),
+            Term::Path(path) => self.check_path(path, span),
+        }
+    }
+    // Class.MAX, Dir.UP or Class.Dir.UP
+    fn check_path(&mut self, path: &[Ident], span: Span) -> Type {
+        let (class, name) = path_target(&self.class_name, path, &|name| {
+            self.constants.contains_key(name)
+        });
+        let prefix = format!("{}.", class);
+        let vtype = if class == self.class_name
+            || self.constants.keys().any(|name| name.starts_with(&prefix))
+        {
+            self.constants.get(&name).cloned()
+        } else if let Some(index) = self.index {
+            if !index.has_class(&class) {
+                self.error(span, &format!("cannot find class `{}`", class));
+                return Type::Unknown;
+            }
+            index.constant_type(&class, &name)
+        } else {
+            // compiling a lone class, nothing known about the others
+            return Type::Unknown;
+        };
+        match vtype {
+            Some(vtype) => Type::Known(vtype),
+            None => {
+                self.error(span, &format!("cannot find constant `{}`", path_name(path)));
+                Type::Unknown
+            }
+        }
+    }
+    fn check_binary(&mut self, op: BinaryOp, right_span: Span, left: Type, right: Type) -> Type {
+        match op {
+            BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::Lt
+            | BinaryOp::Gt => {
+                for operand in [&left, &right] {
+                    if !Self::is_numeric(operand) && !Self::is_pointer(operand) {
+                        let compat = match operand {
+                            Type::Known(VarType::Bool) => Compat::Warn,
+                            _ => Compat::Error,
+                        };
+                        self.report(
+                            compat,
+                            right_span,
+                            &format!("operator `{}` applied to {}", op, Self::describe(operand)),
+                        );
+                    }
+                }
+                match op {
+                    BinaryOp::Lt | BinaryOp::Gt => Type::Known(VarType::Bool),
+                    _ => Type::Known(VarType::Int),
+                }
+            }
+            BinaryOp::Eq => {
+                // either side can be the subclass
+                let compat = match (&left, &right) {
+                    (Type::Known(vtype), Type::Known(other)) => {
+                        match self.assignable(vtype, &right) {
+                            Compat::Error if self.assignable(other, &left) == Compat::Ok => {
+                                Compat::Ok
+                            }
+                            compat => compat,
+                        }
+                    }
+                    (Type::Known(vtype), _) => self.assignable(vtype, &right),
+                    _ => Compat::Ok,
+                };
+                self.report(
+                    compat,
+                    right_span,
+                    &format!(
+                        "comparing {} with {}",
+                        Self::describe(&left),
+                        Self::describe(&right)
+                    ),
+                );
+                Type::Known(VarType::Bool)
+            }
+            BinaryOp::And | BinaryOp::Or => match (&left, &right) {
+                (Type::Known(VarType::Bool), Type::Known(VarType::Bool)) => {
+                    Type::Known(VarType::Bool)
+                }
+                (Type::Unknown, _) | (_, Type::Unknown) => Type::Unknown,
+                _ => Type::Known(VarType::Int),
+            },
+        }
+    }
+
+    fn is_numeric(t: &Type) -> bool {
+        matches!(
+            t,
+            Type::Known(VarType::Int) | Type::Known(VarType::Char) | Type::Unknown
+        )
+    }
+    fn is_pointer(t: &Type) -> bool {
+        matches!(t, Type::Known(VarType::Instance(cl)) if cl == "Array")
+    }
+    fn assignable(&self, target: &VarType, value: &Type) -> Compat {
+        match (target, value) {
+            (_, Type::Unknown) => Compat::Ok,
+            (_, Type::Void) => Compat::Error,
+            (VarType::Instance(_), Type::Null) => Compat::Ok,
+            (_, Type::Null) => Compat::Warn,
+            (_, Type::Known(vtype)) if vtype == target => Compat::Ok,
+            (VarType::Int | VarType::Char, Type::Known(VarType::Int | VarType::Char)) => Compat::Ok,
+            (VarType::Instance(a), Type::Known(VarType::Instance(b))) => {
+                if a == "Array" || b == "Array" || self.is_subclass(b, a) {
+                    Compat::Ok
+                } else {
+                    Compat::Error
+                }
+            }
+            (VarType::Instance(cl), Type::Known(VarType::Int))
+            | (VarType::Int, Type::Known(VarType::Instance(cl)))
+                if cl == "Array" =>
+            {
+                Compat::Warn
+            }
+            (VarType::Bool, Type::Known(VarType::Int))
+            | (VarType::Int, Type::Known(VarType::Bool)) => Compat::Warn,
+            _ => Compat::Error,
+        }
+    }
+    // a Circle can go where a Shape is expected
+    fn is_subclass(&self, class: &str, ancestor: &str) -> bool {
+        self.index
+            .is_some_and(|index| index.is_subclass(class, ancestor))
+    }
+    fn describe(t: &Type) -> String {
+        match t {
+            Type::Known(vtype) => vtype.to_string(),
+            Type::Null => "null".to_string(),
+            Type::Void => "void".to_string(),
+            Type::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jcomp::parse::AstBuilder;
+
+    // the error messages for a class checked on its own
+    fn errors(source: &str) -> Vec<String> {
+        let class = AstBuilder::new("Main.jack", false)
+            .parse_class(source)
+            .expect("test source parses");
+        let mut diagnostics = Diagnostics::new();
+        TypeChecker::new("Main.jack", &mut diagnostics, None).check(&class);
+        diagnostics
+            .items
+            .iter()
+            .filter(|diag| diag.severity == common::diagnostics::Severity::Error)
+            .map(|diag| diag.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn fields_are_not_reachable_from_functions() {
+        let source = "class Main {
+    field int x;
+    field Array a;
+    field Main next;
+    function int f() {
+        let x = 1;
+        let a[0] = x;
+        do next.g();
+        return a[1];
+    }
+    method void g() {
+        let x = 2;
+        return;
+    }
+}";
+        assert_eq!(
+            errors(source),
+            [
+                "field `x` used in a function",
+                "field `x` used in a function",
+                "field `a` used in a function",
+                "field `next` used in a function",
+                "field `a` used in a function",
+            ]
+        );
+    }
+
+    #[test]
+    fn locals_and_statics_are_fine_in_functions() {
+        let source = "class Main {
+    static int count;
+    field int x;
+    function int f(int x) {
+        var int y;
+        let y = x;
+        let count = y;
+        return count;
+    }
+}";
+        assert!(errors(source).is_empty());
+    }
+}
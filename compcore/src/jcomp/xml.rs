@@ -0,0 +1,311 @@
+use pest::{
+    iterators::{Pair, Pairs},
+    Parser,
+};
+use std::iter::Peekable;
+
+use super::{
+    charset::decode,
+    compiler::{JackParser, Rule},
+    recover::syntax_errors,
+};
+use common::diagnostics::Diagnostics;
+
+// the nand2tetris project 10 output, XxxT.xml is the token stream and
+// Xxx.xml the parse tree, both in the layout the course's JackAnalyzer
+// writes so they can be diffed with TextComparer
+// it is built straight from the JackParser pairs, keywords and symbols are
+// literals in the grammar so they are picked out of the source between
+// the pairs
+// our extensions get elements of their own (forStatement, enumDec, ...),
+// hex, binary and character literals are integerConstants
+
+const INDENT: &str = "  ";
+
+// (tokens, tree), None if the source does not parse
+pub fn course_xml(
+    source: &str,
+    file_name: &str,
+    diagnostics: &mut Diagnostics,
+) -> Option<(String, String)> {
+    let pairs = match JackParser::parse(Rule::class_file, source) {
+        Ok(pairs) => pairs,
+        Err(_) => {
+            for (span, msg) in syntax_errors(source) {
+                diagnostics.error(file_name, span, &msg);
+            }
+            return None;
+        }
+    };
+    let mut writer = XmlWriter::new(source);
+    writer.open("class");
+    writer.sequence(pairs, 0, source.len());
+    writer.close("class");
+    Some(writer.finish())
+}
+
+struct XmlWriter<'s> {
+    source: &'s str,
+    tokens: Vec<String>,
+    tree: Vec<String>,
+    depth: usize,
+}
+
+impl<'s> XmlWriter<'s> {
+    fn new(source: &'s str) -> Self {
+        Self {
+            source,
+            tokens: Vec::new(),
+            tree: Vec::new(),
+            depth: 0,
+        }
+    }
+    fn finish(self) -> (String, String) {
+        let mut tokens = vec!["<tokens>".to_string()];
+        tokens.extend(self.tokens);
+        tokens.push("</tokens>".to_string());
+        (tokens.join("\n") + "\n", self.tree.join("\n") + "\n")
+    }
+
+    fn open(&mut self, tag: &str) {
+        self.tree
+            .push(format!("{}<{}>", INDENT.repeat(self.depth), tag));
+        self.depth += 1;
+    }
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.tree
+            .push(format!("{}</{}>", INDENT.repeat(self.depth), tag));
+    }
+    fn token(&mut self, kind: &str, text: &str) {
+        let line = format!("<{}> {} </{}>", kind, escape(text), kind);
+        self.tree
+            .push(format!("{}{}", INDENT.repeat(self.depth), line));
+        self.tokens.push(line);
+    }
+
+    // keywords and symbols between two pairs
+    fn lex(&self, from: usize, to: usize) -> Vec<(&'static str, &'s str)> {
+        let text = &self.source[from..to];
+        let mut tokens = Vec::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                rest = &rest[c.len_utf8()..];
+            } else if let Some(comment) = rest.strip_prefix("//") {
+                rest = comment.find('\n').map_or("", |end| &comment[end..]);
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+            } else if c.is_ascii_alphabetic() {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                tokens.push(("keyword", &rest[..end]));
+                rest = &rest[end..];
+            } else {
+                tokens.push(("symbol", &rest[..c.len_utf8()]));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        tokens
+    }
+    fn gap(&mut self, from: usize, to: usize) {
+        for (kind, text) in self.lex(from, to) {
+            self.token(kind, text);
+        }
+    }
+
+    // the pairs in from..to with the literals around them
+    fn sequence(&mut self, pairs: Pairs<Rule>, from: usize, to: usize) {
+        let mut pos = from;
+        for pair in pairs {
+            self.gap(pos, pair.as_span().start());
+            pos = pair.as_span().end();
+            self.node(pair);
+        }
+        self.gap(pos, to);
+    }
+    fn children(&mut self, pair: Pair<Rule>) {
+        let span = pair.as_span();
+        self.sequence(pair.into_inner(), span.start(), span.end());
+    }
+    fn element(&mut self, tag: &str, pair: Pair<Rule>) {
+        self.open(tag);
+        self.children(pair);
+        self.close(tag);
+    }
+
+    fn node(&mut self, pair: Pair<Rule>) {
+        match pair.as_rule() {
+            Rule::identifier => self.token("identifier", pair.as_str()),
+            Rule::dotted_id | Rule::path => {
+                for (n, part) in pair.as_str().split('.').enumerate() {
+                    if n > 0 {
+                        self.token("symbol", ".");
+                    }
+                    self.token("identifier", part);
+                }
+            }
+            Rule::class_var => self.element("classVarDec", pair),
+            Rule::class_const => self.element("classConstDec", pair),
+            Rule::class_enum => self.element("enumDec", pair),
+            Rule::subroutine => self.subroutine(pair),
+            Rule::parameter_list => self.element("parameterList", pair),
+            Rule::variables => self.variables(pair),
+            Rule::statements => self.element("statements", pair),
+            Rule::let_st => self.element("letStatement", pair),
+            Rule::if_st => self.element("ifStatement", pair),
+            Rule::while_st => self.element("whileStatement", pair),
+            Rule::for_st => self.element("forStatement", pair),
+            Rule::switch_st => self.element("switchStatement", pair),
+            Rule::case_clause => self.element("caseClause", pair),
+            Rule::default_clause => self.element("defaultClause", pair),
+            Rule::break_st => self.element("breakStatement", pair),
+            Rule::continue_st => self.element("continueStatement", pair),
+            Rule::do_st => self.element("doStatement", pair),
+            Rule::return_st => self.element("returnStatement", pair),
+            Rule::expression_list => self.element("expressionList", pair),
+            Rule::expression => self.expression(pair),
+            Rule::EOI => {}
+            // types, names, array indexes, calls etc add no element
+            _ => self.children(pair),
+        }
+    }
+
+    // the body has no pair of its own, it starts at the { after the )
+    fn subroutine(&mut self, pair: Pair<Rule>) {
+        self.open("subroutineDec");
+        let span = pair.as_span();
+        let mut pos = span.start();
+        for child in pair.into_inner() {
+            if child.as_rule() == Rule::variables {
+                let mut before = self.lex(pos, child.as_span().start());
+                let brace = before.pop();
+                for (kind, text) in before {
+                    self.token(kind, text);
+                }
+                self.open("subroutineBody");
+                if let Some((kind, text)) = brace {
+                    self.token(kind, text);
+                }
+            } else {
+                self.gap(pos, child.as_span().start());
+            }
+            pos = child.as_span().end();
+            self.node(child);
+        }
+        self.gap(pos, span.end());
+        self.close("subroutineBody");
+        self.close("subroutineDec");
+    }
+
+    // the ; after each var_dec is outside it in the grammar
+    fn variables(&mut self, pair: Pair<Rule>) {
+        let end = pair.as_span().end();
+        let mut inner = pair.into_inner().peekable();
+        while let Some(var_dec) = inner.next() {
+            let next = inner.peek().map_or(end, |next| next.as_span().start());
+            let var_end = var_dec.as_span().end();
+            self.open("varDec");
+            self.children(var_dec);
+            self.gap(var_end, next);
+            self.close("varDec");
+        }
+    }
+
+    // term is silent in the grammar so the terms are put back together
+    // from the pairs between the ops
+    fn expression(&mut self, pair: Pair<Rule>) {
+        let span = pair.as_span();
+        self.open("expression");
+        let mut pos = span.start();
+        let mut inner = pair.into_inner().peekable();
+        while let Some(child) = inner.next() {
+            if child.as_rule() == Rule::op {
+                self.gap(pos, child.as_span().start());
+                pos = child.as_span().end();
+                self.children(child);
+            } else {
+                pos = self.term(child, &mut inner, pos, span.end());
+            }
+        }
+        self.gap(pos, span.end());
+        self.close("expression");
+    }
+    // one term starting at first, returns where it ends
+    fn term(
+        &mut self,
+        first: Pair<Rule>,
+        inner: &mut Peekable<Pairs<Rule>>,
+        pos: usize,
+        expression_end: usize,
+    ) -> usize {
+        self.open("term");
+        // the ( of a parenthesised expression
+        self.gap(pos, first.as_span().start());
+        let mut end = first.as_span().end();
+        match first.as_rule() {
+            Rule::unary_op => {
+                self.children(first);
+                if let Some(operand) = inner.next() {
+                    end = self.term(operand, inner, end, expression_end);
+                }
+            }
+            Rule::expression => {
+                self.expression(first);
+                // and its )
+                let next = inner
+                    .peek()
+                    .map_or(expression_end, |next| next.as_span().start());
+                self.gap(end, next);
+                end = next;
+            }
+            Rule::int => {
+                let text: String = first.as_str().split_whitespace().collect();
+                match text.strip_prefix(['-', '+']) {
+                    // the course has no signed literals, -1 is - applied to 1
+                    Some(digits) => {
+                        self.token("symbol", &text[..1]);
+                        self.open("term");
+                        self.token("integerConstant", digits);
+                        self.close("term");
+                    }
+                    None => self.token("integerConstant", &text),
+                }
+            }
+            Rule::hex_num | Rule::bin_num => {
+                let text = first.as_str();
+                let radix = if first.as_rule() == Rule::hex_num {
+                    16
+                } else {
+                    2
+                };
+                let value = u32::from_str_radix(&text[2..], radix).unwrap_or_default();
+                self.token("integerConstant", &value.to_string());
+            }
+            Rule::char_lit => {
+                let text = first.as_str();
+                let code = decode(&text[1..text.len() - 1])
+                    .ok()
+                    .and_then(|codes| codes.first().copied())
+                    .unwrap_or_default();
+                self.token("integerConstant", &code.to_string());
+            }
+            Rule::string => {
+                let text = first.as_str();
+                self.token("stringConstant", &text[1..text.len() - 1]);
+            }
+            Rule::keyword => self.token("keyword", first.as_str()),
+            _ => self.node(first),
+        }
+        self.close("term");
+        end
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
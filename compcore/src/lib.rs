@@ -1,11 +1,30 @@
 mod constants;
+mod utils;
 pub mod assembler {
     pub mod assemble;
 }
 pub mod jcomp {
+    pub mod ast;
+    pub mod charset;
+    pub mod checks;
     pub mod compiler;
+    pub mod consts;
     pub mod expression;
+    pub mod fold;
+    pub mod format;
+    pub mod index;
+    pub mod inherit;
+    pub mod jackdoc;
+    pub mod lint;
+    pub mod outline;
+    pub mod parse;
+    pub mod recover;
+    pub mod reduce;
+    pub mod strings;
+    pub mod switch;
     pub mod symbols;
+    pub mod typecheck;
+    pub mod xml;
 }
 
 pub mod vcomp {
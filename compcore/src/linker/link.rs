@@ -4,18 +4,23 @@ use std::{
     fs::File,
 };
 
-use anyhow::{bail, Result};
+use anyhow::Result;
+use common::diagnostics::{Diagnostics, Span};
 use std::io::Write;
+use std::path::Path;
 
 pub struct Linker {
     chunks: BTreeMap<String, Chunk>,
     emitted: RefCell<HashSet<String>>,
     verbose: bool,
+    pub diagnostics: RefCell<Diagnostics>,
 }
 struct Chunk {
     name: String,
+    file: String,
     code: Vec<String>,
-    calls: Vec<String>,
+    // called function and the line the call is on
+    calls: Vec<(String, usize)>,
 }
 impl Linker {
     pub fn new(verbose: bool) -> Self {
@@ -23,64 +28,105 @@ impl Linker {
             chunks: BTreeMap::new(),
             emitted: RefCell::new(HashSet::new()),
             verbose,
+            diagnostics: RefCell::new(Diagnostics::new()),
         }
     }
-    pub fn load(&mut self, source: &str) -> Result<()> {
+    pub fn load(&mut self, source: &str, path: &Path) -> Result<()> {
         // incoming file is chopped up into chunks , each chunk is a function
         // for each chunk we record what functions it calls
+        let file = path.display().to_string();
         let lines = source.lines();
         let mut pdb_comment = String::new();
         let mut current_function_name = String::new();
-        for line in lines {
+        // set while skipping the body of a duplicate function
+        let mut skipping = false;
+        for (line_no, line) in lines.enumerate() {
+            let line_no = line_no + 1;
             let mut parts = line.split_whitespace();
             if let Some(command) = parts.next() {
                 match command {
-                    "function" => {
-                        let func_name = parts.next().unwrap().to_string();
+                    "function" | "vtable" => {
+                        let Some(mut func_name) = parts.next().map(|s| s.to_string()) else {
+                            self.error(&file, line_no, &format!("{} without a name", command));
+                            continue;
+                        };
+                        // a vtable is a chunk of its own that keeps its
+                        // methods, it is emitted once an object is made
+                        let mut calls = vec![];
+                        if command == "vtable" {
+                            func_name = format!("vtable {}", func_name);
+                            calls = parts.map(|method| (method.to_string(), line_no)).collect();
+                        }
                         if self.verbose {
                             println!("Function {}", func_name)
                         };
                         // start new chunk
                         current_function_name = func_name.clone();
+                        skipping = false;
                         let mut new_chunk = Chunk {
                             name: func_name.to_string(),
+                            file: file.clone(),
                             code: vec![],
-                            calls: vec![],
+                            calls,
                         };
-                        if pdb_comment.len() > 0 {
+                        if !pdb_comment.is_empty() {
                             new_chunk.code.push(pdb_comment.clone());
                             pdb_comment.clear();
                         }
 
-                        if self.chunks.insert(func_name.clone(), new_chunk).is_some() {
-                            bail!("duplicate function {}", func_name);
+                        if let Some(previous) = self.chunks.get(&func_name) {
+                            let note = format!("previously defined in {}", previous.file);
+                            self.error(
+                                &file,
+                                line_no,
+                                &match command {
+                                    "vtable" => format!("duplicate {}", func_name),
+                                    _ => format!("duplicate function {}", func_name),
+                                },
+                            );
+                            self.diagnostics.borrow_mut().note(&note);
+                            // keep going with the first definition
+                            skipping = true;
+                            continue;
+                        }
+                        self.chunks.insert(func_name.clone(), new_chunk);
+                    }
+                    "push" if !skipping && parts.next() == Some("vtable") => {
+                        if let (Some(class), Some(chunk)) =
+                            (parts.next(), self.chunks.get_mut(&current_function_name))
+                        {
+                            chunk.calls.push((format!("vtable {}", class), line_no));
                         }
                     }
-                    "call" => {
+                    "call" if !skipping => {
                         // record the call
-                        let name = parts.next().unwrap();
+                        let Some(name) = parts.next() else {
+                            self.error(&file, line_no, "call without a function name");
+                            continue;
+                        };
                         if self.verbose {
                             println!("   call {}", name)
                         };
                         if let Some(ref mut chunk) = self.chunks.get_mut(&current_function_name) {
-                            chunk.calls.push(name.to_string());
+                            chunk.calls.push((name.to_string(), line_no));
                         } else {
-                            bail!("call without function");
+                            self.error(&file, line_no, "call without function");
                         }
                     }
 
                     _ => {}
                 }
             }
+            if skipping {
+                continue;
+            }
             // store each line in the current chunk
             if let Some(ref mut chunk) = self.chunks.get_mut(&current_function_name) {
                 chunk.code.push(line.to_string());
-            } else {
-                if line.starts_with("// ++pdb") {
-                    pdb_comment = line.to_string();
-                } else {
-                    bail!("line outside of function");
-                }
+            } else if line.starts_with("// ++pdb") {
+                pdb_comment = line.to_string();
+            } else if !line.trim().is_empty() {
+                self.error(&file, line_no, "line outside of function");
             }
         }
 
@@ -93,7 +139,7 @@ impl Linker {
             println!("Generated call tree---------------");
             for chunk in self.chunks.values() {
                 println!("{}:", chunk.name);
-                for call in &chunk.calls {
+                for (call, _) in &chunk.calls {
                     println!(" =>{}", call);
                 }
             }
@@ -102,9 +148,20 @@ impl Linker {
         }
         // Sys.init is the root of the call tree
 
+        if !self.chunks.contains_key("Sys.init") {
+            self.diagnostics
+                .borrow_mut()
+                .error(name, Span::default(), "Sys.init not found");
+            return Ok(());
+        }
         self.emit("Sys.init", &mut output, 0)?;
         Ok(())
     }
+    fn error(&self, file: &str, line: usize, msg: &str) {
+        self.diagnostics
+            .borrow_mut()
+            .error(file, Span::line(line), msg);
+    }
 
     fn emit(&self, name: &str, output: &mut File, depth: i8) -> Result<()> {
         // recurse down the call tree satisfying each function call we hit
@@ -129,11 +186,18 @@ impl Linker {
                 writeln!(output, "{}", line)?;
             }
             // include the code for each function it calls
-            for call in &chunk.calls {
-                self.emit(call, output, depth + 1)?;
+            for (call, line_no) in &chunk.calls {
+                if self.chunks.contains_key(call) {
+                    self.emit(call, output, depth + 1)?;
+                } else if self.emitted.borrow_mut().insert(call.to_string()) {
+                    // report each missing function once
+                    self.error(
+                        &chunk.file,
+                        *line_no,
+                        &format!("Unresolved external reference: {}", call),
+                    );
+                }
             }
-        } else {
-            bail!("Unresolved external reference: {}", name);
         }
         Ok(())
     }
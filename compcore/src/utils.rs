@@ -0,0 +1,31 @@
+use common::diagnostics::{Diagnostics, Span};
+use pest::{
+    error::{Error, LineColLocation},
+    RuleType,
+};
+
+// conversions from pest positions and errors into diagnostics
+
+pub(crate) fn span_of(span: &pest::Span) -> Span {
+    let (line, col) = span.start_pos().line_col();
+    let (end_line, end_col) = span.end_pos().line_col();
+    Span::new(line, col, end_line, end_col)
+}
+
+pub(crate) fn push_parse_error<R: RuleType>(
+    diagnostics: &mut Diagnostics,
+    file: &str,
+    err: &Error<R>,
+) {
+    let span = match err.line_col {
+        LineColLocation::Pos((line, col)) => Span::new(line, col, line, col + 1),
+        LineColLocation::Span((line, col), (end_line, end_col)) => {
+            Span::new(line, col, end_line, end_col)
+        }
+    };
+    diagnostics.error(
+        file,
+        span,
+        &format!("syntax error, {}", err.variant.message()),
+    );
+}
@@ -1,8 +1,14 @@
-use crate::constants;
+use crate::{
+    constants,
+    utils::{push_parse_error, span_of},
+};
 use anyhow::Result;
-use common::pdb::database::{FileType, Pdb, SymbolType};
+use common::{
+    diagnostics::Diagnostics,
+    pdb::database::{FileType, Pdb, SymbolType},
+};
 use pest::{iterators::Pair, Parser};
-use std::fs;
+use std::{fs, path::Path};
 #[derive(pest_derive::Parser)]
 #[grammar = "vcomp/vm.pest"]
 pub struct VMParser;
@@ -26,11 +32,13 @@ struct Push {
 pub struct VMComp<'pdb> {
     code: Vec<String>,
     file_name: String,
+    source_path: String,
     label_count: i32,
     current_function: String,
     current_module: String,
     last_push: Option<Push>,
     pdb: &'pdb mut Pdb,
+    pub diagnostics: Diagnostics,
 }
 
 impl<'pdb> VMComp<'pdb> {
@@ -38,11 +46,13 @@ impl<'pdb> VMComp<'pdb> {
         Self {
             code: Vec::new(),
             file_name: String::new(),
+            source_path: String::new(),
             label_count: 0,
             current_function: String::new(),
             current_module: String::new(),
             last_push: None,
             pdb,
+            diagnostics: Diagnostics::new(),
         }
     }
     pub fn output_code(&self, output_name: &str) -> Result<()> {
@@ -50,6 +60,13 @@ impl<'pdb> VMComp<'pdb> {
         fs::write(output_name, code).expect("Unable to write file");
         Ok(())
     }
+    // preinitialised RAM, only loaded by the hackem output format
+    pub fn emit_data(&mut self, address: u16, words: &[u16]) {
+        self.write(&format!(".org 0x{:04x}", address));
+        for word in words {
+            self.write(&format!("word 0x{:04x}", word));
+        }
+    }
     pub fn bootstrap(&mut self) -> Result<()> {
         self.write("@256");
         self.write("D=A");
@@ -164,11 +181,22 @@ impl<'pdb> VMComp<'pdb> {
 
         Ok(())
     }
-    pub fn run(&mut self, source: &str, file_name: &str) -> Result<()> {
-        self.file_name = file_name.to_string();
+    pub fn run(&mut self, source: &str, path: &Path) -> Result<()> {
+        self.file_name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+        self.source_path = path.display().to_string();
         self.current_function = String::new();
         self.current_module = String::new();
-        let pairs = VMParser::parse(Rule::program, source)?;
+        let pairs = match VMParser::parse(Rule::program, source) {
+            Ok(pairs) => pairs,
+            Err(err) => {
+                push_parse_error(&mut self.diagnostics, &self.source_path, &err);
+                return Ok(());
+            }
+        };
         for pair in pairs {
             // insert original source line as comment
             let source_line = pair.as_str();
@@ -219,6 +247,24 @@ impl<'pdb> VMComp<'pdb> {
                     self.write(&format!("@{}", self.make_private_label(label)));
                     self.write("0;JMP");
                 }
+                Rule::goto_table_st => {
+                    let labels: Vec<String> = pair
+                        .into_inner()
+                        .map(|label| self.make_private_label(label.as_str()))
+                        .collect();
+                    self.emit_goto_table(&labels);
+                }
+                Rule::vtable_st => {
+                    let mut pair_iter = pair.into_inner();
+                    let class = pair_iter.next().unwrap().as_str();
+                    let methods: Vec<&str> = pair_iter.map(|method| method.as_str()).collect();
+                    self.emit_vtable(class, &methods);
+                }
+                Rule::push_vtable_st => {
+                    let class = pair.into_inner().next().unwrap().as_str();
+                    self.write(&format!("@{}$vtable", class));
+                    self.emit_push(PushSource::A);
+                }
                 Rule::if_goto_st => {
                     let label = pair.into_inner().next().unwrap().as_str();
                     self.emit_dec_load_sp();
@@ -229,7 +275,10 @@ impl<'pdb> VMComp<'pdb> {
                 Rule::function_st => {
                     let mut pair_iter = pair.into_inner();
                     let name = pair_iter.next().unwrap().as_str();
-                    let locals = pair_iter.next().unwrap().as_str().trim();
+                    let locals_pair = pair_iter.next().unwrap();
+                    let Some(locals) = self.parse_index::<u16>(&locals_pair) else {
+                        continue;
+                    };
                     self.write(&format!("({})", name));
                     self.current_function = name.to_string();
                     self.current_module =
@@ -253,13 +302,24 @@ impl<'pdb> VMComp<'pdb> {
                             instance_type: String::new(),
                         })
                     }
-                    for _ in 0..locals.parse::<u16>()? {
+                    for _ in 0..locals {
                         self.emit_push(PushSource::Constant(0));
                     }
                 }
                 Rule::call_st => {
                     self.call_st(pair)?;
                 }
+                Rule::call_virtual_st => {
+                    let mut pair_iter = pair.into_inner();
+                    pair_iter.next();
+                    let Some(args) = self.parse_index::<u16>(&pair_iter.next().unwrap()) else {
+                        continue;
+                    };
+                    let Some(slot) = self.parse_index::<u16>(&pair_iter.next().unwrap()) else {
+                        continue;
+                    };
+                    self.emit_virtual_call(args, slot);
+                }
                 Rule::return_st => {
                     self.return_st()?;
                 }
@@ -273,8 +333,9 @@ impl<'pdb> VMComp<'pdb> {
     fn push(&mut self, pair: Pair<Rule>) -> Result<()> {
         let mut pair_iter = pair.into_inner();
         let segment = pair_iter.next().unwrap();
-        let index_str = pair_iter.next().unwrap().as_str().trim();
-        let index = index_str.parse::<i16>()?;
+        let Some(index) = self.parse_index::<i16>(&pair_iter.next().unwrap()) else {
+            return Ok(());
+        };
         self.last_push = Some(Push {
             segment: segment.as_rule(),
             index,
@@ -320,8 +381,9 @@ impl<'pdb> VMComp<'pdb> {
     fn pop(&mut self, pair: Pair<Rule>, line: &str) -> Result<()> {
         let mut pair_iter = pair.into_inner();
         let segment = pair_iter.next().unwrap();
-        let index_str = pair_iter.next().unwrap().as_str().trim();
-        let index = index_str.parse::<u16>()?;
+        let Some(index) = self.parse_index::<u16>(&pair_iter.next().unwrap()) else {
+            return Ok(());
+        };
 
         if self.last_push.is_some() {
             self.fast_push_pop(&segment.as_rule(), index, line)?;
@@ -473,6 +535,17 @@ impl<'pdb> VMComp<'pdb> {
         Ok(())
     }
 
+    fn parse_index<T: std::str::FromStr>(&mut self, pair: &Pair<Rule>) -> Option<T> {
+        let index = pair.as_str().trim().parse::<T>().ok();
+        if index.is_none() {
+            self.diagnostics.error(
+                &self.source_path,
+                span_of(&pair.as_span()),
+                &format!("`{}` is out of range", pair.as_str().trim()),
+            );
+        }
+        index
+    }
     fn make_private_label(&self, label: &str) -> String {
         format!("{}.{}${}", self.file_name, self.current_function, label)
     }
@@ -506,12 +579,60 @@ impl<'pdb> VMComp<'pdb> {
 
         Ok(())
     }
+    // the object is the first argument, its first word is its class's
+    // vtable and each entry is two instructions long
+    fn emit_virtual_call(&mut self, args: u16, slot: u16) {
+        let return_label = self.make_label();
+        self.write("@SP");
+        self.write("D=M");
+        self.write(&format!("@{}", args));
+        self.write("A=D-A");
+        self.write("A=M");
+        self.write("D=M");
+        self.write(&format!("@{}", slot * 2));
+        self.write("D=D+A");
+        self.write("@R14");
+        self.write("M=D");
+        self.write(&format!("@{}", args));
+        self.write("D=A");
+        self.write("@R13");
+        self.write("M=D");
+        self.write(&format!("@{}", return_label));
+        self.write("D=A");
+        self.write("@FW__CALL");
+        self.write("0;JMP");
+        self.write(&format!("({})", return_label));
+    }
     fn return_st(&mut self) -> Result<()> {
         self.write("@FW__RETURN");
         self.write("0;JMP");
         Ok(())
     }
 
+    // rom cannot be read as data so the table is a run of
+    // @label, 0;JMP pairs and the jump lands on entry n
+    fn emit_goto_table(&mut self, labels: &[String]) {
+        let table = self.make_label();
+        self.emit_dec_load_sp();
+        self.write("D=M");
+        self.write("D=D+M");
+        self.write(&format!("@{}", table));
+        self.write("A=D+A");
+        self.write("0;JMP");
+        self.write(&format!("({})", table));
+        for label in labels {
+            self.write(&format!("@{}", label));
+            self.write("0;JMP");
+        }
+    }
+    // jumps to the methods like a goto table, objects hold its address
+    fn emit_vtable(&mut self, class: &str, methods: &[&str]) {
+        self.write(&format!("({}$vtable)", class));
+        for method in methods {
+            self.write(&format!("@{}", method));
+            self.write("0;JMP");
+        }
+    }
     fn make_label(&mut self) -> String {
         self.label_count += 1;
         format!("L_{}_{}", self.file_name, self.label_count)
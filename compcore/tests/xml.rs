@@ -0,0 +1,39 @@
+use common::diagnostics::Diagnostics;
+use compcore::jcomp::xml::course_xml;
+
+// Main.jack from the course's project 10 Square, with the token and
+// parse tree files in the layout of the course's compare files
+const MAIN: &str = include_str!("square/Main.jack");
+const MAIN_TOKENS: &str = include_str!("square/MainT.xml");
+const MAIN_TREE: &str = include_str!("square/Main.xml");
+
+fn xml(source: &str) -> Option<(String, String)> {
+    let mut diagnostics = Diagnostics::new();
+    let xml = course_xml(source, "Main.jack", &mut diagnostics);
+    assert_eq!(xml.is_some(), !diagnostics.has_errors());
+    xml
+}
+
+#[test]
+fn square_main_matches_the_course() {
+    let (tokens, tree) = xml(MAIN).expect("Main.jack parses");
+    assert_eq!(tokens, MAIN_TOKENS);
+    assert_eq!(tree, MAIN_TREE);
+}
+
+#[test]
+fn symbols_are_escaped() {
+    let source =
+        "class A { function void f(int a) { if ((a < 1) & (a > 0)) { return; } return; } }";
+    let (tokens, tree) = xml(source).expect("parses");
+    for symbol in ["&lt;", "&gt;", "&amp;"] {
+        let line = format!("<symbol> {} </symbol>", symbol);
+        assert!(tokens.lines().any(|l| l == line), "{}", symbol);
+        assert!(tree.lines().any(|l| l.trim() == line), "{}", symbol);
+    }
+}
+
+#[test]
+fn syntax_errors_give_no_xml() {
+    assert!(xml("class A { function void f() { let = 1; } }").is_none());
+}
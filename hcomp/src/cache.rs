@@ -0,0 +1,228 @@
+use anyhow::Result;
+use common::{diagnostics::Diagnostics, pdb::database::Pdb};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::UNIX_EPOCH,
+};
+
+// incremental builds, every stage remembers a hash of its inputs in
+// .hcomp-cache in the project directory and is skipped when they match
+// - each jack class keeps its vm code, its part of the pdb and its
+//   warnings, the key covers the source, the settings, what the class can
+//   see of the rest of the project and the compiler that built it
+// - linking is skipped when no vm file changed
+// - translating and assembling are skipped when the linked vm, the pdb so
+//   far and the output format are unchanged, the finished pdb is kept
+pub const CACHE_DIR: &str = ".hcomp-cache";
+
+// a cached entry is only good for the compiler that wrote it
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// the version stays the same while hcomp is being worked on, so the
+// size and modification time of the running executable go in as well,
+// rebuilding hcomp changes them and every entry misses
+fn compiler() -> &'static str {
+    static COMPILER: OnceLock<String> = OnceLock::new();
+    COMPILER.get_or_init(|| {
+        let mut compiler = VERSION.to_string();
+        if let Ok(metadata) = std::env::current_exe().and_then(fs::metadata) {
+            compiler.push_str(&format!(" {}", metadata.len()));
+            if let Some(modified) = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            {
+                compiler.push_str(&format!(" {}", modified.as_nanos()));
+            }
+        }
+        compiler
+    })
+}
+
+// 64 bit FNV-1a, stable across builds and platforms unlike DefaultHasher
+pub struct Key(u64);
+
+impl Key {
+    pub fn new() -> Self {
+        Self::for_compiler(compiler())
+    }
+    fn for_compiler(compiler: &str) -> Self {
+        let mut key = Self(0xcbf2_9ce4_8422_2325);
+        key.add(compiler);
+        key
+    }
+    pub fn add(&mut self, part: &str) -> &mut Self {
+        // the length keeps "ab" + "c" apart from "a" + "bc"
+        for byte in (part.len() as u64)
+            .to_le_bytes()
+            .iter()
+            .chain(part.as_bytes())
+        {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+}
+
+// one compiled class, as a worker thread hands it back and as it is kept
+// in the cache, the pdb only has this class's file_info and symbols and
+// the pdb comments in the code say it is file 0
+#[derive(Serialize, Deserialize)]
+pub struct CompiledClass {
+    pub ok: bool,
+    pub vm: String,
+    pub pdb: Pdb,
+    pub diagnostics: Diagnostics,
+}
+
+// linking has no output besides the file
+#[derive(Serialize, Deserialize)]
+pub struct LinkEntry {
+    pub key: String,
+}
+
+// translating and assembling, the pdb is the one they finished with
+#[derive(Serialize, Deserialize)]
+pub struct BackEndEntry {
+    pub key: String,
+    pub pdb: Pdb,
+}
+
+pub struct BuildCache {
+    dir: PathBuf,
+}
+
+impl BuildCache {
+    pub fn new(project_dir: &Path) -> Self {
+        Self {
+            dir: project_dir.join(CACHE_DIR),
+        }
+    }
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    // a missing or unreadable entry is just a miss
+    pub fn load<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let json = fs::read_to_string(self.path(name)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+    pub fn store<T: Serialize>(&self, name: &str, entry: &T) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(name), serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    pub fn class(&self, name: &str, key: &str) -> Option<CompiledClass> {
+        self.load::<(String, CompiledClass)>(&format!("class.{}", name))
+            .filter(|(entry_key, _)| entry_key == key)
+            .map(|(_, class)| class)
+    }
+    pub fn store_class(&self, name: &str, key: &str, class: &CompiledClass) -> Result<()> {
+        self.store(&format!("class.{}", name), &(key, class))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_cache(name: &str) -> BuildCache {
+        let dir = std::env::temp_dir().join(format!("hcomp-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        BuildCache::new(&dir)
+    }
+
+    fn class(vm: &str) -> CompiledClass {
+        CompiledClass {
+            ok: true,
+            vm: vm.to_string(),
+            pdb: Pdb::new(),
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    #[test]
+    fn keys_are_stable_and_keep_parts_apart() {
+        let key = |parts: &[&str]| {
+            let mut key = Key::new();
+            for part in parts {
+                key.add(part);
+            }
+            key.finish()
+        };
+        assert_eq!(key(&["ab", "c"]), key(&["ab", "c"]));
+        assert_ne!(key(&["ab", "c"]), key(&["a", "bc"]));
+        assert_ne!(key(&["ab"]), key(&["ab", ""]));
+        assert_eq!(key(&[]).len(), 16);
+    }
+
+    #[test]
+    fn class_hits_only_on_the_same_key() {
+        let cache = scratch_cache("cache-class");
+        assert!(cache.class("Main", "k1").is_none());
+        cache
+            .store_class("Main", "k1", &class("function Main.main 0"))
+            .unwrap();
+
+        let hit = cache.class("Main", "k1").expect("same key hits");
+        assert!(hit.ok);
+        assert_eq!(hit.vm, "function Main.main 0");
+        assert!(cache.class("Main", "k2").is_none());
+        assert!(cache.class("Other", "k1").is_none());
+
+        // a newer entry replaces the old one
+        cache
+            .store_class("Main", "k2", &class("function Main.main 1"))
+            .unwrap();
+        assert!(cache.class("Main", "k1").is_none());
+        assert_eq!(
+            cache.class("Main", "k2").unwrap().vm,
+            "function Main.main 1"
+        );
+        fs::remove_dir_all(cache.dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn a_rebuilt_compiler_misses_old_entries() {
+        assert!(compiler().starts_with(VERSION));
+        assert_ne!(compiler(), VERSION);
+
+        let cache = scratch_cache("cache-rebuilt");
+        let key = |compiler: &str| Key::for_compiler(compiler).add("class Main {}").finish();
+        let old = key("0.1.0 1000 1");
+        cache
+            .store_class("Main", &old, &class("function Main.main 0"))
+            .unwrap();
+        assert!(cache.class("Main", &key("0.1.0 1000 1")).is_some());
+        assert!(cache.class("Main", &key("0.1.0 1000 2")).is_none());
+        assert!(cache.class("Main", &key("0.1.0 1001 1")).is_none());
+        fs::remove_dir_all(cache.dir.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unreadable_entries_are_misses() {
+        let cache = scratch_cache("cache-corrupt");
+        cache
+            .store(
+                "link",
+                &LinkEntry {
+                    key: "k".to_string(),
+                },
+            )
+            .unwrap();
+        assert!(cache.load::<LinkEntry>("link").is_some());
+        fs::write(cache.path("link"), "{ not json").unwrap();
+        assert!(cache.load::<LinkEntry>("link").is_none());
+        fs::write(cache.path("class.Main"), "[\"k\"]").unwrap();
+        assert!(cache.class("Main", "k").is_none());
+        fs::remove_dir_all(cache.dir.parent().unwrap()).unwrap();
+    }
+}
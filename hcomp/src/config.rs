@@ -0,0 +1,54 @@
+use anyhow::{Context, Result};
+use compcore::jcomp::{lint::Lint, reduce::OptimizeFor};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+// per project settings, read from hcomp.json in the project directory
+// command line flags can switch these on for a single build
+pub const CONFIG_FILE: &str = "hcomp.json";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    // conventional operator precedence instead of jack's left to right
+    pub precedence: bool,
+    // string literals preinitialised in RAM, needs the hackem output format
+    pub pool_strings: bool,
+    // compile time evaluation of constant expressions
+    pub fold: bool,
+    // "size" or "speed", how much to inline multiplies and divides
+    pub optimize: OptimizeFor,
+    // match the course's JackCompiler output byte for byte, turns off
+    // folding, strength reduction and string pooling
+    pub reference: bool,
+    // lints that should stay quiet, e.g. ["unused-parameter"]
+    pub allow: Vec<Lint>,
+    // run time checks that stop with Sys.error, for the project's own
+    // classes, library code is trusted
+    pub checked: bool,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            precedence: false,
+            pool_strings: false,
+            fold: true,
+            optimize: OptimizeFor::Size,
+            reference: false,
+            allow: Vec::new(),
+            checked: false,
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(CONFIG_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(&path)?;
+        serde_json::from_str(&json).with_context(|| format!("bad project file {}", path.display()))
+    }
+}
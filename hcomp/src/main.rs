@@ -1,20 +1,38 @@
+mod cache;
+mod config;
+
 use anyhow::{anyhow, bail, Result};
-use common::pdb::database::Pdb;
+use common::{diagnostics::Diagnostics, pdb::database::Pdb};
 // pub mod compiler;
 // //mod constants;
 // mod expression;
 // mod symbols;
+use cache::{BackEndEntry, BuildCache, CompiledClass, Key, LinkEntry};
 use clap::Parser;
 use clap_derive::{Parser, ValueEnum};
 use compcore::{
     assembler::assemble::{Assembler, Format},
-    jcomp::compiler::Compiler,
+    jcomp::{
+        checks::{fail_vm, FAIL_CLASS},
+        compiler::{renumber_pdb_comments, Compiler},
+        format::format_source,
+        index::ClassIndex,
+        jackdoc::JackDoc,
+        lint::Lint,
+        reduce::OptimizeFor,
+        strings::StringPool,
+        xml::course_xml,
+    },
     linker::link::Linker,
     vcomp::vmcomp::VMComp,
 };
+use config::ProjectConfig;
 use std::{
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
 };
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -35,6 +53,33 @@ struct Args {
     mode: Option<Mode>,
     #[arg(short, long)]
     bootstrap: bool,
+    // conventional operator precedence for jack expressions
+    #[arg(long)]
+    precedence: bool,
+    // string literals preinitialised in RAM rather than built at run time
+    #[arg(long)]
+    pool_strings: bool,
+    // keep constant expressions as written
+    #[arg(long)]
+    no_fold: bool,
+    // size or speed
+    #[arg(long)]
+    opt: Option<String>,
+    // fmt mode, report files that would change instead of rewriting them
+    #[arg(long)]
+    check: bool,
+    // vm output identical to the course's JackCompiler
+    #[arg(long)]
+    reference: bool,
+    // build everything again, ignoring and not updating the build cache
+    #[arg(long)]
+    no_cache: bool,
+    // bounds, null object, divide by zero and stack overflow checks
+    #[arg(long)]
+    checked: bool,
+    // turn a lint off, e.g. --allow unused-parameter
+    #[arg(long, value_name = "LINT")]
+    allow: Vec<String>,
 }
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 enum Mode {
@@ -42,6 +87,14 @@ enum Mode {
     Vm,
     Link,
     Asm,
+    // dump the jack syntax tree as json
+    Ast,
+    // reformat jack source in place
+    Fmt,
+    // nand2tetris XxxT.xml tokens and Xxx.xml parse tree
+    Xml,
+    // html and markdown api pages from the /** */ comments, into -o or doc
+    Doc,
 }
 fn main() -> Result<()> {
     let args = Args::parse();
@@ -49,38 +102,110 @@ fn main() -> Result<()> {
     let input_path = &args.input;
     let mut pdb = Pdb::new();
     let bootstrap = args.bootstrap;
+    let mut diagnostics = Diagnostics::new();
+    let project_dir = if input_path.is_file() {
+        input_path.parent().unwrap_or(Path::new("."))
+    } else {
+        input_path.as_path()
+    };
+    let mut config = ProjectConfig::load(project_dir)?;
+    config.precedence |= args.precedence;
+    config.pool_strings |= args.pool_strings;
+    config.fold &= !args.no_fold;
+    config.reference |= args.reference;
+    config.checked |= args.checked;
+    if config.checked && config.reference {
+        bail!("checked builds add code, they cannot match the course's output (--reference)");
+    }
+    if let Some(opt) = &args.opt {
+        config.optimize = match opt.as_str() {
+            "size" | "s" => OptimizeFor::Size,
+            "speed" | "f" => OptimizeFor::Speed,
+            _ => bail!("Invalid optimization, use size or speed"),
+        };
+    }
+    for name in &args.allow {
+        let Some(lint) = Lint::from_name(name) else {
+            let names: Vec<&str> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+            bail!("Unknown lint {}, use one of {}", name, names.join(", "));
+        };
+        config.allow.push(lint);
+    }
 
     if let Some(mode) = args.mode {
-        let name = input_path.file_stem().unwrap().to_str().unwrap();
         match mode {
             Mode::Jack => {
+                let name = output_stem(input_path)?;
                 let source = fs::read_to_string(input_path.clone())?;
 
                 let mut compiler = Compiler::new(verbose, &mut pdb);
-                if compiler.run(&source, input_path)? {
+                compiler.set_precedence(config.precedence);
+                compiler.set_fold(config.fold);
+                compiler.set_allow(&config.allow);
+                compiler.set_reference(config.reference);
+                compiler.set_optimize_for(config.optimize);
+                compiler.set_checked(config.checked);
+                let ok = compiler.run(&source, input_path)?;
+                diagnostics.append(&mut compiler.diagnostics);
+                check_errors(&diagnostics)?;
+                if ok {
                     compiler.output_code(&format!("{}.vm", name))?;
-                } else {
-                    bail!("No code generated");
+                    if config.checked {
+                        fs::write(format!("{}.vm", FAIL_CLASS), fail_vm())?;
+                    }
                 }
             }
             Mode::Vm => {
                 let source = fs::read_to_string(input_path)?;
-                let output_name = format!("{}.asm", name);
+                let output_name = format!("{}.asm", output_stem(input_path)?);
 
                 let mut vmcompiler = VMComp::new(&mut pdb);
                 if bootstrap {
                     vmcompiler.bootstrap()?
                 };
-                vmcompiler.run(&source, name)?;
+                vmcompiler.run(&source, input_path)?;
+                diagnostics.append(&mut vmcompiler.diagnostics);
+                check_errors(&diagnostics)?;
                 if bootstrap {
                     vmcompiler.emit_firmware()?
                 };
                 vmcompiler.output_code(&output_name)?;
             }
             Mode::Link => {
-                link_all_vm(verbose, input_path, &args.oslib, name)?;
+                link_all_vm(
+                    verbose,
+                    input_path,
+                    &args.oslib,
+                    output_stem(input_path)?,
+                    None,
+                    &mut diagnostics,
+                )?;
             }
-            Mode::Asm => assemble(verbose, input_path, args.format, args.listing, &mut pdb)?,
+            Mode::Asm => assemble(
+                verbose,
+                input_path,
+                args.format,
+                args.listing,
+                &mut pdb,
+                &mut diagnostics,
+            )?,
+            Mode::Ast => {
+                let name = output_stem(input_path)?;
+                let source = fs::read_to_string(input_path)?;
+
+                let mut compiler = Compiler::new(verbose, &mut pdb);
+                compiler.set_precedence(config.precedence);
+                let class = compiler.parse(&source, input_path)?;
+                diagnostics.append(&mut compiler.diagnostics);
+                check_errors(&diagnostics)?;
+                if let Some(class) = class {
+                    let json = serde_json::to_string_pretty(&class)?;
+                    fs::write(format!("{}.ast.json", name), json)?;
+                }
+            }
+            Mode::Fmt => format_jack(input_path, args.check, &mut diagnostics)?,
+            Mode::Xml => xml_jack(input_path, &mut diagnostics)?,
+            Mode::Doc => doc_jack(input_path, args.outfile.as_deref(), &mut diagnostics)?,
         }
     } else {
         let dir = if input_path == Path::new(".") {
@@ -91,26 +216,181 @@ fn main() -> Result<()> {
 
         let name = dir
             .components()
-            .last()
+            .next_back()
             .unwrap()
             .as_os_str()
             .to_str()
             .unwrap();
-        build_all_jack(verbose, input_path, &mut pdb)?;
-        let linked_vm = link_all_vm(verbose, input_path, &args.oslib, name)?;
-        let compiled_vm = compile_linked_vm(verbose, &linked_vm, &mut pdb)?;
-        assemble(verbose, &compiled_vm, args.format, args.listing, &mut pdb)?;
+        if config.pool_strings && !matches!(args.format.as_deref(), Some("hx")) {
+            bail!("pooled strings are loaded into RAM, they need the hackem format (-f hx)");
+        }
+        let cache = (!args.no_cache).then(|| BuildCache::new(input_path));
+        let strings = build_all_jack(
+            verbose,
+            input_path,
+            &args.oslib,
+            &config,
+            cache.as_ref(),
+            &mut pdb,
+            &mut diagnostics,
+        )?;
+        let linked_vm = link_all_vm(
+            verbose,
+            input_path,
+            &args.oslib,
+            name,
+            cache.as_ref(),
+            &mut diagnostics,
+        )?;
+
+        // translating and assembling only depend on the linked code, the
+        // strings, the pdb so far and the output options
+        let (_, suffix) = output_format(args.format.as_deref())?;
+        let mut key = Key::new();
+        key.add(&fs::read_to_string(&linked_vm)?)
+            .add(&format!("{:?}", strings.data()))
+            .add(suffix)
+            .add(&format!("{:?}", args.listing))
+            .add(&pdb.save_json()?);
+        let key = key.finish();
+        let outputs_exist = Path::new(&format!("{}.asm", name)).exists()
+            && Path::new(&format!("{}.{}", name, suffix)).exists()
+            && args.listing.as_ref().is_none_or(|listing| listing.exists());
+        let cached = cache
+            .as_ref()
+            .and_then(|cache| cache.load::<BackEndEntry>("backend"))
+            .filter(|entry| entry.key == key && outputs_exist);
+        match cached {
+            Some(entry) => pdb = entry.pdb,
+            None => {
+                let compiled_vm =
+                    compile_linked_vm(verbose, &linked_vm, &strings, &mut pdb, &mut diagnostics)?;
+                assemble(
+                    verbose,
+                    &compiled_vm,
+                    args.format,
+                    args.listing,
+                    &mut pdb,
+                    &mut diagnostics,
+                )?;
+                if let Some(cache) = &cache {
+                    let entry = BackEndEntry {
+                        key,
+                        pdb: std::mem::take(&mut pdb),
+                    };
+                    cache.store("backend", &entry)?;
+                    pdb = entry.pdb;
+                }
+            }
+        }
         let json = pdb.save_json()?;
         fs::write(format!("{}.pdb", name), json)?;
     };
 
+    // no errors got this far, but there may be warnings
+    report(&diagnostics);
+    Ok(())
+}
+
+// print every diagnostic collected so far, rustc style
+fn report(diagnostics: &Diagnostics) {
+    let mut sources: HashMap<&str, Option<String>> = HashMap::new();
+    for diag in &diagnostics.items {
+        let source = sources
+            .entry(&diag.file)
+            .or_insert_with(|| fs::read_to_string(&diag.file).ok());
+        eprintln!("{}", diag.render(source.as_deref()));
+    }
+}
+
+// stop the build once a stage has reported errors
+fn check_errors(diagnostics: &Diagnostics) -> Result<()> {
+    if diagnostics.has_errors() {
+        report(diagnostics);
+        bail!(
+            "build failed with {} error(s), {} warning(s)",
+            diagnostics.error_count(),
+            diagnostics.warning_count()
+        );
+    }
+    Ok(())
+}
+
+// a single .jack file or every one in a directory
+fn jack_files(input_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if input_path.is_file() {
+        files.push(input_path.to_path_buf());
+    } else {
+        for entry in fs::read_dir(input_path)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().and_then(|e| e.to_str()) == Some("jack") {
+                files.push(path);
+            }
+        }
+        files.sort();
+    }
+    Ok(files)
+}
+fn format_jack(input_path: &Path, check: bool, diagnostics: &mut Diagnostics) -> Result<()> {
+    let files = jack_files(input_path)?;
+    let mut changed = 0;
+    for path in &files {
+        let source = fs::read_to_string(path)?;
+        let file_name = path.to_str().unwrap_or_default();
+        let Some(formatted) = format_source(&source, file_name, diagnostics) else {
+            continue;
+        };
+        if formatted == source {
+            continue;
+        }
+        changed += 1;
+        if check {
+            println!("would reformat {}", path.display());
+        } else {
+            fs::write(path, formatted)?;
+        }
+    }
+    check_errors(diagnostics)?;
+    if check && changed > 0 {
+        bail!("{} file(s) would be reformatted", changed);
+    }
+    Ok(())
+}
+fn xml_jack(input_path: &Path, diagnostics: &mut Diagnostics) -> Result<()> {
+    for path in jack_files(input_path)? {
+        let source = fs::read_to_string(&path)?;
+        let file_name = path.to_str().unwrap_or_default();
+        let Some((tokens, tree)) = course_xml(&source, file_name, diagnostics) else {
+            continue;
+        };
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        fs::write(format!("{}T.xml", name), tokens)?;
+        fs::write(format!("{}.xml", name), tree)?;
+    }
+    check_errors(diagnostics)
+}
+fn doc_jack(input_path: &Path, outdir: Option<&Path>, diagnostics: &mut Diagnostics) -> Result<()> {
+    let mut docs = JackDoc::new();
+    for path in jack_files(input_path)? {
+        let source = fs::read_to_string(&path)?;
+        docs.add(&source, path.to_str().unwrap_or_default(), diagnostics);
+    }
+    check_errors(diagnostics)?;
+    let outdir = outdir.unwrap_or(Path::new("doc"));
+    fs::create_dir_all(outdir)?;
+    for (name, page) in docs.pages() {
+        fs::write(outdir.join(name), page)?;
+    }
     Ok(())
 }
 fn link_all_vm(
     verbose: bool,
-    input_path: &PathBuf,
+    input_path: &Path,
     oslib: &Option<PathBuf>,
     name: &str,
+    cache: Option<&BuildCache>,
+    diagnostics: &mut Diagnostics,
 ) -> Result<PathBuf> {
     // the input points at a directory
     if input_path.is_file() {
@@ -133,6 +413,35 @@ fn link_all_vm(
 
     let output_name = format!("{}.vm", name);
 
+    // nothing to do if no vm file has changed since the last link
+    let output = Path::new(&output_name).canonicalize().ok();
+    let mut inputs = Vec::new();
+    for dir in std::iter::once(input_path).chain(oslib.as_deref()) {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) == Some("vm")
+                && path.canonicalize().ok() != output
+            {
+                inputs.push(path);
+            }
+        }
+    }
+    inputs.sort();
+    let mut key = Key::new();
+    for path in &inputs {
+        key.add(&path.to_string_lossy())
+            .add(&fs::read_to_string(path)?);
+    }
+    let key = key.finish();
+    if let Some(cache) = cache {
+        let linked = cache
+            .load::<LinkEntry>("link")
+            .is_some_and(|entry| entry.key == key);
+        if linked && output.is_some() {
+            return Ok(PathBuf::from(output_name));
+        }
+    }
+
     // delete the previous output so we dont try to include in the new run
     fs::remove_file(&output_name).ok();
 
@@ -143,11 +452,18 @@ fn link_all_vm(
     }
 
     let mut linker = Linker::new(verbose);
-    load_dir(input_path.as_path(), &mut linker, verbose)?;
+    load_dir(input_path, &mut linker, verbose)?;
     if let Some(oslib) = oslib {
         load_dir(oslib.as_path(), &mut linker, verbose)?;
     }
+    diagnostics.append(&mut linker.diagnostics.borrow_mut());
+    check_errors(diagnostics)?;
     linker.output_code(&output_name)?;
+    diagnostics.append(&mut linker.diagnostics.borrow_mut());
+    check_errors(diagnostics)?;
+    if let Some(cache) = cache {
+        cache.store("link", &LinkEntry { key })?;
+    }
     Ok(PathBuf::from(output_name))
 }
 
@@ -164,7 +480,7 @@ fn load_dir(path: &Path, linker: &mut Linker, verbose: bool) -> Result<()> {
                     };
                     let source = fs::read_to_string(path.clone())?;
                     // let name = path.file_stem().unwrap().to_str().unwrap();
-                    linker.load(&source)?;
+                    linker.load(&source, &path)?;
                 }
             }
         }
@@ -172,32 +488,258 @@ fn load_dir(path: &Path, linker: &mut Linker, verbose: bool) -> Result<()> {
     Ok(())
 }
 
-fn build_all_jack(verbose: bool, input_path: &PathBuf, pdb: &mut Pdb) -> Result<()> {
+fn build_all_jack(
+    verbose: bool,
+    input_path: &Path,
+    oslib: &Option<PathBuf>,
+    config: &ProjectConfig,
+    cache: Option<&BuildCache>,
+    pdb: &mut Pdb,
+    diagnostics: &mut Diagnostics,
+) -> Result<StringPool> {
     //let name = input_path.file_stem().unwrap().to_str().unwrap();
     //let pdb = Pdb::open(&format!("{}.pdb", name))?;
 
-    for entry in fs::read_dir(input_path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() {
-            if let Some(ftype) = path.extension() {
-                if ftype.to_str().unwrap() == "jack" {
-                    let source = fs::read_to_string(path.clone())?;
-                    let name = path.file_stem().unwrap().to_str().unwrap();
-                    let mut compiler = Compiler::new(verbose, pdb);
-                    if compiler.run(&source, &path)? {
-                        compiler.output_code(&format!("{}.vm", name))?;
-                    } else {
-                        bail!("No code generated");
-                    }
-                }
+    // read every class first so that calls between them can be checked
+    // compile in a fixed order, pooled string addresses depend on it
+    let mut sources = read_jack_dir(input_path)?;
+    // library classes written in jack come after the project's, their vm
+    // goes next to them for the linker to pick up like any library vm
+    let project_classes = sources.len();
+    if let Some(oslib) = oslib {
+        sources.extend(read_jack_dir(oslib)?);
+    }
+    // the guards of a checked or pooled build call a class of their own
+    if (config.checked || config.pool_strings)
+        && sources
+            .iter()
+            .any(|(path, _)| class_name(path) == FAIL_CLASS)
+    {
+        bail!(
+            "checked and pooled builds add a class called {}, rename yours",
+            FAIL_CLASS
+        );
+    }
+    let mut index = ClassIndex::new();
+    for (n, (_, source)) in sources.iter().enumerate() {
+        if n < project_classes {
+            index.add_jack(source);
+        } else {
+            index.add_library_jack(source);
+        }
+    }
+    index_vm_dir(input_path, &mut index)?;
+    if let Some(oslib) = oslib {
+        index_vm_dir(oslib, &mut index)?;
+    }
+
+    // a pooled string's address depends on every class compiled before it
+    // so nothing can be reused, and the classes are compiled one by one
+    let cache = cache.filter(|_| !config.pool_strings);
+    let mut strings = StringPool::new();
+    if config.pool_strings && !config.reference {
+        for (_, source) in &sources {
+            strings.add_class(source);
+        }
+    }
+    let mut settings = Key::new();
+    settings
+        .add(&format!("{:?}", config))
+        .add(&index.fingerprint());
+    let settings = settings.finish();
+
+    let keys: Vec<String> = sources
+        .iter()
+        .map(|(_, source)| Key::new().add(&settings).add(source).finish())
+        .collect();
+    let mut cached: Vec<Option<CompiledClass>> = sources
+        .iter()
+        .zip(&keys)
+        .map(|((path, _), key)| cache.and_then(|cache| cache.class(&class_name(path), key)))
+        .collect();
+    // library classes are trusted, they are built without run time checks
+    // or the stack guard of a pooled build, their literals are still pooled
+    let library_config = ProjectConfig {
+        checked: false,
+        pool_strings: false,
+        ..config.clone()
+    };
+    let jobs: Vec<Job> = sources
+        .iter()
+        .enumerate()
+        .filter(|(n, _)| cached[*n].is_none())
+        .map(|(n, (path, source))| Job {
+            path,
+            source,
+            config: if n < project_classes {
+                config
+            } else {
+                &library_config
+            },
+        })
+        .collect();
+    let compiled = if config.pool_strings {
+        jobs.iter()
+            .map(|job| compile_class(verbose, job, &index, Some(&mut strings)))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        compile_parallel(verbose, &jobs, &index)?
+    };
+
+    // put the classes together in file name order whichever thread
+    // finished first, each was compiled as file 0 of its own pdb
+    let mut compiled = compiled.into_iter();
+    for (n, (path, _)) in sources.iter().enumerate() {
+        let name = class_name(path);
+        let fresh = cached[n].is_none();
+        let mut class = cached[n]
+            .take()
+            .or_else(|| compiled.next())
+            .expect("every class is either cached or compiled");
+        if class.ok {
+            let file_number = pdb.file_info.len();
+            let vm = renumber_pdb_comments(&class.vm, file_number);
+            if n < project_classes {
+                fs::write(format!("{}.vm", name), vm)?;
+            } else {
+                fs::write(path.with_extension("vm"), vm)?;
+            }
+            if let Some(cache) = cache.filter(|_| fresh) {
+                cache.store_class(&name, &keys[n], &class)?;
             }
         }
+        pdb.file_info.append(&mut class.pdb.file_info);
+        pdb.symbols.append(&mut class.pdb.symbols);
+        diagnostics.append(&mut class.diagnostics);
+    }
+
+    check_errors(diagnostics)?;
+    if config.checked || config.pool_strings {
+        fs::write(format!("{}.vm", FAIL_CLASS), fail_vm())?;
+    }
+    Ok(strings)
+}
+
+// every .jack file in a directory, sorted by path
+fn read_jack_dir(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut sources = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().and_then(|e| e.to_str()) == Some("jack") {
+            let source = fs::read_to_string(&path)?;
+            sources.push((path, source));
+        }
+    }
+    sources.sort();
+    Ok(sources)
+}
+
+// the single file modes name their output after the input file
+fn output_stem(path: &Path) -> Result<&str> {
+    path.file_stem()
+        .ok_or(anyhow!("bad path"))?
+        .to_str()
+        .ok_or(anyhow!("bad path"))
+}
+
+fn class_name(path: &Path) -> String {
+    path.file_stem().unwrap().to_str().unwrap().to_string()
+}
+
+// a class to compile and the settings for it
+struct Job<'a> {
+    path: &'a Path,
+    source: &'a str,
+    config: &'a ProjectConfig,
+}
+
+// one class into its own pdb, errors are in the returned diagnostics
+fn compile_class(
+    verbose: bool,
+    job: &Job,
+    index: &ClassIndex,
+    strings: Option<&mut StringPool>,
+) -> Result<CompiledClass> {
+    let config = job.config;
+    let mut pdb = Pdb::new();
+    let mut compiler = Compiler::new(verbose, &mut pdb);
+    compiler.set_index(index);
+    compiler.set_precedence(config.precedence);
+    compiler.set_fold(config.fold);
+    compiler.set_allow(&config.allow);
+    compiler.set_reference(config.reference);
+    compiler.set_optimize_for(config.optimize);
+    compiler.set_checked(config.checked);
+    compiler.set_stack_guard(config.pool_strings && !config.reference);
+    if let Some(strings) = strings {
+        compiler.set_string_pool(strings);
     }
+    let ok = compiler.run(job.source, job.path)?;
+    let vm = if ok { compiler.finish() } else { String::new() };
+    let diagnostics = std::mem::take(&mut compiler.diagnostics);
+    Ok(CompiledClass {
+        ok,
+        vm,
+        pdb,
+        diagnostics,
+    })
+}
 
+// every class on a pool of threads, the results come back in job order
+// verbose output is only readable from one thread
+fn compile_parallel(verbose: bool, jobs: &[Job], index: &ClassIndex) -> Result<Vec<CompiledClass>> {
+    let workers = if verbose {
+        1
+    } else {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    };
+    let next = AtomicUsize::new(0);
+    let mut done: Vec<(usize, Result<CompiledClass>)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers.min(jobs.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let n = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(job) = jobs.get(n) else {
+                            break;
+                        };
+                        done.push((n, compile_class(verbose, job, index, None)));
+                    }
+                    done
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("compiler thread panicked"))
+            .collect()
+    });
+    done.sort_by_key(|(n, _)| *n);
+    done.into_iter().map(|(_, class)| class).collect()
+}
+
+// add the functions from hand written or library vm files to the index
+// vm files generated from a jack file in the same directory are skipped
+fn index_vm_dir(path: &Path, index: &mut ClassIndex) -> Result<()> {
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_file()
+            && path.extension().and_then(|e| e.to_str()) == Some("vm")
+            && !path.with_extension("jack").exists()
+        {
+            index.add_vm(&fs::read_to_string(&path)?);
+        }
+    }
     Ok(())
 }
-fn compile_linked_vm(_verbose: bool, input_path: &PathBuf, pdb: &mut Pdb) -> Result<PathBuf> {
+fn compile_linked_vm(
+    _verbose: bool,
+    input_path: &PathBuf,
+    strings: &StringPool,
+    pdb: &mut Pdb,
+    diagnostics: &mut Diagnostics,
+) -> Result<PathBuf> {
     let name = input_path
         .file_stem()
         .ok_or(anyhow!("bad path"))?
@@ -210,18 +752,35 @@ fn compile_linked_vm(_verbose: bool, input_path: &PathBuf, pdb: &mut Pdb) -> Res
 
     let source = fs::read_to_string(input_path)?;
     vmcompiler.bootstrap()?;
-    vmcompiler.run(&source, name)?;
+    vmcompiler.run(&source, input_path)?;
+    diagnostics.append(&mut vmcompiler.diagnostics);
+    check_errors(diagnostics)?;
     vmcompiler.emit_firmware()?;
+    if !strings.is_empty() {
+        let (address, words) = strings.data();
+        vmcompiler.emit_data(address, &words);
+    }
     vmcompiler.output_code(&output_name)?;
     Ok(PathBuf::from(output_name))
 }
 
+// the assembler format for -f and the suffix of its output file
+fn output_format(format: Option<&str>) -> Result<(Format, &'static str)> {
+    Ok(match format {
+        Some("binary" | "b") | None => (Format::RawBinary, "hack"),
+        Some("hex" | "h") => (Format::RawHex, "hex"),
+        Some("hx") => (Format::Hackem, "hx"),
+        Some("test") => (Format::Test, "test"),
+        Some(_) => bail!("Invalid format"),
+    })
+}
 fn assemble(
     verbose: bool,
     input_path: &PathBuf,
     format: Option<String>,
     listing: Option<PathBuf>,
     pdb: &mut Pdb,
+    diagnostics: &mut Diagnostics,
 ) -> Result<()> {
     //   let args = Args::parse();
     let name = input_path.file_stem().unwrap().to_str().unwrap();
@@ -229,18 +788,10 @@ fn assemble(
 
     let mut assembler = Assembler::new(pdb);
     let source = fs::read_to_string(input_path)?;
-    assembler.run(&source, name, verbose)?;
-    let (fmt, suffix) = if let Some(fstr) = format {
-        match fstr.as_str() {
-            "binary" | "b" => (Format::RawBinary, "hack"),
-            "hex" | "h" => (Format::RawHex, "hex"),
-            "hx" => (Format::Hackem, "hx"),
-            "test" => (Format::Test, "test"),
-            _ => bail!("Invalid format"),
-        }
-    } else {
-        (Format::RawBinary, "hack")
-    };
+    assembler.run(&source, input_path, verbose)?;
+    diagnostics.append(&mut assembler.diagnostics);
+    check_errors(diagnostics)?;
+    let (fmt, suffix) = output_format(format.as_deref())?;
     let output_name = format!("{}.{}", name, suffix);
     let listing_str = assembler.listing();
     assembler.output_code(&output_name, fmt)?;
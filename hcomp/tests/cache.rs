@@ -0,0 +1,57 @@
+mod common;
+
+use std::fs;
+
+use common::{hcomp, os_dir, scratch_dir};
+
+// the class cache seen from outside, an entry is found on a hit by
+// changing the code kept in it and seeing that code come out
+
+const MAIN: &str = "class Main {
+    function void main() {
+        do Output.printInt(7);
+        return;
+    }
+}
+";
+
+#[test]
+fn classes_come_from_the_cache_until_something_changes() {
+    let dir = scratch_dir("cache_build");
+    let os = os_dir();
+    let build = |extra: &[&str]| {
+        let mut args = vec!["-i", ".", "--oslib", os.as_str()];
+        args.extend(extra);
+        hcomp(&dir, &args);
+        fs::read_to_string(dir.join("Main.vm")).unwrap()
+    };
+    let entry = dir.join(".hcomp-cache/class.Main.json");
+    let tamper = || {
+        let json = fs::read_to_string(&entry).unwrap();
+        assert!(json.contains("push constant 7"));
+        fs::write(&entry, json.replace("push constant 7", "push constant 8")).unwrap();
+    };
+    fs::write(dir.join("Main.jack"), MAIN).unwrap();
+
+    // miss, nothing cached yet
+    assert!(build(&[]).contains("push constant 7"));
+    assert!(entry.exists());
+
+    // hit, the vm is the cached code
+    tamper();
+    assert!(build(&[]).contains("push constant 8"));
+
+    // miss on different settings, and without the cache
+    assert!(build(&["--precedence"]).contains("push constant 7"));
+    tamper();
+    assert!(build(&["--no-cache"]).contains("push constant 7"));
+    assert!(fs::read_to_string(&entry)
+        .unwrap()
+        .contains("push constant 8"));
+
+    // miss on a changed source
+    fs::write(dir.join("Main.jack"), MAIN.replace('7', "9")).unwrap();
+    assert!(build(&[]).contains("push constant 9"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
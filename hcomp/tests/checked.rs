@@ -0,0 +1,121 @@
+mod common;
+
+use std::fs;
+
+use common::{hcomp, run_hx, scratch_dir};
+
+// a failed check seen from outside, built against a small os whose
+// Output leaves each character, and each printInt, in a word from 16384
+// on, so what the failure printed can be read back from the ram
+
+const MAIN: &str = "class Main {
+    function void main() {
+        var Main m;
+        do m.run();
+        return;
+    }
+
+    method void run() {
+        return;
+    }
+}
+";
+
+const OUTPUT: &str = "class Output {
+    static Array next;
+
+    function void init() {
+        let next = 16384;
+        return;
+    }
+
+    function void printChar(char c) {
+        let next[0] = c;
+        let next = next + 1;
+        return;
+    }
+
+    function void printInt(int i) {
+        do Output.printChar(i);
+        return;
+    }
+}
+";
+
+const SYS: &str = "class Sys {
+    function void init() {
+        do Output.init();
+        do Main.main();
+        do Sys.halt();
+        return;
+    }
+
+    function void halt() {
+        while (true) {
+        }
+        return;
+    }
+
+    function void error(int code) {
+        do Output.printInt(code);
+        do Sys.halt();
+        return;
+    }
+}
+";
+
+// builds main against the small os and returns what it printed
+fn build_and_run(name: &str, main: &str, extra: &[&str], length: usize) -> Vec<i16> {
+    let dir = scratch_dir(name);
+    let os = dir.join("os");
+    fs::create_dir_all(&os).unwrap();
+    fs::write(dir.join("Main.jack"), main).unwrap();
+    fs::write(os.join("Output.jack"), OUTPUT).unwrap();
+    fs::write(os.join("Sys.jack"), SYS).unwrap();
+    let os = os.to_str().unwrap();
+    let mut args = vec!["-i", ".", "--oslib", os, "-f", "hx", "--no-cache"];
+    args.extend(extra);
+    hcomp(&dir, &args);
+
+    let project = dir.file_name().unwrap().to_str().unwrap();
+    let ram = run_hx(&dir.join(format!("{}.hx", project)), 1_000_000);
+    fs::remove_dir_all(&dir).unwrap();
+    ram[16384..16384 + length].to_vec()
+}
+
+// the file, the line and the code as Checked.fail prints them
+fn failure(line: i16, code: i16) -> Vec<i16> {
+    let mut printed: Vec<i16> = "Main.jack:".chars().map(|c| c as i16).collect();
+    printed.extend([line, 32, code]);
+    printed
+}
+
+#[test]
+fn a_failure_names_the_file_and_line() {
+    // a method called on null
+    let printed = build_and_run("checked_fail", MAIN, &["--checked"], 13);
+    assert_eq!(printed, failure(4, 101));
+}
+
+// the pool is in the stack segment, running into it stops the program
+// even without --checked
+#[test]
+fn pooled_builds_stop_before_the_stack_reaches_the_pool() {
+    let main = "class Main {
+    function void main() {
+        var String s;
+        let s = \"deep\";
+        do Main.down(0);
+        return;
+    }
+
+    function void down(int depth) {
+        do Main.down(depth + 1);
+        return;
+    }
+}
+";
+    let printed = build_and_run("checked_pool", main, &["--pool-strings"], 13);
+    // the first line of down's body
+    assert_eq!(printed, failure(10, 103));
+}
@@ -0,0 +1,125 @@
+// not every test file uses every helper
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+// an empty directory of its own for each test, named so that it can be
+// a project name in the generated labels
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hcomp_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// hcomp run in dir, panics with its errors if it fails
+pub fn hcomp(dir: &PathBuf, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_hcomp"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "hcomp {}\n{}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+// the course's os, as vm
+pub fn os_dir() -> String {
+    concat!(env!("CARGO_MANIFEST_DIR"), "/../os").to_string()
+}
+
+// runs a hackem (-f hx) file until it reaches its halt address and
+// returns the ram
+pub fn run_hx(path: &Path, max_cycles: usize) -> Vec<i16> {
+    let text = fs::read_to_string(path).unwrap();
+    let mut words = text.split_whitespace();
+    assert_eq!(words.next(), Some("hackem"));
+    words.next();
+    let halt = u16::from_str_radix(words.next().unwrap().trim_start_matches("0x"), 16).unwrap();
+    let mut rom = vec![0u16; 32768];
+    let mut ram = vec![0u16; 32768];
+    let mut in_rom = true;
+    let mut addr = 0;
+    for word in words {
+        if let Some(at) = word.strip_prefix("ROM@") {
+            (in_rom, addr) = (true, usize::from_str_radix(at, 16).unwrap());
+            continue;
+        }
+        if let Some(at) = word.strip_prefix("RAM@") {
+            (in_rom, addr) = (false, usize::from_str_radix(at, 16).unwrap());
+            continue;
+        }
+        let value = u16::from_str_radix(word, 16).unwrap();
+        if in_rom {
+            rom[addr] = value;
+        } else {
+            ram[addr] = value;
+        }
+        addr += 1;
+    }
+
+    let (mut a, mut d, mut pc) = (0u16, 0u16, 0u16);
+    for _ in 0..max_cycles {
+        if pc == halt {
+            return ram.into_iter().map(|word| word as i16).collect();
+        }
+        let instruction = rom[pc as usize];
+        if instruction & 0x8000 == 0 {
+            a = instruction;
+            pc += 1;
+            continue;
+        }
+        let comp = (instruction >> 6) & 0x3f;
+        let dest = (instruction >> 3) & 7;
+        let jump = instruction & 7;
+        let mut x = d;
+        let mut y = if instruction & 0x1000 != 0 {
+            ram[(a & 0x7fff) as usize]
+        } else {
+            a
+        };
+        if comp & 0x20 != 0 {
+            x = 0;
+        }
+        if comp & 0x10 != 0 {
+            x = !x;
+        }
+        if comp & 0x08 != 0 {
+            y = 0;
+        }
+        if comp & 0x04 != 0 {
+            y = !y;
+        }
+        let mut out = if comp & 0x02 != 0 {
+            x.wrapping_add(y)
+        } else {
+            x & y
+        };
+        if comp & 0x01 != 0 {
+            out = !out;
+        }
+        if dest & 1 != 0 {
+            ram[(a & 0x7fff) as usize] = out;
+        }
+        if dest & 2 != 0 {
+            d = out;
+        }
+        if dest & 4 != 0 {
+            a = out;
+        }
+        let signed = out as i16;
+        let taken = (jump & 4 != 0 && signed < 0)
+            || (jump & 2 != 0 && signed == 0)
+            || (jump & 1 != 0 && signed > 0);
+        pc = if taken { a } else { pc + 1 };
+    }
+    panic!("{} did not halt", path.display());
+}
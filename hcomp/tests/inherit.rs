@@ -0,0 +1,43 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{hcomp, os_dir, run_hx, scratch_dir};
+
+// Shape, Circle extends Shape and Square extends Circle built and run,
+// Main leaves what the virtual calls returned at 16384
+// - s.area(), c.area(), q.area() where q is a Shape holding a Square
+// - s.describe() and so on, Shape's method calling the overrides
+// - cc.radius() inherited and cc.area() overridden on a Circle variable
+// - q = cc, the same object seen through either type
+
+const EXPECTED: [i16; 9] = [0, 48, 81, 1005, 1049, 3088, 9, 81, -1];
+
+fn build_and_run(name: &str, extra: &[&str]) {
+    let dir = scratch_dir(name);
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/inherit");
+    for entry in fs::read_dir(fixtures).unwrap() {
+        let path = entry.unwrap().path();
+        fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+    let os = os_dir();
+    let mut args = vec!["-i", ".", "--oslib", os.as_str(), "-f", "hx", "--no-cache"];
+    args.extend(extra);
+    hcomp(&dir, &args);
+
+    // the output is named after the project directory
+    let project = dir.file_name().unwrap().to_str().unwrap();
+    let ram = run_hx(&dir.join(format!("{}.hx", project)), 50_000_000);
+    assert_eq!(ram[16384..16384 + EXPECTED.len()], EXPECTED);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn virtual_calls() {
+    build_and_run("inherit", &[]);
+}
+
+#[test]
+fn virtual_calls_checked() {
+    build_and_run("inherit_checked", &["--checked"]);
+}
@@ -0,0 +1,32 @@
+mod common;
+
+use std::fs;
+
+use common::{hcomp, scratch_dir};
+
+// the -m modes that take a directory as well as a single file
+
+const MAIN: &str = "class Main {
+function void main() {
+do Output.printInt(7);
+return;
+}
+}
+";
+
+#[test]
+fn directory_modes_run_on_the_current_directory() {
+    let dir = scratch_dir("modes_dir");
+    fs::write(dir.join("Main.jack"), MAIN).unwrap();
+
+    hcomp(&dir, &["-i", ".", "-m", "fmt"]);
+    let formatted = fs::read_to_string(dir.join("Main.jack")).unwrap();
+    assert!(formatted.contains("    function void main() {"));
+
+    hcomp(&dir, &["-i", ".", "-m", "xml"]);
+    assert!(dir.join("Main.xml").exists());
+    assert!(dir.join("MainT.xml").exists());
+
+    hcomp(&dir, &["-i", ".", "-m", "doc"]);
+    assert!(dir.join("doc").is_dir());
+}
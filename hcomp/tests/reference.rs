@@ -0,0 +1,41 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{hcomp, scratch_dir};
+
+// course sources compiled with --reference, the .vm next to each is what
+// the course's JackCompiler writes for it, Square.jack is cut down to
+// its first methods
+
+fn compile_reference(program: &str, class: &str) {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/reference")
+        .join(program);
+    let dir = scratch_dir(&format!("{}_{}", program, class));
+    let source = dir.join(format!("{}.jack", class));
+    fs::copy(fixtures.join(format!("{}.jack", class)), &source).unwrap();
+    hcomp(
+        &dir,
+        &["-i", source.to_str().unwrap(), "-m", "jack", "--reference"],
+    );
+    let expected = fs::read_to_string(fixtures.join(format!("{}.vm", class))).unwrap();
+    let actual = fs::read_to_string(dir.join(format!("{}.vm", class))).unwrap();
+    assert_eq!(actual, expected, "{}/{}.vm", program, class);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn seven() {
+    compile_reference("Seven", "Main");
+}
+
+#[test]
+fn square_main() {
+    compile_reference("Square", "Main");
+}
+
+#[test]
+fn square() {
+    compile_reference("Square", "Square");
+}
@@ -0,0 +1,31 @@
+mod rpc;
+mod server;
+mod workspace;
+
+use anyhow::Result;
+use clap::Parser;
+use clap_derive::Parser;
+use rpc::read_message;
+use server::Server;
+use std::{io, path::PathBuf, process};
+
+// a language server for jack, json-rpc over stdin and stdout
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    // the os vm files, for the names of its classes and functions
+    #[arg(long)]
+    oslib: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let mut input = io::stdin().lock();
+    let mut server = Server::new(io::stdout().lock(), args.oslib);
+    while let Some(message) = read_message(&mut input)? {
+        if let Some(code) = server.handle(&message)? {
+            process::exit(code);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,815 @@
+use anyhow::{anyhow, Result};
+use common::{
+    diagnostics::{Diagnostic, Severity, Span},
+    pdb::database::Pdb,
+};
+use compcore::jcomp::{
+    compiler::{Compiler, SubroutineKind},
+    outline::{signature, Outline, Variable},
+    symbols::{VarKind, VarType},
+};
+use serde_json::{json, Value};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    rpc::{error_response, notification, response, write_message},
+    workspace::{path_of, uri_of, Project, Workspace},
+};
+
+// the requests and notifications the server answers
+// - diagnostics are the compiler's, published when a file is opened or
+//   saved
+// - definition, hover and completion work out what the name under the
+//   cursor means from the Outline of its class, a name can be qualified
+//   by a class, an object or an enum: Output.printInt, list.add, Dir.UP
+// lsp positions are 0 based and ours 1 based, both count characters which
+// for jack's ascii source is the same as the utf-16 the protocol wants
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+// lsp SymbolKind and CompletionItemKind numbers
+const SYMBOL_CLASS: u32 = 5;
+const SYMBOL_METHOD: u32 = 6;
+const SYMBOL_FIELD: u32 = 8;
+const SYMBOL_CONSTRUCTOR: u32 = 9;
+const SYMBOL_ENUM: u32 = 10;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+const SYMBOL_CONSTANT: u32 = 14;
+const SYMBOL_ENUM_MEMBER: u32 = 22;
+const COMPLETION_METHOD: u32 = 2;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_CONSTRUCTOR: u32 = 4;
+const COMPLETION_ENUM: u32 = 13;
+const COMPLETION_ENUM_MEMBER: u32 = 20;
+const COMPLETION_CONSTANT: u32 = 21;
+
+pub struct Server<W: Write> {
+    output: W,
+    workspace: Workspace,
+    shutting_down: bool,
+}
+
+// the name under the cursor and what it is qualified by, outermost first
+struct Cursor {
+    line: usize,
+    col: usize,
+    word: String,
+    qualifiers: Vec<String>,
+}
+
+// what a qualifier names
+enum Qualified<'p> {
+    // a variable, its methods
+    Object(String),
+    // a class, its functions, constructors, constants and enums
+    Class(String),
+    // an enum, its members, in the file of the class declaring it
+    Enum(&'p Path, &'p Outline, String),
+}
+
+// a name that was looked up, the vm only os has no location
+struct Found {
+    location: Option<(PathBuf, Span)>,
+    hover: String,
+}
+
+impl<W: Write> Server<W> {
+    pub fn new(output: W, oslib: Option<PathBuf>) -> Self {
+        Self {
+            output,
+            workspace: Workspace::new(oslib),
+            shutting_down: false,
+        }
+    }
+    fn send(&mut self, message: &Value) -> Result<()> {
+        write_message(&mut self.output, message)
+    }
+
+    // Some(exit code) once the client says exit
+    pub fn handle(&mut self, message: &Value) -> Result<Option<i32>> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let Some(id) = message.get("id") else {
+            if method == "exit" {
+                return Ok(Some(if self.shutting_down { 0 } else { 1 }));
+            }
+            // a notification has no one to tell about a failure
+            if let Err(err) = self.notification(method, params) {
+                eprintln!("jack-lsp: {}: {:#}", method, err);
+            }
+            return Ok(None);
+        };
+        let result = match method {
+            "initialize" => Ok(initialize()),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/completion" => self.completion(params),
+            _ => {
+                let message = format!("unknown method {}", method);
+                self.send(&error_response(id, METHOD_NOT_FOUND, &message))?;
+                return Ok(None);
+            }
+        };
+        let message = match result {
+            Ok(result) => response(id, result),
+            Err(err) => error_response(id, INTERNAL_ERROR, &format!("{:#}", err)),
+        };
+        self.send(&message)?;
+        Ok(None)
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Result<()> {
+        match method {
+            "textDocument/didOpen" => {
+                let path = document_path(params)?;
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.workspace.set_text(&path, text.to_string());
+                self.publish_diagnostics(&path)
+            }
+            // full sync, the last change is the whole text
+            "textDocument/didChange" => {
+                let path = document_path(params)?;
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.workspace.set_text(&path, text.to_string());
+                }
+                Ok(())
+            }
+            "textDocument/didSave" => {
+                let path = document_path(params)?;
+                if let Some(text) = params["text"].as_str() {
+                    self.workspace.set_text(&path, text.to_string());
+                }
+                self.publish_diagnostics(&path)
+            }
+            "textDocument/didClose" => {
+                let path = document_path(params)?;
+                self.workspace.close(&path);
+                Ok(())
+            }
+            // initialized, $/cancelRequest, ...
+            _ => Ok(()),
+        }
+    }
+
+    fn publish_diagnostics(&mut self, path: &Path) -> Result<()> {
+        let project = self.workspace.project(path);
+        let Some(file) = project.file(path) else {
+            return Ok(());
+        };
+        let settings = &project.settings;
+        let mut pdb = Pdb::new();
+        let mut compiler = Compiler::new(false, &mut pdb);
+        compiler.set_index(&project.index);
+        compiler.set_precedence(settings.precedence);
+        compiler.set_fold(settings.fold);
+        compiler.set_allow(&settings.allow);
+        compiler.set_reference(settings.reference);
+        // the few errors that stop the compiler go on the first line
+        if let Err(err) = compiler.run(&file.source, path) {
+            compiler
+                .diagnostics
+                .error("", Span::line(1), &format!("{:#}", err));
+        }
+        let diagnostics: Vec<Value> = compiler
+            .diagnostics
+            .items
+            .iter()
+            .map(|diagnostic| lsp_diagnostic(diagnostic, &file.source))
+            .collect();
+        let params = json!({ "uri": uri_of(path), "diagnostics": diagnostics });
+        self.send(&notification("textDocument/publishDiagnostics", params))
+    }
+
+    fn definition(&mut self, params: &Value) -> Result<Value> {
+        let (project, path, cursor) = self.cursor(params)?;
+        let location = cursor
+            .and_then(|cursor| resolve(&project, &path, &cursor))
+            .and_then(|found| found.location);
+        Ok(match location {
+            Some((path, span)) => {
+                let source = project
+                    .file(&path)
+                    .map_or(String::new(), |file| file.source.clone());
+                json!({ "uri": uri_of(&path), "range": range(span, &source) })
+            }
+            None => Value::Null,
+        })
+    }
+
+    fn hover(&mut self, params: &Value) -> Result<Value> {
+        let (project, path, cursor) = self.cursor(params)?;
+        let Some(cursor) = cursor else {
+            return Ok(Value::Null);
+        };
+        Ok(match resolve(&project, &path, &cursor) {
+            Some(found) => {
+                let span = Span::new(
+                    cursor.line,
+                    cursor.col,
+                    cursor.line,
+                    cursor.col + cursor.word.chars().count(),
+                );
+                json!({
+                    "contents": { "kind": "markdown", "value": found.hover },
+                    "range": range(span, ""),
+                })
+            }
+            None => Value::Null,
+        })
+    }
+
+    fn document_symbols(&mut self, params: &Value) -> Result<Value> {
+        let path = document_path(params)?;
+        let project = self.workspace.project(&path);
+        let Some(file) = project.file(&path) else {
+            return Ok(json!([]));
+        };
+        let Some(outline) = &file.outline else {
+            return Ok(json!([]));
+        };
+        Ok(json!([class_symbol(outline, &file.source)]))
+    }
+
+    fn completion(&mut self, params: &Value) -> Result<Value> {
+        let path = document_path(params)?;
+        let (line, col) = position(params)?;
+        let project = self.workspace.project(&path);
+        let Some(file) = project.file(&path) else {
+            return Ok(json!([]));
+        };
+        let qualifiers = source_line(&file.source, line)
+            .map(|text| {
+                let before: Vec<char> = text.chars().take(col - 1).collect();
+                // the part of the name already typed
+                let start = word_start(&before, before.len());
+                qualifiers_before(&before, start)
+            })
+            .unwrap_or_default();
+        let items = match qualified(&project, &path, line, col, &qualifiers) {
+            Some(qualified) => completions(&project, qualified),
+            None => Vec::new(),
+        };
+        Ok(json!(items))
+    }
+
+    // the project and the name at the request's position
+    fn cursor(&self, params: &Value) -> Result<(Project, PathBuf, Option<Cursor>)> {
+        let path = document_path(params)?;
+        let (line, col) = position(params)?;
+        let project = self.workspace.project(&path);
+        let cursor = project
+            .file(&path)
+            .and_then(|file| source_line(&file.source, line))
+            .and_then(|text| cursor_at(&text.chars().collect::<Vec<char>>(), line, col));
+        Ok((project, path, cursor))
+    }
+}
+
+fn initialize() -> Value {
+    json!({
+        "capabilities": {
+            "textDocumentSync": { "openClose": true, "change": 1, "save": { "includeText": true } },
+            "definitionProvider": true,
+            "hoverProvider": true,
+            "documentSymbolProvider": true,
+            "completionProvider": { "triggerCharacters": ["."] },
+        },
+        "serverInfo": { "name": "jack-lsp", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn document_path(params: &Value) -> Result<PathBuf> {
+    let uri = params["textDocument"]["uri"]
+        .as_str()
+        .ok_or_else(|| anyhow!("no textDocument.uri"))?;
+    path_of(uri).ok_or_else(|| anyhow!("not a file uri {}", uri))
+}
+// 1 based line and column
+fn position(params: &Value) -> Result<(usize, usize)> {
+    let position = &params["position"];
+    match (position["line"].as_u64(), position["character"].as_u64()) {
+        (Some(line), Some(character)) => Ok((line as usize + 1, character as usize + 1)),
+        _ => Err(anyhow!("no position")),
+    }
+}
+
+fn source_line(source: &str, line: usize) -> Option<&str> {
+    source.lines().nth(line.checked_sub(1)?)
+}
+
+// a span as an lsp range, an empty span is the rest of its line
+fn range(span: Span, source: &str) -> Value {
+    let start =
+        json!({ "line": span.line.saturating_sub(1), "character": span.col.saturating_sub(1) });
+    let end = if (span.end_line, span.end_col) > (span.line, span.col) {
+        json!({ "line": span.end_line - 1, "character": span.end_col - 1 })
+    } else {
+        let length = source_line(source, span.line).map_or(span.col, |text| text.chars().count());
+        json!({ "line": span.line.saturating_sub(1), "character": length.max(span.col - 1) })
+    };
+    json!({ "start": start, "end": end })
+}
+
+fn lsp_diagnostic(diagnostic: &Diagnostic, source: &str) -> Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let message = match &diagnostic.note {
+        Some(note) => format!("{}\n{}", diagnostic.message, note),
+        None => diagnostic.message.clone(),
+    };
+    json!({
+        "range": range(diagnostic.span, source),
+        "severity": severity,
+        "source": "jack",
+        "message": message,
+    })
+}
+
+fn is_name(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+fn word_start(chars: &[char], mut i: usize) -> usize {
+    while i > 0 && is_name(chars[i - 1]) {
+        i -= 1;
+    }
+    i
+}
+// the names before a . at start, a.b.|c gives [a, b]
+fn qualifiers_before(chars: &[char], mut start: usize) -> Vec<String> {
+    let mut qualifiers = Vec::new();
+    while start > 0 && chars[start - 1] == '.' {
+        let end = start - 1;
+        start = word_start(chars, end);
+        if start == end {
+            break;
+        }
+        qualifiers.insert(0, chars[start..end].iter().collect());
+    }
+    qualifiers
+}
+// the name at a 1 based column or just before it
+fn cursor_at(chars: &[char], line: usize, col: usize) -> Option<Cursor> {
+    let mut i = (col - 1).min(chars.len());
+    if !chars.get(i).is_some_and(|c| is_name(*c)) {
+        i = i.checked_sub(1).filter(|i| is_name(chars[*i]))?;
+    }
+    let start = word_start(chars, i);
+    let end = (i..chars.len())
+        .find(|i| !is_name(chars[*i]))
+        .unwrap_or(chars.len());
+    if chars[start].is_ascii_digit() {
+        return None;
+    }
+    Some(Cursor {
+        line,
+        col: start + 1,
+        word: chars[start..end].iter().collect(),
+        qualifiers: qualifiers_before(chars, start),
+    })
+}
+
+fn qualified<'p>(
+    project: &'p Project,
+    path: &Path,
+    line: usize,
+    col: usize,
+    qualifiers: &[String],
+) -> Option<Qualified<'p>> {
+    let file = project.file(path)?;
+    let outline = file.outline.as_ref()?;
+    let declares =
+        |outline: &Outline, name: &str| outline.class.enums.iter().any(|e| e.name.name == name);
+    match qualifiers {
+        [] => None,
+        [name] => {
+            if let Some(variable) = outline.variable(line, col, name) {
+                return match variable.var_type {
+                    VarType::Instance(class) => Some(Qualified::Object(class)),
+                    _ => None,
+                };
+            }
+            if declares(outline, name) {
+                return Some(Qualified::Enum(&file.path, outline, name.clone()));
+            }
+            Some(Qualified::Class(name.clone()))
+        }
+        // Class.Dir.UP
+        [.., class, name] => {
+            let (file, outline) = project.class(class)?;
+            declares(outline, name).then(|| Qualified::Enum(&file.path, outline, name.clone()))
+        }
+    }
+}
+
+// what the name at the cursor is and where it was declared
+fn resolve(project: &Project, path: &Path, cursor: &Cursor) -> Option<Found> {
+    let outline = project.file(path)?.outline.as_ref()?;
+    let word = cursor.word.as_str();
+    let (line, col) = (cursor.line, cursor.col);
+    if cursor.qualifiers.is_empty() {
+        if let Some(variable) = outline.variable(line, col, word) {
+            return Some(variable_found(path, outline, &variable));
+        }
+        if let Some(found) = member_found(path, outline, word) {
+            return Some(found);
+        }
+        return class_found(project, word);
+    }
+    match qualified(project, path, line, col, &cursor.qualifiers) {
+        Some(Qualified::Enum(path, outline, name)) => {
+            let span = outline.enum_member(&name, word)?;
+            Some(Found {
+                location: Some((path.to_path_buf(), span)),
+                hover: hover_text(
+                    &format!("{}.{}", name, word),
+                    &format!("member of enum `{}`", name),
+                ),
+            })
+        }
+        Some(Qualified::Object(class) | Qualified::Class(class)) => {
+            if let Some((file, other)) = project.class(&class) {
+                return member_found(&file.path, other, word);
+            }
+            let names = project.vm_functions.get(&class)?;
+            names.iter().any(|name| name == word).then(|| Found {
+                location: None,
+                hover: hover_text(
+                    &format!("{}.{}", class, word),
+                    "from the vm code, the signature is not known",
+                ),
+            })
+        }
+        None => None,
+    }
+}
+
+fn hover_text(code: &str, description: &str) -> String {
+    let code = format!("```jack\n{}\n```", code);
+    match description {
+        "" => code,
+        _ => format!("{}\n{}", code, description),
+    }
+}
+
+fn variable_found(path: &Path, outline: &Outline, variable: &Variable) -> Found {
+    let description = match variable.var_kind {
+        VarKind::Field => format!("field of `{}`", outline.name()),
+        VarKind::Static => format!("static of `{}`", outline.name()),
+        VarKind::Local => "local variable".to_string(),
+        VarKind::Argument => "argument".to_string(),
+    };
+    Found {
+        location: Some((path.to_path_buf(), variable.span)),
+        hover: hover_text(
+            &format!("{} {}", variable.var_type, variable.name),
+            &description,
+        ),
+    }
+}
+
+// a subroutine, constant or enum of a class
+fn member_found(path: &Path, outline: &Outline, name: &str) -> Option<Found> {
+    let class = outline.name();
+    if let Some(subroutine) = outline.subroutine(name) {
+        return Some(Found {
+            location: Some((path.to_path_buf(), subroutine.name.span)),
+            hover: hover_text(&signature(subroutine), &format!("in class `{}`", class)),
+        });
+    }
+    let span = outline.constant(name)?;
+    let code = match outline.class.constants.iter().find(|c| c.name.name == name) {
+        Some(constant) => format!("const {} {}", constant.var_type, name),
+        None => format!("enum {}", name),
+    };
+    Some(Found {
+        location: Some((path.to_path_buf(), span)),
+        hover: hover_text(&code, &format!("in class `{}`", class)),
+    })
+}
+
+fn class_found(project: &Project, name: &str) -> Option<Found> {
+    if let Some((file, outline)) = project.class(name) {
+        return Some(Found {
+            location: Some((file.path.clone(), outline.class.name.span)),
+            hover: hover_text(&format!("class {}", name), ""),
+        });
+    }
+    project.vm_functions.get(name).map(|_| Found {
+        location: None,
+        hover: hover_text(&format!("class {}", name), "from the vm code"),
+    })
+}
+
+fn completions(project: &Project, qualified: Qualified) -> Vec<Value> {
+    let item = |label: &str, kind: u32, detail: String| json!({ "label": label, "kind": kind, "detail": detail });
+    let (class, methods) = match qualified {
+        Qualified::Enum(_, outline, name) => {
+            let Some(e) = outline.class.enums.iter().find(|e| e.name.name == name) else {
+                return Vec::new();
+            };
+            return e
+                .members
+                .iter()
+                .map(|member| {
+                    item(
+                        &member.name.name,
+                        COMPLETION_ENUM_MEMBER,
+                        format!("{}.{}", name, member.name.name),
+                    )
+                })
+                .collect();
+        }
+        Qualified::Object(class) => (class, true),
+        Qualified::Class(class) => (class, false),
+    };
+    let Some((_, outline)) = project.class(&class) else {
+        // the os, all that is known are the names
+        return project
+            .vm_functions
+            .get(&class)
+            .map(|names| {
+                names
+                    .iter()
+                    .map(|name| item(name, COMPLETION_FUNCTION, format!("{}.{}", class, name)))
+                    .collect()
+            })
+            .unwrap_or_default();
+    };
+    let mut items: Vec<Value> = outline
+        .class
+        .subroutines
+        .iter()
+        .filter(|subroutine| (subroutine.kind == SubroutineKind::Method) == methods)
+        .map(|subroutine| {
+            let kind = match subroutine.kind {
+                SubroutineKind::Method => COMPLETION_METHOD,
+                SubroutineKind::Constructor => COMPLETION_CONSTRUCTOR,
+                _ => COMPLETION_FUNCTION,
+            };
+            item(&subroutine.name.name, kind, signature(subroutine))
+        })
+        .collect();
+    if !methods {
+        for constant in &outline.class.constants {
+            let detail = format!("const {} {}", constant.var_type, constant.name.name);
+            items.push(item(&constant.name.name, COMPLETION_CONSTANT, detail));
+        }
+        for e in &outline.class.enums {
+            let detail = format!("enum {}", e.name.name);
+            items.push(item(&e.name.name, COMPLETION_ENUM, detail));
+        }
+    }
+    items
+}
+
+fn document_symbol(name: &str, detail: String, kind: u32, span: Span, name_span: Span) -> Value {
+    json!({
+        "name": name,
+        "detail": detail,
+        "kind": kind,
+        "range": range(span, ""),
+        "selectionRange": range(name_span, ""),
+        "children": [],
+    })
+}
+
+fn class_symbol(outline: &Outline, source: &str) -> Value {
+    let class = &outline.class;
+    let mut children = Vec::new();
+    for constant in &class.constants {
+        let detail = format!("const {}", constant.var_type);
+        children.push(document_symbol(
+            &constant.name.name,
+            detail,
+            SYMBOL_CONSTANT,
+            constant.span,
+            constant.name.span,
+        ));
+    }
+    for e in &class.enums {
+        let mut symbol = document_symbol(
+            &e.name.name,
+            String::new(),
+            SYMBOL_ENUM,
+            e.span,
+            e.name.span,
+        );
+        symbol["children"] = e
+            .members
+            .iter()
+            .map(|member| {
+                let span = member.name.span;
+                document_symbol(
+                    &member.name.name,
+                    String::new(),
+                    SYMBOL_ENUM_MEMBER,
+                    span,
+                    span,
+                )
+            })
+            .collect();
+        children.push(symbol);
+    }
+    for var in &class.vars {
+        let kind = match var.kind {
+            VarKind::Static => "static",
+            _ => "field",
+        };
+        for name in &var.names {
+            let detail = format!("{} {}", kind, var.var_type);
+            children.push(document_symbol(
+                &name.name,
+                detail,
+                SYMBOL_FIELD,
+                var.span,
+                name.span,
+            ));
+        }
+    }
+    for subroutine in &class.subroutines {
+        let kind = match subroutine.kind {
+            SubroutineKind::Constructor => SYMBOL_CONSTRUCTOR,
+            SubroutineKind::Method => SYMBOL_METHOD,
+            _ => SYMBOL_FUNCTION,
+        };
+        let mut symbol = document_symbol(
+            &subroutine.name.name,
+            signature(subroutine),
+            kind,
+            subroutine.span,
+            subroutine.name.span,
+        );
+        symbol["children"] = outline
+            .subroutine_variables(subroutine)
+            .iter()
+            .filter(|variable| variable.name != "this")
+            .map(|variable| {
+                let span = variable.span;
+                document_symbol(
+                    &variable.name,
+                    variable.declaration(),
+                    SYMBOL_VARIABLE,
+                    span,
+                    span,
+                )
+            })
+            .collect();
+        children.push(symbol);
+    }
+    // the class has no span of its own, it is the whole file
+    let lines = source.lines().count().max(1);
+    let span = Span::new(
+        1,
+        1,
+        lines,
+        source_line(source, lines).map_or(0, |text| text.len()) + 1,
+    );
+    let mut symbol = document_symbol(
+        &class.name.name,
+        String::new(),
+        SYMBOL_CLASS,
+        span,
+        class.name.span,
+    );
+    symbol["children"] = json!(children);
+    symbol
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::read_message;
+    use std::{fs, io::Cursor as Input};
+
+    const MAIN: &str = "class Main {
+    field int count;
+
+    /** one more than last time */
+    method int next() {
+        let count = count + 1;
+        return count;
+    }
+
+    function void main() {
+        var Main m;
+        let count = 0;
+        do Output.printInt(m.next());
+        return;
+    }
+}
+";
+
+    // what the server wrote, one message at a time
+    fn sent(server: &mut Server<Vec<u8>>) -> Vec<Value> {
+        let mut output = Input::new(std::mem::take(&mut server.output));
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    // one request and the one response it gets
+    fn request(server: &mut Server<Vec<u8>>, id: i64, method: &str, params: Value) -> Value {
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        assert_eq!(server.handle(&message).unwrap(), None);
+        let mut sent = sent(server);
+        assert_eq!(sent.len(), 1, "{:?}", sent);
+        let response = sent.remove(0);
+        assert_eq!(response["id"], id);
+        response["result"].clone()
+    }
+
+    #[test]
+    fn a_session_from_initialize_to_exit() {
+        let dir = std::env::temp_dir().join(format!("jack_lsp_session_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let uri = uri_of(&dir.join("Main.jack"));
+        let os = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../os"));
+        let mut server = Server::new(Vec::new(), Some(os));
+        let at = |line: u32, character: u32| {
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character },
+            })
+        };
+
+        let result = request(&mut server, 1, "initialize", json!({}));
+        assert_eq!(result["capabilities"]["hoverProvider"], true);
+        assert_eq!(result["serverInfo"]["name"], "jack-lsp");
+
+        // opening a document publishes its diagnostics
+        let open = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "jack", "version": 1, "text": MAIN } },
+        });
+        assert_eq!(server.handle(&open).unwrap(), None);
+        let published = sent(&mut server);
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(published[0]["params"]["uri"], uri);
+        let diagnostics = published[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(
+            diagnostics[0]["message"],
+            "field `count` used in a function"
+        );
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 11);
+
+        // m.next() goes to the method's name
+        let result = request(&mut server, 2, "textDocument/definition", at(12, 32));
+        assert_eq!(result["uri"], uri);
+        assert_eq!(
+            result["range"]["start"],
+            json!({ "line": 4, "character": 15 })
+        );
+
+        let result = request(&mut server, 3, "textDocument/hover", at(12, 32));
+        let hover = result["contents"]["value"].as_str().unwrap();
+        assert!(hover.contains("method int next()"), "{}", hover);
+        assert!(hover.contains("in class `Main`"), "{}", hover);
+
+        // after Output. come the os's functions
+        let result = request(&mut server, 4, "textDocument/completion", at(12, 18));
+        let labels: Vec<&str> = result
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|item| item["label"].as_str())
+            .collect();
+        assert!(labels.contains(&"printInt"), "{:?}", labels);
+        assert!(labels.contains(&"println"), "{:?}", labels);
+
+        let unknown = json!({ "jsonrpc": "2.0", "id": 5, "method": "textDocument/unknown" });
+        assert_eq!(server.handle(&unknown).unwrap(), None);
+        let error = sent(&mut server);
+        assert_eq!(error[0]["id"], 5);
+        assert_eq!(error[0]["error"]["code"], METHOD_NOT_FOUND);
+
+        let result = request(&mut server, 6, "shutdown", Value::Null);
+        assert_eq!(result, Value::Null);
+        let exit = json!({ "jsonrpc": "2.0", "method": "exit" });
+        assert_eq!(server.handle(&exit).unwrap(), Some(0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,227 @@
+use compcore::jcomp::{index::ClassIndex, lint::Lint, outline::Outline};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// the documents the editor has open and the projects they belong to
+// a project is a directory of jack files, as hcomp builds it, and is
+// read again for every request so it never goes stale, open documents
+// are used in place of what is on disk
+
+// the hcomp.json settings that change what the compiler reports, the
+// others are left to hcomp
+const CONFIG_FILE: &str = "hcomp.json";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub precedence: bool,
+    pub fold: bool,
+    pub reference: bool,
+    pub allow: Vec<Lint>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            precedence: false,
+            fold: true,
+            reference: false,
+            allow: Vec::new(),
+        }
+    }
+}
+
+pub struct ProjectFile {
+    pub path: PathBuf,
+    pub source: String,
+    // None if it does not parse
+    pub outline: Option<Outline>,
+}
+
+pub struct Project {
+    pub files: Vec<ProjectFile>,
+    pub index: ClassIndex,
+    pub settings: Settings,
+    // functions of the classes that only exist as vm, the os mostly
+    pub vm_functions: HashMap<String, Vec<String>>,
+}
+
+impl Project {
+    pub fn file(&self, path: &Path) -> Option<&ProjectFile> {
+        self.files.iter().find(|file| file.path == path)
+    }
+    pub fn class(&self, name: &str) -> Option<(&ProjectFile, &Outline)> {
+        self.files.iter().find_map(|file| {
+            file.outline
+                .as_ref()
+                .filter(|outline| outline.name() == name)
+                .map(|outline| (file, outline))
+        })
+    }
+}
+
+pub struct Workspace {
+    oslib: Option<PathBuf>,
+    // text of the open documents, saved or not
+    open: HashMap<PathBuf, String>,
+}
+
+impl Workspace {
+    pub fn new(oslib: Option<PathBuf>) -> Self {
+        Self {
+            oslib,
+            open: HashMap::new(),
+        }
+    }
+    pub fn set_text(&mut self, path: &Path, text: String) {
+        self.open.insert(path.to_path_buf(), text);
+    }
+    pub fn close(&mut self, path: &Path) {
+        self.open.remove(path);
+    }
+    pub fn text(&self, path: &Path) -> Option<String> {
+        self.open
+            .get(path)
+            .cloned()
+            .or_else(|| fs::read_to_string(path).ok())
+    }
+
+    // every jack and vm file next to path, plus the oslib
+    pub fn project(&self, path: &Path) -> Project {
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut jack = files_with_extension(dir, "jack");
+        if !jack.iter().any(|file| file == path) {
+            jack.push(path.to_path_buf());
+        }
+        let mut vm = files_with_extension(dir, "vm");
+        // library classes can be jack, hcomp compiles them next to their source
+        let mut library = Vec::new();
+        if let Some(oslib) = &self.oslib {
+            vm.extend(files_with_extension(oslib, "vm"));
+            library = files_with_extension(oslib, "jack");
+        }
+
+        let mut index = ClassIndex::new();
+        let mut vm_functions: HashMap<String, Vec<String>> = HashMap::new();
+        for path in &vm {
+            let Ok(source) = fs::read_to_string(path) else {
+                continue;
+            };
+            index.add_vm(&source);
+            for line in source.lines() {
+                let mut parts = line.split_whitespace();
+                if parts.next() != Some("function") {
+                    continue;
+                }
+                if let Some((class, name)) = parts.next().and_then(|name| name.split_once('.')) {
+                    let names = vm_functions.entry(class.to_string()).or_default();
+                    if !names.iter().any(|known| known == name) {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        }
+        for path in &library {
+            if let Ok(source) = fs::read_to_string(path) {
+                index.add_library_jack(&source);
+            }
+        }
+        let files: Vec<ProjectFile> = jack
+            .into_iter()
+            .filter_map(|path| {
+                let source = self.text(&path)?;
+                index.add_jack(&source);
+                let outline = Outline::parse(&source);
+                Some(ProjectFile {
+                    path,
+                    source,
+                    outline,
+                })
+            })
+            .collect();
+        // what the classes compiled to is not the os
+        for file in &files {
+            if let Some(outline) = &file.outline {
+                vm_functions.remove(outline.name());
+            }
+        }
+        Project {
+            files,
+            index,
+            settings: load_settings(dir),
+            vm_functions,
+        }
+    }
+}
+
+// a bad hcomp.json is hcomp's to report, here it is just the defaults
+fn load_settings(dir: &Path) -> Settings {
+    fs::read_to_string(dir.join(CONFIG_FILE))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+// file:///home/me/Main.jack to a path and back, the escapes a path can
+// need are %xx for bytes
+pub fn path_of(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = String::from_utf8(decoded).ok()?;
+    // file:///C:/dir on windows
+    let path = match path.strip_prefix('/') {
+        Some(rest) if rest.get(1..2) == Some(":") => rest.to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+pub fn uri_of(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}